@@ -2,13 +2,16 @@
 
 //! A directory containing mutated source to run cargo builds and tests.
 
+use std::collections::HashSet;
 use std::convert::TryInto;
+use std::fs;
+use std::path::Path;
 
 use camino::{Utf8Path, Utf8PathBuf};
 use tempfile::TempDir;
-use tracing::info;
+use tracing::{debug, info};
 
-use crate::copy_tree::copy_tree;
+use crate::copy_tree::{copy_tree, copy_tree_sharing};
 use crate::manifest::fix_cargo_config;
 use crate::*;
 
@@ -24,6 +27,10 @@ pub struct BuildDir {
     /// object is dropped. If None, there's nothing to clean up.
     #[allow(dead_code)]
     temp_dir: Option<TempDir>,
+    /// The prefix for the names of temporary directories copied from this one.
+    name_base: String,
+    /// True if this is the original source directory, being mutated in place.
+    in_place: bool,
 }
 
 impl BuildDir {
@@ -39,13 +46,18 @@ impl BuildDir {
             .canonicalize_utf8()
             .context("canonicalize source path")?;
         let temp_dir = copy_tree(source, &name_base, gitignore, console)?;
+        let build_dir = BuildDir::from_temp_dir(temp_dir, &name_base, leak_temp_dir)?;
+        fix_manifest(&build_dir.path.join("Cargo.toml"), &source_abs)?;
+        fix_cargo_config(&build_dir.path, &source_abs)?;
+        Ok(build_dir)
+    }
+
+    fn from_temp_dir(temp_dir: TempDir, name_base: &str, leak_temp_dir: bool) -> Result<BuildDir> {
         let path: Utf8PathBuf = temp_dir
             .path()
             .to_owned()
             .try_into()
             .context("tempdir path to UTF-8")?;
-        fix_manifest(&path.join("Cargo.toml"), &source_abs)?;
-        fix_cargo_config(&path, &source_abs)?;
         let temp_dir = if leak_temp_dir {
             let _ = temp_dir.into_path();
             info!(?path, "Build directory will be leaked for inspection");
@@ -53,18 +65,67 @@ impl BuildDir {
         } else {
             Some(temp_dir)
         };
-        let build_dir = BuildDir { temp_dir, path };
-        Ok(build_dir)
+        Ok(BuildDir {
+            path,
+            temp_dir,
+            name_base: name_base.to_owned(),
+            in_place: false,
+        })
+    }
+
+    /// Make a new build dir by copying this one, including any build products
+    /// in `target/`, so that builds in the copy can start from where this one
+    /// left off.
+    ///
+    /// This is typically called after the baseline build, so that every build
+    /// dir has a warm `target/` directory. Where the filesystem supports it,
+    /// files are cloned rather than copied, so this is cheap in time and space.
+    ///
+    /// If `share_deps` is true, the libraries built for dependencies from outside this
+    /// tree, which will never be rebuilt when the tree is mutated, are hard-linked
+    /// rather than copied, so that all the build dirs share one copy of them. Their
+    /// fingerprints and other files that Cargo might rewrite are still copied.
+    ///
+    /// If this is the source directory being mutated in place, it's instead copied as by
+    /// [BuildDir::copy_from], without its `target/` directory, so that files excluded by
+    /// `gitignore` are skipped, and relative paths are fixed up for the new location.
+    pub fn copy(
+        &self,
+        gitignore: bool,
+        share_deps: bool,
+        leak_temp_dir: bool,
+        console: &Console,
+    ) -> Result<BuildDir> {
+        if self.in_place {
+            return BuildDir::copy_from(&self.path, gitignore, leak_temp_dir, console);
+        }
+        let temp_dir = if share_deps {
+            let shared_units = external_units(&self.path)?;
+            debug!(n_shared_units = shared_units.len());
+            copy_tree_sharing(
+                &self.path,
+                &self.name_base,
+                false,
+                |path| is_shared_unit_file(path, &shared_units),
+                console,
+            )?
+        } else {
+            copy_tree(&self.path, &self.name_base, false, console)?
+        };
+        BuildDir::from_temp_dir(temp_dir, &self.name_base, leak_temp_dir)
     }
 
     /// Make a build dir that works in-place on the source directory.
     pub fn in_place(source_path: &Utf8Path) -> Result<BuildDir> {
+        let path = source_path
+            .canonicalize_utf8()
+            .context("canonicalize source path")?;
+        let name_base = format!("cargo-mutants-{}-", path.file_name().unwrap_or("unnamed"));
         Ok(BuildDir {
             temp_dir: None,
-            path: source_path
-                .canonicalize_utf8()
-                .context("canonicalize source path")?
-                .to_owned(),
+            path,
+            name_base,
+            in_place: true,
         })
     }
 
@@ -73,6 +134,128 @@ impl BuildDir {
     }
 }
 
+/// Find the compilation units in `target/` that were built from sources outside
+/// this tree, identified like `serde-1a2b3c4d5e6f7a8b`.
+///
+/// These are found from the dep-info files that rustc writes alongside its output
+/// in each `deps` directory: a unit is external if none of its inputs are relative
+/// paths or paths inside this tree.
+fn external_units(build_dir: &Utf8Path) -> Result<HashSet<String>> {
+    let mut units = HashSet::new();
+    let target_dir = build_dir.join("target");
+    if !target_dir.is_dir() {
+        return Ok(units);
+    }
+    for deps_dir in find_deps_dirs(&target_dir)? {
+        for entry in fs::read_dir(&deps_dir).with_context(|| format!("read {deps_dir:?}"))? {
+            let path = entry?.path();
+            if path.extension() != Some("d".as_ref()) {
+                continue;
+            }
+            let dep_info =
+                fs::read_to_string(&path).with_context(|| format!("read dep-info {path:?}"))?;
+            let inputs = dep_info_inputs(&dep_info);
+            if !inputs.is_empty()
+                && inputs.iter().all(|input| {
+                    let input = Utf8Path::new(input);
+                    input.is_absolute() && !input.starts_with(build_dir)
+                })
+            {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    units.insert(unit_key(stem));
+                }
+            }
+        }
+    }
+    Ok(units)
+}
+
+/// Find the `deps` directories for each profile, and each target triple, within
+/// a target directory.
+fn find_deps_dirs(target_dir: &Utf8Path) -> Result<Vec<Utf8PathBuf>> {
+    let mut deps_dirs = Vec::new();
+    let mut search = vec![(target_dir.to_owned(), 0)];
+    while let Some((dir, depth)) = search.pop() {
+        for entry in fs::read_dir(&dir).with_context(|| format!("read {dir:?}"))? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let Ok(path) = Utf8PathBuf::try_from(entry.path()) else {
+                continue;
+            };
+            if path.file_name() == Some("deps") {
+                deps_dirs.push(path);
+            } else if depth < 2 {
+                search.push((path, depth + 1));
+            }
+        }
+    }
+    Ok(deps_dirs)
+}
+
+/// Return the input paths from the first rule of a Makefile-style dep-info file,
+/// like `out.rlib: src/lib.rs src/a\ b.rs`.
+fn dep_info_inputs(dep_info: &str) -> Vec<String> {
+    let rule = dep_info
+        .lines()
+        .find(|line| !line.starts_with('#') && !line.trim().is_empty())
+        .unwrap_or_default();
+    let mut inputs = Vec::new();
+    let mut path = String::new();
+    for word in rule
+        .split_once(": ")
+        .map_or("", |(_, inputs)| inputs)
+        .split(' ')
+    {
+        // A backslash before a space escapes it, as part of the path.
+        if let Some(escaped) = word.strip_suffix('\\') {
+            path.push_str(escaped);
+            path.push(' ');
+        } else {
+            path.push_str(word);
+            if !path.is_empty() {
+                inputs.push(std::mem::take(&mut path));
+            }
+        }
+    }
+    inputs
+}
+
+/// True if a path, relative to the top of the build dir, is a library built for one of
+/// the given units, in a `deps` directory within `target/`.
+///
+/// Only `.rlib` and `.rmeta` files are shared, since rustc writes them once and Cargo
+/// never changes them in place. Fingerprints, dep-info files, and anything else Cargo
+/// might update aren't shared, so that one build dir can't disturb another.
+fn is_shared_unit_file(path: &Path, units: &HashSet<String>) -> bool {
+    let components = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>();
+    let [first, .., parent, name] = components.as_slice() else {
+        return false;
+    };
+    if first != "target" || parent != "deps" {
+        return false;
+    }
+    // Libraries are named like `libserde-1234.rlib`.
+    let Some(stem) = name
+        .strip_suffix(".rlib")
+        .or_else(|| name.strip_suffix(".rmeta"))
+    else {
+        return false;
+    };
+    stem.strip_prefix("lib")
+        .is_some_and(|stem| units.contains(&unit_key(stem)))
+}
+
+/// Normalize the name of a unit's files, since the outputs in `deps` are named for the
+/// crate, like `serde_json-1234`, but the package can be named like `serde-json`.
+fn unit_key(name: &str) -> String {
+    name.replace('-', "_")
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -90,6 +273,116 @@ mod test {
         assert!(build_dir.path().join("src").is_dir());
     }
 
+    #[test]
+    fn build_dir_copy_includes_target_and_preserves_mtime() {
+        let workspace = Workspace::open("testdata/factorial").unwrap();
+        let console = Console::new();
+        let build_dir = BuildDir::copy_from(&workspace.dir, true, false, &console).unwrap();
+        let target_file = build_dir.path().join("target/debug/built");
+        std::fs::create_dir_all(target_file.parent().unwrap()).unwrap();
+        std::fs::write(&target_file, b"output").unwrap();
+        let copy = build_dir.copy(true, false, false, &console).unwrap();
+        assert_ne!(copy.path(), build_dir.path());
+        assert!(copy.path().join("src/bin/factorial.rs").is_file());
+        let copied_target_file = copy.path().join("target/debug/built");
+        assert_eq!(std::fs::read(&copied_target_file).unwrap(), b"output");
+        assert_eq!(
+            copied_target_file.metadata().unwrap().modified().unwrap(),
+            target_file.metadata().unwrap().modified().unwrap()
+        );
+    }
+
+    #[test]
+    fn build_dir_copy_of_in_place_dir_fixes_relative_paths() {
+        let workspace = Workspace::open("testdata/relative_dependency").unwrap();
+        let build_dir = BuildDir::in_place(&workspace.dir).unwrap();
+        let copy = build_dir.copy(true, false, false, &Console::new()).unwrap();
+        assert_ne!(copy.path(), build_dir.path());
+        let manifest: toml::Value = fs::read_to_string(copy.path().join("Cargo.toml"))
+            .unwrap()
+            .parse()
+            .unwrap();
+        let dependency_path = manifest["dependencies"]["cargo-mutants-testdata-dependency"]["path"]
+            .as_str()
+            .unwrap();
+        assert_eq!(
+            Utf8Path::new(dependency_path),
+            workspace
+                .dir
+                .canonicalize_utf8()
+                .unwrap()
+                .join("../dependency")
+        );
+        assert!(!copy.path().join("target").exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn build_dir_copy_shares_external_dependencies() {
+        use std::os::unix::fs::MetadataExt;
+
+        let workspace = Workspace::open("testdata/factorial").unwrap();
+        let console = Console::new();
+        let build_dir = BuildDir::copy_from(&workspace.dir, true, false, &console).unwrap();
+        let deps = build_dir.path().join("target/debug/deps");
+        fs::create_dir_all(&deps).unwrap();
+        fs::write(
+            deps.join("the_dep-1234.d"),
+            "/x/deps/libthe_dep-1234.rlib: /registry/dep/src/lib.rs /registry/dep/src/a\\ b.rs\n",
+        )
+        .unwrap();
+        fs::write(deps.join("libthe_dep-1234.rlib"), b"dep").unwrap();
+        fs::write(deps.join("libthe_dep-1234.rmeta"), b"dep").unwrap();
+        fs::write(
+            deps.join("factorial-5678.d"),
+            "/x/deps/factorial-5678: src/bin/factorial.rs\n",
+        )
+        .unwrap();
+        fs::write(deps.join("factorial-5678"), b"bin").unwrap();
+        let fingerprint = build_dir
+            .path()
+            .join("target/debug/.fingerprint/the-dep-1234");
+        fs::create_dir_all(&fingerprint).unwrap();
+        fs::write(fingerprint.join("lib-the-dep"), b"hash").unwrap();
+
+        let copy = build_dir.copy(true, true, false, &console).unwrap();
+        let same_file = |relative_path: &str| {
+            build_dir
+                .path()
+                .join(relative_path)
+                .metadata()
+                .unwrap()
+                .ino()
+                == copy.path().join(relative_path).metadata().unwrap().ino()
+        };
+        assert!(same_file("target/debug/deps/libthe_dep-1234.rlib"));
+        assert!(same_file("target/debug/deps/libthe_dep-1234.rmeta"));
+        // Files that Cargo might rewrite are copied, not shared.
+        assert!(!same_file("target/debug/deps/the_dep-1234.d"));
+        assert!(!same_file(
+            "target/debug/.fingerprint/the-dep-1234/lib-the-dep"
+        ));
+        assert_eq!(
+            fs::read(
+                copy.path()
+                    .join("target/debug/.fingerprint/the-dep-1234/lib-the-dep")
+            )
+            .unwrap(),
+            b"hash"
+        );
+        assert!(!same_file("target/debug/deps/factorial-5678"));
+        assert!(!same_file("target/debug/deps/factorial-5678.d"));
+        assert!(!same_file("src/bin/factorial.rs"));
+    }
+
+    #[test]
+    fn dep_info_inputs_unescapes_spaces() {
+        assert_eq!(
+            dep_info_inputs("# comment\nout.rlib: src/lib.rs src/a\\ b.rs\n\nsrc/lib.rs:\n"),
+            ["src/lib.rs", "src/a b.rs"]
+        );
+    }
+
     #[test]
     fn build_dir_in_place() -> Result<()> {
         let workspace = Workspace::open("testdata/factorial")?;
@@ -3,63 +3,290 @@
 //! Run Cargo as a subprocess, including timeouts and propagating signals.
 
 use std::env;
+use std::fs;
 use std::time::{Duration, Instant};
 
-use anyhow::Result;
-use camino::Utf8Path;
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
 use itertools::Itertools;
 use nextest_metadata::NextestExitCode;
+use tempfile::TempDir;
 use tracing::{debug, debug_span, warn};
 
-use crate::options::TestTool;
+use crate::junit;
+use crate::libtest;
+use crate::options::{TargetKind, TestTool};
 use crate::outcome::PhaseResult;
 use crate::package::Package;
-use crate::process::{Process, ProcessStatus};
+use crate::process::{Confinement, Process, ProcessStatus};
+use crate::rustflags::rustflags;
 use crate::*;
 
 /// Run cargo build, check, or test.
+///
+/// `extra_env` is set in the environment of cargo, in addition to the variables
+/// always set by cargo-mutants and those from the options.
+#[allow(clippy::too_many_arguments)]
 pub fn run_cargo(
     build_dir: &BuildDir,
     packages: Option<&[&Package]>,
     phase: Phase,
     timeout: Duration,
+    extra_env: &[(String, String)],
+    log_file: &mut LogFile,
+    options: &Options,
+    console: &Console,
+) -> Result<PhaseResult> {
+    let argv = cargo_argv(build_dir.path(), packages, phase, options);
+    run_argv(
+        argv, build_dir, packages, phase, timeout, extra_env, log_file, options, console,
+    )
+}
+
+/// Run `cargo test --doc`, to run only the doctests: all of them, or if `function_name`
+/// is given, only those from the documentation of that function.
+#[allow(clippy::too_many_arguments)]
+pub fn run_doctests(
+    build_dir: &BuildDir,
+    packages: &[&Package],
+    function_name: Option<&str>,
+    timeout: Duration,
+    extra_env: &[(String, String)],
+    log_file: &mut LogFile,
+    options: &Options,
+    console: &Console,
+) -> Result<PhaseResult> {
+    let argv = doctest_argv(build_dir.path(), packages, function_name, options);
+    run_argv(
+        argv,
+        build_dir,
+        Some(packages),
+        Phase::Test,
+        timeout,
+        extra_env,
+        log_file,
+        options,
+        console,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_argv(
+    argv: Vec<String>,
+    build_dir: &BuildDir,
+    packages: Option<&[&Package]>,
+    phase: Phase,
+    timeout: Duration,
+    extra_env: &[(String, String)],
     log_file: &mut LogFile,
     options: &Options,
     console: &Console,
 ) -> Result<PhaseResult> {
     let _span = debug_span!("run", ?phase).entered();
     let start = Instant::now();
-    let argv = cargo_argv(build_dir.path(), packages, phase, options);
-    let env = vec![
-        ("CARGO_ENCODED_RUSTFLAGS".to_owned(), rustflags()),
+    let nextest = argv
+        .iter()
+        .skip(1)
+        .find(|arg| !arg.starts_with('+'))
+        .is_some_and(|arg| arg == "nextest");
+    let mut argv = argv;
+    let nextest_junit = if nextest && phase == Phase::Test {
+        let nextest_junit = NextestJunit::new(build_dir.path(), options)?;
+        nextest_junit.add_tool_config_arg(&mut argv);
+        Some(nextest_junit)
+    } else {
+        None
+    };
+    let mut env = vec![
+        ("CARGO_ENCODED_RUSTFLAGS".to_owned(), rustflags(options)),
         // The tests might use Insta <https://insta.rs>, and we don't want it to write
         // updates to the source tree, and we *certainly* don't want it to write
         // updates and then let the test pass.
         ("INSTA_UPDATE".to_owned(), "no".to_owned()),
         ("INSTA_FORCE_PASS".to_owned(), "0".to_owned()),
     ];
-    let process_status = Process::run(&argv, &env, build_dir.path(), timeout, log_file, console)?;
+    env.extend_from_slice(&options.env);
+    if phase == Phase::Test {
+        env.extend_from_slice(&options.test_env);
+    }
+    env.extend_from_slice(extra_env);
+    // Resource limits and the sandbox are meant to contain the tests, not the compiler.
+    let confinement = Confinement {
+        limits: options.limits,
+        sandbox: options.sandbox,
+    };
+    if phase == Phase::Test {
+        if let Some((name, runner)) = target_runner(options) {
+            log_file.message(&format!("tests are run by {name}={runner}"));
+        }
+    }
+    let log_start = log_file.position()?;
+    let process_status = Process::run(
+        &argv,
+        &env,
+        &options.unset_env,
+        build_dir.path(),
+        timeout,
+        (phase == Phase::Test).then_some(&confinement),
+        log_file,
+        console,
+    )?;
     check_interrupted()?;
     debug!(?process_status, elapsed = ?start.elapsed());
-    if options.test_tool == TestTool::Nextest && phase == Phase::Test {
+    if nextest {
         // Nextest returns detailed exit codes. I think we should still treat any non-zero result as just an
         // error, but we can at least warn if it's unexpected.
         if let ProcessStatus::Failure(code) = process_status {
-            // TODO: When we build with `nextest test --no-test` then we should also check build
-            // processes.
-            if code != NextestExitCode::TEST_RUN_FAILED as u32 {
-                warn!(%code, "nextest process exited with unexpected code (not TEST_RUN_FAILED)");
+            let expected = if phase == Phase::Test {
+                NextestExitCode::TEST_RUN_FAILED
+            } else {
+                NextestExitCode::BUILD_FAILED
+            };
+            if code != expected as u32 {
+                warn!(%code, %expected, "nextest process exited with unexpected code");
             }
         }
     }
+    // Output from tests that were stopped by the timeout might be incomplete.
+    let (failing_tests, passing_tests) = if phase != Phase::Test || process_status.is_timeout() {
+        (None, None)
+    } else if let Some(nextest_junit) = &nextest_junit {
+        nextest_junit
+            .read_test_results()
+            .map_or((None, None), |(failing, passing)| {
+                (Some(failing), Some(passing))
+            })
+    } else {
+        let output = log_file.read_from(log_start)?;
+        let failing_tests = libtest::failing_tests(&output);
+        let passing_tests = failing_tests
+            .is_some()
+            .then(|| libtest::passing_tests(&output));
+        (failing_tests, passing_tests)
+    };
     Ok(PhaseResult {
         phase,
         duration: start.elapsed(),
         process_status,
         argv,
+        packages: packages
+            .unwrap_or_default()
+            .iter()
+            .map(|p| p.name.clone())
+            .collect(),
+        failing_tests,
+        passing_tests,
     })
 }
 
+/// A nextest tool config that turns on the JUnit report, and where the report will be.
+///
+/// The config is passed to nextest with `--tool-config-file`, and is written to a temporary
+/// directory so that nothing is added to the tree, which might be the user's own tree with
+/// `--in-place`. Tool config has lower priority than the tree's own nextest config.
+struct NextestJunit {
+    /// Holds the temporary directory containing the tool config until the run is finished.
+    _temp_dir: TempDir,
+    tool_config_path: Utf8PathBuf,
+    report_path: Utf8PathBuf,
+}
+
+impl NextestJunit {
+    fn new(build_dir: &Utf8Path, options: &Options) -> Result<NextestJunit> {
+        let profile = nextest_profile(options);
+        let temp_dir = tempfile::Builder::new()
+            .prefix("cargo-mutants-nextest-")
+            .tempdir()
+            .context("create temporary directory for nextest config")?;
+        let tool_config_path = Utf8Path::from_path(temp_dir.path())
+            .context("nextest config directory path to UTF-8")?
+            .join("nextest.toml");
+        fs::write(&tool_config_path, nextest_tool_config(&profile))
+            .with_context(|| format!("write nextest tool config {tool_config_path}"))?;
+        Ok(NextestJunit {
+            _temp_dir: temp_dir,
+            tool_config_path,
+            report_path: nextest_store_dir(build_dir, options)
+                .join(&profile)
+                .join("junit.xml"),
+        })
+    }
+
+    /// Add the option to read the tool config to a `cargo nextest run` argv, just after `run`,
+    /// so that it comes before any `--` in the additional arguments.
+    fn add_tool_config_arg(&self, argv: &mut Vec<String>) {
+        let position = argv
+            .iter()
+            .position(|arg| arg == "run")
+            .map_or(argv.len(), |i| i + 1);
+        argv.insert(
+            position,
+            format!("--tool-config-file=cargo-mutants:{}", self.tool_config_path),
+        );
+    }
+
+    /// Read the names of the failing and passing tests from the JUnit report, and remove it
+    /// so that it's not mistaken for the report from a later run.
+    fn read_test_results(&self) -> Option<(Vec<String>, Vec<String>)> {
+        let path = &self.report_path;
+        match fs::read_to_string(path) {
+            Ok(xml) => {
+                let _ = fs::remove_file(path);
+                Some((junit::failing_tests(&xml), junit::passing_tests(&xml)))
+            }
+            Err(err) => {
+                debug!(%path, ?err, "no nextest JUnit report");
+                None
+            }
+        }
+    }
+}
+
+/// Return a nextest tool config that writes a JUnit report for the given profile.
+fn nextest_tool_config(profile: &str) -> String {
+    // Quote the profile name, in case it's not a bare TOML key.
+    format!(
+        "[profile.{}.junit]\npath = \"junit.xml\"\n",
+        toml::Value::String(profile.to_owned())
+    )
+}
+
+/// Return the directory where nextest writes reports for the tree in `build_dir`: `nextest`
+/// under Cargo's target directory.
+fn nextest_store_dir(build_dir: &Utf8Path, options: &Options) -> Utf8PathBuf {
+    // If the target directory is absolute, joining it replaces the build directory.
+    build_dir
+        .join(
+            options
+                .target_dir
+                .as_deref()
+                .unwrap_or(Utf8Path::new("target")),
+        )
+        .join("nextest")
+}
+
+/// Return the nextest profile that the tests will use: from `--profile` in the additional
+/// test arguments, or `NEXTEST_PROFILE` in the environment, or otherwise `default`.
+fn nextest_profile(options: &Options) -> String {
+    let mut args = options
+        .additional_cargo_test_args
+        .iter()
+        .take_while(|arg| *arg != "--");
+    let mut from_args = None;
+    while let Some(arg) = args.next() {
+        if let Some(profile) = arg.strip_prefix("--profile=") {
+            from_args = Some(profile.to_owned());
+        } else if arg == "--profile" || arg == "-P" {
+            from_args = args.next().cloned();
+        } else if let Some(profile) = arg.strip_prefix("-P").filter(|p| !p.is_empty()) {
+            from_args = Some(profile.to_owned());
+        }
+    }
+    from_args
+        .or_else(|| test_env_var(options, "NEXTEST_PROFILE"))
+        .unwrap_or_else(|| "default".to_owned())
+}
+
 /// Return the name of the cargo binary.
 pub fn cargo_bin() -> String {
     // When run as a Cargo subcommand, which is the usual/intended case,
@@ -68,6 +295,48 @@ pub fn cargo_bin() -> String {
     env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned())
 }
 
+/// Return the start of the argv to run cargo: the cargo binary, followed by the toolchain
+/// if one is selected.
+fn cargo_command(options: &Options) -> Vec<String> {
+    if let Some(toolchain) = &options.toolchain {
+        // `$CARGO` is the binary from one particular toolchain, which can't switch to
+        // another, so use the rustup proxy.
+        vec!["cargo".to_owned(), format!("+{toolchain}")]
+    } else {
+        vec![cargo_bin()]
+    }
+}
+
+/// Return the name and value of the `CARGO_TARGET_<TRIPLE>_RUNNER` variable that Cargo will
+/// use to run tests built for the selected target, if it's set in the environment or
+/// the options.
+fn target_runner(options: &Options) -> Option<(String, String)> {
+    let target = options.target.as_ref()?;
+    let name = format!(
+        "CARGO_TARGET_{}_RUNNER",
+        target.to_uppercase().replace(['-', '.'], "_")
+    );
+    let runner = test_env_var(options, &name)?;
+    Some((name, runner))
+}
+
+/// Return the value a variable will have in the environment of the tests, from the options
+/// or otherwise inherited from this process.
+fn test_env_var(options: &Options, name: &str) -> Option<String> {
+    let from_options = options
+        .env
+        .iter()
+        .chain(&options.test_env)
+        .filter(|(key, _)| key == name)
+        .map(|(_, value)| value.clone())
+        .next_back();
+    match from_options {
+        Some(value) => Some(value),
+        None if options.unset_env.iter().any(|unset| unset == name) => None,
+        None => env::var(name).ok(),
+    }
+}
+
 /// Make up the argv for a cargo check/build/test invocation, including argv[0] as the
 /// cargo binary itself.
 // (This is split out so it's easier to test.)
@@ -77,19 +346,102 @@ fn cargo_argv(
     phase: Phase,
     options: &Options,
 ) -> Vec<String> {
-    let mut cargo_args = vec![cargo_bin()];
-    if phase == Phase::Test {
+    let mut cargo_args = cargo_command(options);
+    if phase == Phase::Build && options.test_tool == TestTool::Nextest {
+        // Build just what nextest will run, in the same way, so that the test phase
+        // doesn't need to build anything more.
+        cargo_args.push("nextest".to_string());
+        cargo_args.push("run".to_string());
+        cargo_args.push("--no-run".to_string());
+        let extra_targets = extra_target_args(options);
+        if !extra_targets.is_empty() {
+            // Naming any targets replaces the default selection, so ask for the tests too.
+            cargo_args.push("--tests".to_string());
+            cargo_args.extend(extra_targets);
+        }
+    } else if phase == Phase::Test {
         match &options.test_tool {
-            TestTool::Cargo => cargo_args.push("test".to_string()),
+            TestTool::Cargo => {
+                cargo_args.push("test".to_string());
+                if options.doctests == Some(false) {
+                    cargo_args.extend(["--lib", "--bins", "--tests"].map(String::from));
+                }
+            }
             TestTool::Nextest => {
                 cargo_args.push("nextest".to_string());
                 cargo_args.push("run".to_string());
             }
+            TestTool::Command => {
+                return test_command_argv(&options.test_command, build_dir, packages)
+            }
         }
     } else {
         cargo_args.push(phase.name().to_string());
         cargo_args.push("--tests".to_string());
+        cargo_args.extend(extra_target_args(options));
     }
+    cargo_args.extend(package_and_feature_args(build_dir, packages, options));
+    if phase == Phase::Test {
+        cargo_args.extend(options.additional_cargo_test_args.iter().cloned());
+    }
+    cargo_args
+}
+
+/// Return the arguments that select examples and benches to be built, beyond the tests.
+///
+/// Mutants in examples and benches need them to be built, to see if they're viable.
+fn extra_target_args(options: &Options) -> Vec<String> {
+    let target_kinds = options.target_kinds();
+    let mut args = Vec::new();
+    if target_kinds.contains(&TargetKind::Example) {
+        args.push("--examples".to_string());
+    }
+    if target_kinds.contains(&TargetKind::Bench) {
+        args.push("--benches".to_string());
+    }
+    args
+}
+
+/// Make up the argv to run doctests, optionally only those of one function.
+///
+/// The additional `cargo test` arguments aren't passed, because they might select other
+/// targets or be meant for nextest.
+fn doctest_argv(
+    build_dir: &Utf8Path,
+    packages: &[&Package],
+    function_name: Option<&str>,
+    options: &Options,
+) -> Vec<String> {
+    let mut cargo_args = cargo_command(options);
+    cargo_args.extend(["test", "--doc"].map(String::from));
+    cargo_args.extend(package_and_feature_args(build_dir, Some(packages), options));
+    if let Some(function_name) = function_name {
+        cargo_args.push("--".to_owned());
+        cargo_args.extend(doctest_filters(function_name));
+    }
+    cargo_args
+}
+
+/// Return test filters that select the doctests from the documentation of a function.
+///
+/// Doctests are named like `src/lib.rs - Thing::frob (line 12)`. Test filters
+/// match substrings, so this can also pick up a few other doctests, which is harmless.
+pub fn doctest_filters(function_name: &str) -> [String; 2] {
+    let name = function_name
+        .rsplit("::")
+        .next()
+        .expect("rsplit returns at least one part");
+    [format!(" - {name} (line"), format!("::{name} (line")]
+}
+
+/// Return the arguments selecting packages, features, and the target, and the additional
+/// arguments for every cargo command.
+fn package_and_feature_args(
+    build_dir: &Utf8Path,
+    packages: Option<&[&Package]>,
+    options: &Options,
+) -> Vec<String> {
+    let mut cargo_args = Vec::new();
     if let Some([package]) = packages {
         // Use the unambiguous form for this case; it works better when the same
         // package occurs multiple times in the tree with different versions?
@@ -116,45 +468,43 @@ fn cargo_argv(
             .iter()
             .map(|f| format!("--features={}", f)),
     );
-    cargo_args.extend(options.additional_cargo_args.iter().cloned());
-    if phase == Phase::Test {
-        cargo_args.extend(options.additional_cargo_test_args.iter().cloned());
+    if let Some(target) = &options.target {
+        cargo_args.push(format!("--target={target}"));
     }
+    cargo_args.extend(options.additional_cargo_args.iter().cloned());
     cargo_args
 }
 
-/// Return adjusted CARGO_ENCODED_RUSTFLAGS, including any changes to cap-lints.
+/// Expand the `test_command` template into an argv.
 ///
-/// This does not currently read config files; it's too complicated.
-///
-/// See <https://doc.rust-lang.org/cargo/reference/environment-variables.html>
-/// <https://doc.rust-lang.org/rustc/lints/levels.html#capping-lints>
-fn rustflags() -> String {
-    let mut rustflags: Vec<String> = if let Some(rustflags) = env::var_os("CARGO_ENCODED_RUSTFLAGS")
-    {
-        rustflags
-            .to_str()
-            .expect("CARGO_ENCODED_RUSTFLAGS is not valid UTF-8")
-            .split(|c| c == '\x1f')
-            .map(|s| s.to_owned())
-            .collect()
-    } else if let Some(rustflags) = env::var_os("RUSTFLAGS") {
-        rustflags
-            .to_str()
-            .expect("RUSTFLAGS is not valid UTF-8")
-            .split(' ')
-            .map(|s| s.to_owned())
-            .collect()
-    } else {
-        // TODO: We could read the config files, but working out the right target and config seems complicated
-        // given the information available here.
-        // TODO: All matching target.<triple>.rustflags and target.<cfg>.rustflags config entries joined together.
-        // TODO: build.rustflags config value.
-        Vec::new()
-    };
-    rustflags.push("--cap-lints=allow".to_owned());
-    // debug!("adjusted rustflags: {:?}", rustflags);
-    rustflags.join("\x1f")
+/// `{build_dir}` is replaced by the build directory. An argument containing `{package}`
+/// is repeated for each package being tested, with the package name substituted, or
+/// dropped if the whole workspace is being tested.
+fn test_command_argv(
+    template: &[String],
+    build_dir: &Utf8Path,
+    packages: Option<&[&Package]>,
+) -> Vec<String> {
+    let package_names = packages
+        .unwrap_or_default()
+        .iter()
+        .map(|p| p.name.as_str())
+        .sorted()
+        .collect_vec();
+    let mut argv = Vec::new();
+    for arg in template {
+        let arg = arg.replace("{build_dir}", build_dir.as_str());
+        if arg.contains("{package}") {
+            argv.extend(
+                package_names
+                    .iter()
+                    .map(|name| arg.replace("{package}", name)),
+            );
+        } else {
+            argv.push(arg);
+        }
+    }
+    argv
 }
 
 #[cfg(test)]
@@ -197,6 +547,7 @@ mod test {
         let package = Arc::new(Package {
             name: package_name.to_owned(),
             relative_manifest_path: relative_manifest_path.clone(),
+            features: Default::default(),
         });
         let build_manifest_path = build_dir.join(relative_manifest_path);
         assert_eq!(
@@ -300,4 +651,337 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn toolchain_and_target_passed_to_cargo() {
+        let args = Args::try_parse_from(
+            ["mutants", "--toolchain=nightly", "--target=wasm32-wasi"].as_slice(),
+        )
+        .unwrap();
+        let options = Options::from_args(&args).unwrap();
+        let build_dir = Utf8Path::new("/tmp/buildXYZ");
+        assert_eq!(
+            cargo_argv(build_dir, None, Phase::Build, &options),
+            [
+                "cargo",
+                "+nightly",
+                "build",
+                "--tests",
+                "--workspace",
+                "--target=wasm32-wasi"
+            ]
+        );
+        assert_eq!(
+            cargo_argv(build_dir, None, Phase::Test, &options),
+            [
+                "cargo",
+                "+nightly",
+                "test",
+                "--workspace",
+                "--target=wasm32-wasi"
+            ]
+        );
+    }
+
+    #[test]
+    fn target_runner_is_found_in_options() {
+        let mut options = Options {
+            target: Some("wasm32-wasip1".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(target_runner(&options), None);
+        options.env.push((
+            "CARGO_TARGET_WASM32_WASIP1_RUNNER".to_owned(),
+            "wasmtime".to_owned(),
+        ));
+        options.test_env.push((
+            "CARGO_TARGET_WASM32_WASIP1_RUNNER".to_owned(),
+            "wasmtime --dir=.".to_owned(),
+        ));
+        assert_eq!(
+            target_runner(&options),
+            Some((
+                "CARGO_TARGET_WASM32_WASIP1_RUNNER".to_owned(),
+                "wasmtime --dir=.".to_owned()
+            ))
+        );
+        options.target = None;
+        assert_eq!(target_runner(&options), None);
+    }
+
+    #[test]
+    fn test_command_template_is_expanded() {
+        let mut options = Options {
+            test_tool: TestTool::Command,
+            test_command: ["make", "-C", "{build_dir}", "check", "PACKAGE={package}"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            ..Default::default()
+        };
+        options.additional_cargo_args.push("--release".to_owned());
+        let build_dir = Utf8Path::new("/tmp/buildXYZ");
+        let package = |name: &str| Package {
+            name: name.to_owned(),
+            relative_manifest_path: Utf8PathBuf::from(format!("{name}/Cargo.toml")),
+            features: Default::default(),
+        };
+        let (a, b) = (package("a"), package("b"));
+        assert_eq!(
+            cargo_argv(build_dir, Some(&[&a]), Phase::Test, &options),
+            ["make", "-C", "/tmp/buildXYZ", "check", "PACKAGE=a"]
+        );
+        assert_eq!(
+            cargo_argv(build_dir, Some(&[&b, &a]), Phase::Test, &options),
+            [
+                "make",
+                "-C",
+                "/tmp/buildXYZ",
+                "check",
+                "PACKAGE=a",
+                "PACKAGE=b"
+            ]
+        );
+        assert_eq!(
+            cargo_argv(build_dir, None, Phase::Test, &options),
+            ["make", "-C", "/tmp/buildXYZ", "check"]
+        );
+        // Cargo is still used to build.
+        assert_eq!(
+            cargo_argv(build_dir, Some(&[&a]), Phase::Build, &options)[1..],
+            [
+                "build",
+                "--tests",
+                "--manifest-path",
+                "/tmp/buildXYZ/a/Cargo.toml",
+                "--release"
+            ]
+        );
+    }
+
+    #[test]
+    fn nextest_builds_with_no_run_and_writes_junit() {
+        let options = Options {
+            test_tool: TestTool::Nextest,
+            ..Default::default()
+        };
+        let build_dir = Utf8Path::new("/tmp/buildXYZ");
+        assert_eq!(
+            cargo_argv(build_dir, None, Phase::Check, &options)[1..],
+            ["check", "--tests", "--workspace"]
+        );
+        assert_eq!(
+            cargo_argv(build_dir, None, Phase::Build, &options)[1..],
+            ["nextest", "run", "--no-run", "--workspace"]
+        );
+        assert_eq!(
+            cargo_argv(build_dir, None, Phase::Test, &options)[1..],
+            ["nextest", "run", "--workspace"]
+        );
+    }
+
+    #[test]
+    fn doctests_false_selects_other_targets() {
+        let options = Options {
+            doctests: Some(false),
+            ..Default::default()
+        };
+        let build_dir = Utf8Path::new("/tmp/buildXYZ");
+        assert_eq!(
+            cargo_argv(build_dir, None, Phase::Test, &options)[1..],
+            ["test", "--lib", "--bins", "--tests", "--workspace"]
+        );
+        // Building is unchanged.
+        assert_eq!(
+            cargo_argv(build_dir, None, Phase::Build, &options)[1..],
+            ["build", "--tests", "--workspace"]
+        );
+    }
+
+    #[test]
+    fn examples_and_benches_are_built_when_mutated() {
+        let options = Options {
+            targets: vec![TargetKind::Lib, TargetKind::Example, TargetKind::Bench],
+            ..Default::default()
+        };
+        let build_dir = Utf8Path::new("/tmp/buildXYZ");
+        assert_eq!(
+            cargo_argv(build_dir, None, Phase::Check, &options)[1..],
+            ["check", "--tests", "--examples", "--benches", "--workspace"]
+        );
+        assert_eq!(
+            cargo_argv(build_dir, None, Phase::Build, &options)[1..],
+            ["build", "--tests", "--examples", "--benches", "--workspace"]
+        );
+        assert_eq!(
+            cargo_argv(build_dir, None, Phase::Test, &options)[1..],
+            ["test", "--workspace"]
+        );
+    }
+
+    #[test]
+    fn nextest_builds_examples_and_benches_when_mutated() {
+        let options = Options {
+            test_tool: TestTool::Nextest,
+            targets: vec![TargetKind::Lib, TargetKind::Example, TargetKind::Bench],
+            ..Default::default()
+        };
+        let build_dir = Utf8Path::new("/tmp/buildXYZ");
+        assert_eq!(
+            cargo_argv(build_dir, None, Phase::Build, &options)[1..],
+            [
+                "nextest",
+                "run",
+                "--no-run",
+                "--tests",
+                "--examples",
+                "--benches",
+                "--workspace"
+            ]
+        );
+        assert_eq!(
+            cargo_argv(build_dir, None, Phase::Test, &options)[1..],
+            ["nextest", "run", "--workspace"]
+        );
+    }
+
+    #[test]
+    fn doctest_argv_selects_one_function() {
+        let options = Options {
+            additional_cargo_args: vec!["--offline".to_owned()],
+            additional_cargo_test_args: vec!["--all-targets".to_owned()],
+            ..Default::default()
+        };
+        let package = Arc::new(Package {
+            name: "demo".to_owned(),
+            relative_manifest_path: "demo/Cargo.toml".into(),
+            features: Default::default(),
+        });
+        let build_dir = Utf8Path::new("/tmp/buildXYZ");
+        assert_eq!(
+            doctest_argv(build_dir, &[&package], Some("Thing::frob"), &options)[1..],
+            [
+                "test",
+                "--doc",
+                "--manifest-path",
+                "/tmp/buildXYZ/demo/Cargo.toml",
+                "--offline",
+                "--",
+                " - frob (line",
+                "::frob (line",
+            ]
+        );
+        assert_eq!(
+            doctest_argv(build_dir, &[&package], None, &options)[1..],
+            [
+                "test",
+                "--doc",
+                "--manifest-path",
+                "/tmp/buildXYZ/demo/Cargo.toml",
+                "--offline",
+            ]
+        );
+    }
+
+    #[test]
+    fn nextest_tool_config_enables_junit() {
+        let config: toml::Value = nextest_tool_config("default").parse().unwrap();
+        assert_eq!(
+            config["profile"]["default"]["junit"]["path"].as_str(),
+            Some("junit.xml")
+        );
+        let config: toml::Value = nextest_tool_config("ci.slow").parse().unwrap();
+        assert_eq!(
+            config["profile"]["ci.slow"]["junit"]["path"].as_str(),
+            Some("junit.xml")
+        );
+    }
+
+    #[test]
+    fn nextest_profile_is_found_in_args_and_env() {
+        let mut options = Options {
+            unset_env: vec!["NEXTEST_PROFILE".to_owned()],
+            ..Default::default()
+        };
+        assert_eq!(nextest_profile(&options), "default");
+        options
+            .env
+            .push(("NEXTEST_PROFILE".to_owned(), "ci".to_owned()));
+        assert_eq!(nextest_profile(&options), "ci");
+        for args in [
+            &["--profile", "mutants"][..],
+            &["--profile=mutants"],
+            &["-P", "mutants"],
+            &["-Pmutants"],
+            &["--profile", "mutants", "--", "--profile", "other"],
+        ] {
+            options.additional_cargo_test_args = args.iter().map(|s| s.to_string()).collect();
+            assert_eq!(nextest_profile(&options), "mutants", "{args:?}");
+        }
+    }
+
+    #[test]
+    fn nextest_junit_report_is_under_target_dir_for_profile() {
+        let build_dir = Utf8Path::new("/tmp/buildXYZ");
+        let mut options = Options {
+            additional_cargo_test_args: vec!["--profile=ci".to_owned()],
+            ..Default::default()
+        };
+        let nextest_junit = NextestJunit::new(build_dir, &options).unwrap();
+        assert_eq!(
+            nextest_junit.report_path,
+            "/tmp/buildXYZ/target/nextest/ci/junit.xml"
+        );
+        assert!(!nextest_junit.tool_config_path.starts_with(build_dir));
+        assert!(nextest_junit.tool_config_path.is_file());
+
+        options.target_dir = Some("build/out".into());
+        assert_eq!(
+            NextestJunit::new(build_dir, &options).unwrap().report_path,
+            "/tmp/buildXYZ/build/out/nextest/ci/junit.xml"
+        );
+        options.target_dir = Some("/var/cache/target".into());
+        assert_eq!(
+            NextestJunit::new(build_dir, &options).unwrap().report_path,
+            "/var/cache/target/nextest/ci/junit.xml"
+        );
+    }
+
+    #[test]
+    fn nextest_tool_config_arg_goes_before_test_args() {
+        let build_dir = Utf8Path::new("/tmp/buildXYZ");
+        let nextest_junit = NextestJunit::new(build_dir, &Options::default()).unwrap();
+        let mut argv = ["cargo", "nextest", "run", "--workspace", "--", "--ignored"]
+            .map(String::from)
+            .to_vec();
+        nextest_junit.add_tool_config_arg(&mut argv);
+        assert_eq!(
+            argv[3],
+            format!(
+                "--tool-config-file=cargo-mutants:{}",
+                nextest_junit.tool_config_path
+            )
+        );
+        assert_eq!(argv[4..], ["--workspace", "--", "--ignored"]);
+    }
+
+    #[test]
+    fn nextest_junit_report_is_read_and_removed() {
+        let tmp = tempfile::tempdir().unwrap();
+        let build_dir = Utf8Path::from_path(tmp.path()).unwrap();
+        let nextest_junit = NextestJunit::new(build_dir, &Options::default()).unwrap();
+        assert_eq!(nextest_junit.read_test_results(), None);
+        let junit_path = &nextest_junit.report_path;
+        fs::create_dir_all(junit_path.parent().unwrap()).unwrap();
+        fs::write(
+            junit_path,
+            r#"<testcase name="t" classname="p"><failure/></testcase><testcase name="u" classname="p"/>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            nextest_junit.read_test_results(),
+            Some((vec!["p t".to_owned()], vec!["p u".to_owned()]))
+        );
+        assert!(!junit_path.exists());
+    }
 }
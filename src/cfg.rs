@@ -0,0 +1,384 @@
+// Copyright 2024 Martin Pool
+
+//! Understand `#[cfg(...)]` attributes, to know which code is compiled for the target
+//! and under which features.
+
+use std::collections::{BTreeSet, HashSet};
+use std::env;
+use std::process::Command;
+
+use camino::Utf8Path;
+use syn::punctuated::Punctuated;
+use syn::{Attribute, Expr, ExprLit, Lit, Meta, Token};
+use tracing::{debug, warn};
+
+use crate::rustflags::effective_rustflags;
+use crate::Options;
+
+/// Names that rustc reports for the target, but that depend on the build profile, or on
+/// flags that can differ between packages or builds, such as `-C target-feature`.
+const PROFILE_CFG_NAMES: &[&str] = &[
+    "debug_assertions",
+    "overflow_checks",
+    "panic",
+    "target_feature",
+];
+
+/// Names that are set for some targets, and so are known to be false if rustc doesn't
+/// report them.
+const TARGET_CFG_NAMES: &[&str] = &["unix", "windows"];
+
+/// A `cfg` predicate, such as `any(unix, feature = "fast")`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Cfg {
+    /// A bare name, like `unix` or `test`.
+    Name(String),
+    /// A key with a value, like `feature = "fast"` or `target_os = "linux"`.
+    KeyValue(String, String),
+    All(Vec<Cfg>),
+    Any(Vec<Cfg>),
+    Not(Box<Cfg>),
+}
+
+impl Cfg {
+    /// Parse the predicate from a `#[cfg(...)]` attribute.
+    ///
+    /// Returns None for other attributes, or if the predicate isn't understood.
+    pub fn from_attr(attr: &Attribute) -> Option<Cfg> {
+        if !attr.path().is_ident("cfg") {
+            return None;
+        }
+        match attr.parse_args::<Meta>() {
+            Ok(meta) => Cfg::from_meta(&meta),
+            Err(err) => {
+                debug!(?err, "cfg attribute is not in conventional form; ignored");
+                None
+            }
+        }
+    }
+
+    /// Parse a predicate written as `cfg(...)`, as in the `target.'cfg(...)'` tables of
+    /// Cargo config files.
+    ///
+    /// Returns None if it's not in that form, or if the predicate isn't understood.
+    pub fn from_cfg_str(s: &str) -> Option<Cfg> {
+        match syn::parse_str::<Meta>(s).ok()? {
+            Meta::List(list) if list.path.is_ident("cfg") => {
+                Cfg::from_meta(&list.parse_args::<Meta>().ok()?)
+            }
+            _ => None,
+        }
+    }
+
+    /// Return the predicates of all the `#[cfg(...)]` attributes in a list.
+    pub fn from_attrs(attrs: &[Attribute]) -> Vec<Cfg> {
+        attrs.iter().filter_map(Cfg::from_attr).collect()
+    }
+
+    fn from_meta(meta: &Meta) -> Option<Cfg> {
+        let name = meta.path().get_ident()?.to_string();
+        match meta {
+            Meta::Path(_) => Some(Cfg::Name(name)),
+            Meta::NameValue(name_value) => match &name_value.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(value),
+                    ..
+                }) => Some(Cfg::KeyValue(name, value.value())),
+                _ => None,
+            },
+            Meta::List(list) => {
+                let args = list
+                    .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                    .ok()?
+                    .iter()
+                    .map(Cfg::from_meta)
+                    .collect::<Option<Vec<Cfg>>>()?;
+                match name.as_str() {
+                    "all" => Some(Cfg::All(args)),
+                    "any" => Some(Cfg::Any(args)),
+                    "not" => match <[Cfg; 1]>::try_from(args) {
+                        Ok([arg]) => Some(Cfg::Not(Box::new(arg))),
+                        Err(_) => None,
+                    },
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// Evaluate the predicate, given a function that evaluates the names and key-values
+    /// in it.
+    ///
+    /// Returns None if the result depends on something that `leaf` doesn't know.
+    pub fn eval(&self, leaf: &impl Fn(&Cfg) -> Option<bool>) -> Option<bool> {
+        match self {
+            Cfg::Name(_) | Cfg::KeyValue(..) => leaf(self),
+            Cfg::All(cfgs) => all(cfgs, leaf),
+            Cfg::Any(cfgs) => {
+                let mut known = true;
+                for cfg in cfgs {
+                    match cfg.eval(leaf) {
+                        Some(true) => return Some(true),
+                        Some(false) => (),
+                        None => known = false,
+                    }
+                }
+                known.then_some(false)
+            }
+            Cfg::Not(cfg) => cfg.eval(leaf).map(|value| !value),
+        }
+    }
+}
+
+/// Evaluate whether all of the predicates are true, or None if that's not known.
+pub fn all(cfgs: &[Cfg], leaf: &impl Fn(&Cfg) -> Option<bool>) -> Option<bool> {
+    let mut known = true;
+    for cfg in cfgs {
+        match cfg.eval(leaf) {
+            Some(true) => (),
+            Some(false) => return Some(false),
+            None => known = false,
+        }
+    }
+    known.then_some(true)
+}
+
+/// Evaluate whether code under all these predicates is compiled with the given features,
+/// or None if it depends on something other than features.
+pub fn enabled_by_features(cfgs: &[Cfg], features: &BTreeSet<String>) -> Option<bool> {
+    enabled(cfgs, &TargetCfg::default(), Some(features))
+}
+
+/// Evaluate whether code under all these predicates is compiled for the target with the
+/// given features, or None if that's not known.
+///
+/// `features` is None if the active features aren't known.
+pub fn enabled(
+    cfgs: &[Cfg],
+    target_cfg: &TargetCfg,
+    features: Option<&BTreeSet<String>>,
+) -> Option<bool> {
+    all(cfgs, &|cfg| match cfg {
+        Cfg::KeyValue(key, value) if key == "feature" => {
+            features.map(|features| features.contains(value))
+        }
+        _ => target_cfg.eval(cfg),
+    })
+}
+
+/// Make a command to run rustc from the selected toolchain.
+pub fn rustc_command(options: &Options) -> Command {
+    if let Some(toolchain) = &options.toolchain {
+        let mut command = Command::new("rustc");
+        command.arg(format!("+{toolchain}"));
+        command
+    } else {
+        Command::new(env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned()))
+    }
+}
+
+/// The names and key-values that are set when compiling for the target, as reported by
+/// `rustc --print cfg`.
+///
+/// The default knows nothing about the target.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TargetCfg {
+    /// The names and key-values that are set.
+    set: HashSet<Cfg>,
+    /// The keys that occur in the set: other values for these keys are false.
+    keys: HashSet<String>,
+}
+
+impl TargetCfg {
+    /// Ask rustc what's set for the selected toolchain and target, with the rustflags
+    /// that Cargo would use.
+    ///
+    /// If rustc can't be run, a warning is logged and nothing is known about the target.
+    pub fn from_rustc(dir: &Utf8Path, options: &Options) -> TargetCfg {
+        let mut command = rustc_command(options);
+        command.args(["--print", "cfg"]);
+        if let Some(target) = &options.target {
+            command.arg(format!("--target={target}"));
+        }
+        command.args(effective_rustflags(options));
+        let rustc = command.get_program().to_string_lossy().into_owned();
+        match command.current_dir(dir).output() {
+            Ok(output) if output.status.success() => {
+                TargetCfg::parse(&String::from_utf8_lossy(&output.stdout))
+            }
+            Ok(output) => {
+                warn!(
+                    status = ?output.status,
+                    stderr = %String::from_utf8_lossy(&output.stderr),
+                    "{rustc} --print cfg failed; cfg attributes won't be evaluated",
+                );
+                TargetCfg::default()
+            }
+            Err(err) => {
+                warn!(
+                    "Failed to run {rustc} --print cfg: {err}; cfg attributes won't be evaluated"
+                );
+                TargetCfg::default()
+            }
+        }
+    }
+
+    /// Parse the output of `rustc --print cfg`, which has lines like `unix` or
+    /// `target_os="linux"`.
+    pub fn parse(output: &str) -> TargetCfg {
+        let mut target_cfg = TargetCfg::default();
+        for line in output
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+        {
+            let (name, value) = match line.split_once('=') {
+                Some((key, value)) => (key, Some(value.trim_matches('"'))),
+                None => (line, None),
+            };
+            if PROFILE_CFG_NAMES.contains(&name) {
+                continue;
+            }
+            target_cfg.set.insert(match value {
+                Some(value) => {
+                    target_cfg.keys.insert(name.to_owned());
+                    Cfg::KeyValue(name.to_owned(), value.to_owned())
+                }
+                None => Cfg::Name(name.to_owned()),
+            });
+        }
+        target_cfg
+    }
+
+    /// Evaluate a name or key-value, or return None if it's not known.
+    fn eval(&self, cfg: &Cfg) -> Option<bool> {
+        if self.set.contains(cfg) {
+            return Some(true);
+        }
+        match cfg {
+            Cfg::Name(name) => TARGET_CFG_NAMES
+                .contains(&name.as_str())
+                .then_some(false)
+                .filter(|_| !self.set.is_empty()),
+            Cfg::KeyValue(key, _) => self.keys.contains(key).then_some(false),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use syn::parse_quote;
+
+    use super::*;
+
+    fn features(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_cfg_attrs() {
+        let attrs: Vec<Attribute> = vec![
+            parse_quote!(#[cfg(feature = "fast")]),
+            parse_quote!(#[inline]),
+            parse_quote!(#[cfg(all(unix, not(target_os = "macos")))]),
+        ];
+        assert_eq!(
+            Cfg::from_attrs(&attrs),
+            [
+                Cfg::KeyValue("feature".into(), "fast".into()),
+                Cfg::All(vec![
+                    Cfg::Name("unix".into()),
+                    Cfg::Not(Box::new(Cfg::KeyValue("target_os".into(), "macos".into()))),
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn unconventional_cfg_is_ignored() {
+        let attr: Attribute = parse_quote!(#[cfg(not(a, b))]);
+        assert_eq!(Cfg::from_attr(&attr), None);
+        let attr: Attribute = parse_quote!(#[cfg(feature = 1)]);
+        assert_eq!(Cfg::from_attr(&attr), None);
+    }
+
+    #[test]
+    fn evaluate_target_cfg() {
+        let target_cfg = TargetCfg::parse(indoc! { r#"
+            debug_assertions
+            panic="unwind"
+            target_family="unix"
+            target_feature="sse2"
+            target_os="linux"
+            unix
+        "# });
+        let eval = |attr: Attribute| {
+            enabled(
+                &Cfg::from_attrs(&[attr]),
+                &target_cfg,
+                Some(&features(&["fast"])),
+            )
+        };
+        assert_eq!(eval(parse_quote!(#[cfg(unix)])), Some(true));
+        assert_eq!(eval(parse_quote!(#[cfg(windows)])), Some(false));
+        assert_eq!(eval(parse_quote!(#[cfg(target_os = "macos")])), Some(false));
+        assert_eq!(
+            eval(parse_quote!(#[cfg(all(target_os = "linux", feature = "fast"))])),
+            Some(true)
+        );
+        assert_eq!(
+            eval(parse_quote!(#[cfg(any(windows, feature = "slow"))])),
+            Some(false)
+        );
+        // These depend on the profile, or on flags passed to rustc.
+        assert_eq!(eval(parse_quote!(#[cfg(debug_assertions)])), None);
+        assert_eq!(eval(parse_quote!(#[cfg(panic = "abort")])), None);
+        assert_eq!(eval(parse_quote!(#[cfg(target_feature = "avx2")])), None);
+        assert_eq!(eval(parse_quote!(#[cfg(target_feature = "sse2")])), None);
+        assert_eq!(eval(parse_quote!(#[cfg(tokio_unstable)])), None);
+        assert_eq!(eval(parse_quote!(#[cfg(not(tokio_unstable))])), None);
+
+        // Nothing is known about the target by default.
+        assert_eq!(
+            enabled(
+                &Cfg::from_attrs(&[parse_quote!(#[cfg(windows)])]),
+                &TargetCfg::default(),
+                None
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_cfg_str() {
+        assert_eq!(
+            Cfg::from_cfg_str("cfg(all(unix, target_arch = \"x86_64\"))"),
+            Some(Cfg::All(vec![
+                Cfg::Name("unix".into()),
+                Cfg::KeyValue("target_arch".into(), "x86_64".into()),
+            ]))
+        );
+        assert_eq!(Cfg::from_cfg_str("cfg(all())"), Some(Cfg::All(vec![])));
+        assert_eq!(Cfg::from_cfg_str("x86_64-unknown-linux-gnu"), None);
+        assert_eq!(Cfg::from_cfg_str("thumbv7em-none-eabihf"), None);
+    }
+
+    #[test]
+    fn evaluate_features() {
+        let attrs: Vec<Attribute> = vec![parse_quote!(#[cfg(any(feature = "a", feature = "b"))])];
+        let cfgs = Cfg::from_attrs(&attrs);
+        assert_eq!(enabled_by_features(&cfgs, &features(&[])), Some(false));
+        assert_eq!(enabled_by_features(&cfgs, &features(&["b"])), Some(true));
+
+        let attrs: Vec<Attribute> = vec![
+            parse_quote!(#[cfg(feature = "a")]),
+            parse_quote!(#[cfg(unix)]),
+        ];
+        let cfgs = Cfg::from_attrs(&attrs);
+        assert_eq!(enabled_by_features(&cfgs, &features(&[])), Some(false));
+        assert_eq!(enabled_by_features(&cfgs, &features(&["a"])), None);
+        assert_eq!(enabled_by_features(&[], &features(&[])), Some(true));
+    }
+}
@@ -8,6 +8,7 @@
 //!
 //! The config file is then merged in to the [Options].
 
+use std::collections::BTreeMap;
 use std::default::Default;
 use std::fs::read_to_string;
 use std::path::Path;
@@ -16,7 +17,7 @@ use anyhow::Context;
 use camino::Utf8Path;
 use serde::Deserialize;
 
-use crate::options::TestTool;
+use crate::options::{TargetKind, TestTool};
 use crate::Result;
 
 /// Configuration read from a config file.
@@ -28,6 +29,10 @@ use crate::Result;
 pub struct Config {
     /// Generate these error values from functions returning Result.
     pub error_values: Vec<String>,
+    /// Mutate the source of these kinds of targets: lib, bin, example, bench, custom-build.
+    pub targets: Vec<TargetKind>,
+    /// Also mutate code under `cfg` attributes that are false for the target and features.
+    pub include_inactive: bool,
     /// Generate mutants from source files matching these globs.
     pub examine_globs: Vec<String>,
     /// Exclude mutants from source files matching these globs.
@@ -38,14 +43,46 @@ pub struct Config {
     pub examine_re: Vec<String>,
     /// Pass extra args to every cargo invocation.
     pub additional_cargo_args: Vec<String>,
+    /// Build and test with this rustup toolchain.
+    pub toolchain: Option<String>,
+    /// Build for this target triple.
+    pub target: Option<String>,
     /// Pass extra args to cargo test.
     pub additional_cargo_test_args: Vec<String>,
     /// Minimum test timeout, in seconds, as a floor on the autoset value.
     pub minimum_test_timeout: Option<f64>,
-    /// Choice of test tool: cargo or nextest.
+    /// Choice of test tool: cargo, nextest, or command.
     pub test_tool: Option<TestTool>,
+    /// Command to run tests, as an argv template, with `{build_dir}` and `{package}` placeholders.
+    pub test_command: Vec<String>,
+    /// Run doctests: if false, only the lib, bin, and test targets; if true, also alongside nextest.
+    pub doctests: Option<bool>,
+    /// Build and test each mutant with each of these lists of features in turn.
+    pub feature_sets: Vec<Vec<String>>,
+    /// Test each package separately in the baseline, to set a timeout for each package.
+    pub per_package_timeouts: bool,
     /// Timeout multiplier, relative to the baseline 'cargo test'.
     pub timeout_multiplier: Option<f64>,
+    /// Timeout for each build or check, in seconds.
+    pub build_timeout: Option<f64>,
+    /// Build timeout multiplier, relative to the baseline 'cargo build'.
+    pub build_timeout_multiplier: Option<f64>,
+    /// Number of times to retry failing tests, to detect flaky tests.
+    pub retries: Option<usize>,
+    /// Limit on the address space of each test process, in MiB.
+    pub limit_memory: Option<u64>,
+    /// Limit on the CPU time of each test process, in seconds.
+    pub limit_cpu: Option<u64>,
+    /// Limit on the number of open files in each test process.
+    pub limit_files: Option<u64>,
+    /// Run tests with no network access and a private `/tmp`.
+    pub sandbox: bool,
+    /// Environment variables to set for all cargo invocations.
+    pub env: BTreeMap<String, String>,
+    /// Environment variables to set only for `cargo test`.
+    pub test_env: BTreeMap<String, String>,
+    /// Inherited environment variables to remove from all cargo invocations.
+    pub unset_env: Vec<String>,
 }
 
 impl Config {
@@ -95,6 +95,8 @@ impl Console {
                 SummaryOutcome::Unviable => model.unviable += 1,
                 SummaryOutcome::Success => model.successes += 1,
                 SummaryOutcome::Failure => model.failures += 1,
+                SummaryOutcome::Flaky => model.flaky += 1,
+                SummaryOutcome::BuildTimeout => model.timeouts += 1,
             }
             model.remove_scenario(scenario);
         });
@@ -115,6 +117,9 @@ impl Console {
             style_scenario(scenario, true),
         )
         .unwrap();
+        if let Some(limit) = outcome.last_phase_result().limit_exceeded() {
+            write!(s, " ({limit} exceeded)").unwrap();
+        }
         if options.show_times {
             let prs: Vec<String> = outcome
                 .phase_results()
@@ -142,6 +147,22 @@ impl Console {
         self.message(&s);
     }
 
+    /// Update that the build of a schema finished, successfully or not.
+    ///
+    /// Nothing is printed here, because the outcome is reported for each mutant.
+    pub fn schema_build_finished(&self, scenario: &Scenario) {
+        self.view.update(|model| {
+            model.remove_scenario(scenario);
+        });
+    }
+
+    /// Update that a scenario was abandoned without an outcome, and will be retried.
+    pub fn scenario_abandoned(&self, scenario: &Scenario) {
+        self.view.update(|model| {
+            model.remove_scenario(scenario);
+        });
+    }
+
     pub fn build_dirs_start(&self, _n: usize) {
         // self.message(&format!("Make {n} more build directories...\n"));
     }
@@ -211,6 +232,13 @@ impl Console {
             "{}\n",
             lab_outcome.summary_string(start_time, options)
         ));
+        let useless_tests = lab_outcome.useless_tests();
+        if !useless_tests.is_empty() {
+            self.message(&format!(
+                "{} ran against every mutant but never failed; see useless_tests.txt\n",
+                plural(useless_tests.len(), "test")
+            ));
+        }
     }
 
     pub fn clear(&self) {
@@ -361,6 +389,7 @@ struct LabModel {
     timeouts: usize,
     successes: usize,
     failures: usize,
+    flaky: usize,
 }
 
 impl nutmeg::Model for LabModel {
@@ -406,6 +435,15 @@ impl nutmeg::Model for LabModel {
                 )
                 .unwrap();
             }
+            if self.flaky > 0 {
+                write!(
+                    s,
+                    ", {} {}",
+                    style(self.flaky).cyan(),
+                    style("flaky").yellow()
+                )
+                .unwrap();
+            }
             if self.mutants_caught > 0 {
                 write!(s, ", {} caught", style(self.mutants_caught).cyan()).unwrap();
             }
@@ -590,6 +628,8 @@ pub fn style_outcome(outcome: &ScenarioOutcome) -> StyledObject<&'static str> {
         SummaryOutcome::Success => style("ok").green(),
         SummaryOutcome::Unviable => style("unviable").blue(),
         SummaryOutcome::Timeout => style("TIMEOUT").red().bold(),
+        SummaryOutcome::Flaky => style("FLAKY").yellow().bold(),
+        SummaryOutcome::BuildTimeout => style("BUILD TIMEOUT").red().bold(),
     }
 }
 
@@ -619,6 +659,7 @@ pub fn style_scenario(scenario: &Scenario, line_col: bool) -> Cow<'static, str>
     match scenario {
         Scenario::Baseline => "Unmutated baseline".into(),
         Scenario::Mutant(mutant) => mutant.name(line_col, true).into(),
+        Scenario::Schema(_) => scenario.to_string().into(),
     }
 }
 
@@ -1,16 +1,21 @@
-// Copyright 2023 Martin Pool
+// Copyright 2023-2024 Martin Pool
 
 //! Copy a source tree, with some exclusions, to a new temporary directory.
+//!
+//! Files are cloned rather than copied, where the filesystem supports it, and
+//! their modification times are preserved, so that Cargo can reuse build products
+//! copied along with the source.
 
 use std::convert::TryInto;
 use std::fs::FileType;
+use std::path::Path;
 
 use anyhow::Context;
 use camino::{Utf8Path, Utf8PathBuf};
 use ignore::WalkBuilder;
 use path_slash::PathExt;
 use tempfile::TempDir;
-use tracing::{debug, warn};
+use tracing::{debug, trace, warn};
 
 use crate::check_interrupted;
 use crate::Console;
@@ -39,8 +44,24 @@ pub fn copy_tree(
     name_base: &str,
     gitignore: bool,
     console: &Console,
+) -> Result<TempDir> {
+    copy_tree_sharing(from_path, name_base, gitignore, |_| false, console)
+}
+
+/// Copy a tree as for [copy_tree], but hard-link rather than copy files for which
+/// `share` returns true when given their path relative to the top of the tree.
+///
+/// The linked files are shared between the trees, so this should only be used for
+/// files that will never be rewritten in either tree.
+pub fn copy_tree_sharing(
+    from_path: &Utf8Path,
+    name_base: &str,
+    gitignore: bool,
+    share: impl Fn(&Path) -> bool,
+    console: &Console,
 ) -> Result<TempDir> {
     console.start_copy();
+    let mut total_linked = 0;
     let mut total_bytes = 0;
     let mut total_files = 0;
     let temp_dir = tempfile::Builder::new()
@@ -71,13 +92,16 @@ pub fn copy_tree(
         let ft = entry
             .file_type()
             .with_context(|| format!("Expected file to have a file type: {:?}", entry.path()))?;
-        if ft.is_file() {
-            let bytes_copied = std::fs::copy(entry.path(), &dest_path).with_context(|| {
-                format!(
-                    "Failed to copy {:?} to {dest_path:?}",
-                    entry.path().to_slash_lossy(),
-                )
-            })?;
+        if ft.is_file() && share(relative_path) && link_file(entry.path(), &dest_path) {
+            total_linked += 1;
+        } else if ft.is_file() {
+            let bytes_copied =
+                copy_file(entry.path(), dest_path.as_std_path()).with_context(|| {
+                    format!(
+                        "Failed to copy {:?} to {dest_path:?}",
+                        entry.path().to_slash_lossy(),
+                    )
+                })?;
             total_bytes += bytes_copied;
             total_files += 1;
             console.copy_progress(total_bytes);
@@ -98,10 +122,96 @@ pub fn copy_tree(
         }
     }
     console.finish_copy();
-    debug!(?total_bytes, ?total_files, "Copied source tree");
+    debug!(
+        ?total_bytes,
+        ?total_files,
+        ?total_linked,
+        "Copied source tree"
+    );
     Ok(temp_dir)
 }
 
+/// Hard-link one file, returning false if it could not be linked and should
+/// be copied instead.
+fn link_file(src_path: &Path, dest_path: &Utf8Path) -> bool {
+    match std::fs::hard_link(src_path, dest_path) {
+        Ok(()) => true,
+        Err(err) => {
+            debug!(?src_path, ?err, "hard link failed; copying instead");
+            false
+        }
+    }
+}
+
+/// Copy one file, preserving its modification time, and return the number of bytes copied.
+fn copy_file(src_path: &Path, dest_path: &Path) -> Result<u64> {
+    let bytes_copied = match clone_file(src_path, dest_path) {
+        Ok(()) => src_path.metadata()?.len(),
+        Err(err) => {
+            trace!(?src_path, ?err, "clone failed; copying instead");
+            std::fs::copy(src_path, dest_path)?
+        }
+    };
+    if let Err(err) = copy_mtime(src_path, dest_path) {
+        debug!(?dest_path, ?err, "failed to preserve modification time");
+    }
+    Ok(bytes_copied)
+}
+
+/// Clone a file with the `FICLONE` ioctl, sharing its storage with the source
+/// until either is modified.
+///
+/// This is supported on filesystems such as Btrfs and XFS, and fails on others.
+#[cfg(target_os = "linux")]
+fn clone_file(src_path: &Path, dest_path: &Path) -> Result<()> {
+    use std::fs::File;
+    use std::os::fd::AsRawFd;
+
+    nix::ioctl_write_int!(ficlone, 0x94, 9);
+
+    let src_file = File::open(src_path)?;
+    let dest_file = File::create(dest_path)?;
+    // Safety: both file descriptors are open for the duration of the call, and
+    // FICLONE does not touch any memory in this process.
+    unsafe { ficlone(dest_file.as_raw_fd(), src_file.as_raw_fd() as _) }?;
+    dest_file.set_permissions(src_file.metadata()?.permissions())?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+#[mutants::skip] // Mutant tests run on Linux
+fn clone_file(_src_path: &Path, _dest_path: &Path) -> Result<()> {
+    anyhow::bail!("cloning files is not supported on this platform")
+}
+
+/// Set the modification time of one file to match another.
+///
+/// Cargo decides whether build products are fresh by comparing modification times,
+/// so this lets copies of `target/` be reused.
+#[cfg(unix)]
+fn copy_mtime(src_path: &Path, dest_path: &Path) -> Result<()> {
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::MetadataExt;
+
+    use nix::sys::stat::futimens;
+    use nix::sys::time::TimeSpec;
+
+    let metadata = src_path.metadata()?;
+    let dest_file = std::fs::File::open(dest_path)?;
+    futimens(
+        dest_file.as_raw_fd(),
+        &TimeSpec::UTIME_OMIT,
+        &TimeSpec::new(metadata.mtime(), metadata.mtime_nsec()),
+    )?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+#[mutants::skip] // Mutant tests run on Linux
+fn copy_mtime(_src_path: &Path, _dest_path: &Path) -> Result<()> {
+    Ok(())
+}
+
 #[cfg(unix)]
 fn copy_symlink(_ft: FileType, src_path: &Utf8Path, dest_path: &Utf8Path) -> Result<()> {
     let link_target = std::fs::read_link(src_path)
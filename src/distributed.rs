@@ -0,0 +1,502 @@
+// Copyright 2024 Martin Pool
+
+//! Distribute mutants from one coordinator process to workers over TCP.
+//!
+//! The coordinator, started with `--serve`, discovers the mutants but doesn't build or
+//! test anything itself. Workers, started with `--worker`, each in their own copy of the
+//! same tree, connect to it, run their own baseline, and then repeatedly ask for a mutant
+//! to test and send back its outcome, so that faster workers test more mutants.
+//!
+//! The protocol is one JSON [Message] per line. Mutants are identified by their index in
+//! the list of mutants discovered in the tree, which the worker sends when it connects so
+//! that the coordinator can check that both processes found the same mutants.
+//!
+//! If a worker disconnects before reporting the outcome of a mutant it was given, the
+//! mutant is put back on the queue for another worker. For that reason workers are only
+//! told they're done once every mutant has an outcome: while the queue is empty but some
+//! mutants are still being tested, workers are told to wait and ask again.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::console::{plural, Console};
+use crate::lab::mutant_order;
+use crate::log_file::LogFile;
+use crate::outcome::{LabOutcome, Phase, PhaseResult, ScenarioOutcome};
+use crate::output::OutputDir;
+use crate::process::ProcessStatus;
+use crate::*;
+
+/// How often the coordinator checks for new connections and for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a worker keeps trying to connect, in case it started before the coordinator.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A message between the coordinator and a worker.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum Message {
+    /// Sent by a worker when it connects, with the names of all the mutants it found.
+    Hello {
+        version: String,
+        mutants: Vec<String>,
+    },
+    /// The coordinator accepts the worker.
+    Welcome,
+    /// The coordinator won't give work to this worker.
+    Refused { reason: String },
+    /// The worker asks for a mutant to test.
+    Next,
+    /// The coordinator asks the worker to test the mutant with this index.
+    Test { id: usize },
+    /// No mutant is available now, but some are still being tested by other workers and
+    /// might be given back, so the worker should ask again later.
+    Wait,
+    /// The coordinator has no more mutants to give out.
+    Done,
+    /// The worker reports the outcome of testing a mutant.
+    Outcome {
+        id: usize,
+        log: String,
+        phase_results: Vec<RemotePhaseResult>,
+    },
+}
+
+/// A [PhaseResult] sent between processes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct RemotePhaseResult {
+    phase: Phase,
+    duration: f64,
+    process_status: ProcessStatus,
+    argv: Vec<String>,
+    #[serde(default)]
+    packages: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    failing_tests: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    passing_tests: Option<Vec<String>>,
+}
+
+impl From<&PhaseResult> for RemotePhaseResult {
+    fn from(phase_result: &PhaseResult) -> Self {
+        RemotePhaseResult {
+            phase: phase_result.phase,
+            duration: phase_result.duration.as_secs_f64(),
+            process_status: phase_result.process_status,
+            argv: phase_result.argv.clone(),
+            packages: phase_result.packages.clone(),
+            failing_tests: phase_result.failing_tests.clone(),
+            passing_tests: phase_result.passing_tests.clone(),
+        }
+    }
+}
+
+impl From<RemotePhaseResult> for PhaseResult {
+    fn from(remote: RemotePhaseResult) -> Self {
+        PhaseResult {
+            phase: remote.phase,
+            duration: Duration::from_secs_f64(remote.duration),
+            process_status: remote.process_status,
+            argv: remote.argv,
+            packages: remote.packages,
+            failing_tests: remote.failing_tests,
+            passing_tests: remote.passing_tests,
+        }
+    }
+}
+
+/// One end of a connection, exchanging [Message]s.
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Result<Connection> {
+        Ok(Connection {
+            reader: BufReader::new(stream.try_clone().context("clone TCP stream")?),
+            writer: stream,
+        })
+    }
+
+    fn send(&mut self, message: &Message) -> Result<()> {
+        let mut line = serde_json::to_string(message).context("serialize message")?;
+        line.push('\n');
+        self.writer
+            .write_all(line.as_bytes())
+            .context("send message")
+    }
+
+    /// Receive the next message, or None if the other end closed the connection.
+    fn receive(&mut self) -> Result<Option<Message>> {
+        let mut line = String::new();
+        if self
+            .reader
+            .read_line(&mut line)
+            .context("receive message")?
+            == 0
+        {
+            return Ok(None);
+        }
+        serde_json::from_str(&line)
+            .with_context(|| format!("parse message {line:?}"))
+            .map(Some)
+    }
+}
+
+/// A worker's connection to the coordinator, shared by all its build threads.
+pub struct WorkerClient {
+    connection: Mutex<Connection>,
+}
+
+impl WorkerClient {
+    /// Connect to a coordinator and check that it has the same mutants as this worker.
+    pub fn connect(address: &str, mutants: &[Mutant]) -> Result<WorkerClient> {
+        let start = Instant::now();
+        let stream = loop {
+            match TcpStream::connect(address) {
+                Ok(stream) => break stream,
+                Err(err)
+                    if err.kind() == ErrorKind::ConnectionRefused
+                        && start.elapsed() < CONNECT_TIMEOUT =>
+                {
+                    debug!(?err, "Coordinator is not listening yet; retrying");
+                    check_interrupted()?;
+                    sleep(POLL_INTERVAL);
+                }
+                Err(err) => {
+                    return Err(anyhow!(err).context(format!("connect to coordinator at {address}")))
+                }
+            }
+        };
+        let mut connection = Connection::new(stream)?;
+        connection.send(&Message::Hello {
+            version: crate::VERSION.to_owned(),
+            mutants: mutant_names(mutants),
+        })?;
+        match connection.receive()? {
+            Some(Message::Welcome) => {
+                info!("Connected to coordinator at {address}");
+                Ok(WorkerClient {
+                    connection: Mutex::new(connection),
+                })
+            }
+            Some(Message::Refused { reason }) => bail!("Coordinator refused this worker: {reason}"),
+            other => bail!("Unexpected reply from coordinator: {other:?}"),
+        }
+    }
+
+    /// Ask for the index of the next mutant to test, or None if there are no more.
+    ///
+    /// If the coordinator says to wait, this asks again after a while, without holding the
+    /// connection, so that other threads can report their outcomes.
+    pub fn next_mutant(&self) -> Result<Option<usize>> {
+        loop {
+            let mut connection = self.connection.lock().expect("lock connection");
+            connection.send(&Message::Next)?;
+            match connection.receive()? {
+                Some(Message::Test { id }) => return Ok(Some(id)),
+                Some(Message::Done) => return Ok(None),
+                Some(Message::Wait) => {
+                    drop(connection);
+                    debug!("Waiting for mutants being tested by other workers");
+                    check_interrupted()?;
+                    sleep(POLL_INTERVAL);
+                }
+                other => bail!("Unexpected reply from coordinator: {other:?}"),
+            }
+        }
+    }
+
+    /// Send the outcome of testing a mutant back to the coordinator.
+    pub fn report(&self, id: usize, outcome: &ScenarioOutcome) -> Result<()> {
+        let message = Message::Outcome {
+            id,
+            log: outcome.get_log_content()?,
+            phase_results: outcome.phase_results().iter().map(Into::into).collect(),
+        };
+        self.connection
+            .lock()
+            .expect("lock connection")
+            .send(&message)
+    }
+}
+
+/// Hand out mutants to workers connecting on `address`, until all have been tested,
+/// and collect their outcomes into the output directory.
+pub fn serve(
+    address: &str,
+    mutants: Vec<Mutant>,
+    workspace_dir: &Utf8Path,
+    options: &Options,
+    console: &Console,
+) -> Result<LabOutcome> {
+    let start_time = Instant::now();
+    let output_in_dir: &Utf8Path = options
+        .output_in_dir
+        .as_ref()
+        .map_or(workspace_dir, |p| p.as_path());
+    let output_dir = OutputDir::new(output_in_dir)?;
+    console.set_debug_log(output_dir.open_debug_log()?);
+    output_dir.write_mutants_list(&mutants)?;
+    console.discovered_mutants(&mutants);
+    if mutants.is_empty() {
+        warn!("No mutants found under the active filters");
+        return Ok(LabOutcome::default());
+    }
+    let listener =
+        TcpListener::bind(address).with_context(|| format!("listen for workers on {address}"))?;
+    listener
+        .set_nonblocking(true)
+        .context("set listener nonblocking")?;
+    info!(
+        "Waiting for workers to test {} on {}",
+        plural(mutants.len(), "mutant"),
+        listener.local_addr()?
+    );
+    let coordinator = Coordinator {
+        names: mutant_names(&mutants),
+        state: Mutex::new(CoordinatorState {
+            queue: mutant_order(&mutants, options.order).into(),
+            in_flight: 0,
+            output_dir,
+        }),
+        mutants,
+        options,
+        console,
+    };
+    console.start_testing_mutants(coordinator.mutants.len());
+    let mut streams = Vec::new();
+    thread::scope(|scope| {
+        let result = (|| loop {
+            check_interrupted()?;
+            if coordinator.finished() {
+                return Ok(());
+            }
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    debug!(?peer, "Worker connected");
+                    stream
+                        .set_nonblocking(false)
+                        .context("set stream blocking")?;
+                    streams.push(stream.try_clone().context("clone TCP stream")?);
+                    let coordinator = &coordinator;
+                    scope.spawn(move || {
+                        if let Err(err) = coordinator.serve_worker(stream) {
+                            warn!("Error from worker {peer}: {err:#}");
+                        }
+                    });
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => sleep(POLL_INTERVAL),
+                Err(err) => return Err(anyhow!(err).context("accept worker connection")),
+            }
+        })();
+        // Disconnect any workers that are still connected, so that their threads finish.
+        for stream in &streams {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        result
+    })?;
+    let output_dir = coordinator
+        .state
+        .into_inner()
+        .expect("final unlock coordinator state")
+        .output_dir;
+    output_dir.write_useless_tests()?;
+    console.lab_finished(&output_dir.lab_outcome, start_time, options);
+    Ok(output_dir.take_lab_outcome())
+}
+
+/// State shared between the threads serving each worker.
+struct Coordinator<'a> {
+    /// All the mutants, in the order they were discovered, which is the same in every worker.
+    mutants: Vec<Mutant>,
+    names: Vec<String>,
+    state: Mutex<CoordinatorState>,
+    options: &'a Options,
+    console: &'a Console,
+}
+
+struct CoordinatorState {
+    /// Indexes of mutants that have not yet been given to any worker.
+    queue: VecDeque<usize>,
+    /// The number of mutants given to workers, whose outcomes have not yet been received.
+    in_flight: usize,
+    output_dir: OutputDir,
+}
+
+impl Coordinator<'_> {
+    fn finished(&self) -> bool {
+        let state = self.state.lock().expect("lock coordinator state");
+        state.queue.is_empty() && state.in_flight == 0
+    }
+
+    fn serve_worker(&self, stream: TcpStream) -> Result<()> {
+        let peer = stream.peer_addr().context("get worker address")?;
+        let mut connection = Connection::new(stream)?;
+        // Logs for the mutants given to this worker whose outcomes haven't come back yet.
+        let mut in_flight: HashMap<usize, LogFile> = HashMap::new();
+        let result = self.serve_worker_messages(&mut connection, &peer.to_string(), &mut in_flight);
+        if !in_flight.is_empty() {
+            warn!(
+                "Worker {peer} disconnected while testing {}; they will be given to another worker",
+                plural(in_flight.len(), "mutant")
+            );
+            let mut state = self.state.lock().expect("lock coordinator state");
+            for (id, log_file) in in_flight {
+                state.queue.push_front(id);
+                state.in_flight -= 1;
+                let _ = std::fs::remove_file(log_file.path());
+                self.console
+                    .scenario_abandoned(&Scenario::Mutant(self.mutants[id].clone()));
+            }
+        }
+        result
+    }
+
+    fn serve_worker_messages(
+        &self,
+        connection: &mut Connection,
+        peer: &str,
+        in_flight: &mut HashMap<usize, LogFile>,
+    ) -> Result<()> {
+        match connection.receive()? {
+            Some(Message::Hello { version, mutants }) => {
+                let refusal = if version != crate::VERSION {
+                    Some(format!(
+                        "worker is version {version} but the coordinator is version {}",
+                        crate::VERSION
+                    ))
+                } else if mutants != self.names {
+                    Some(format!(
+                        "worker found {} but the coordinator found {}; check they have the same source and options",
+                        plural(mutants.len(), "mutant"),
+                        plural(self.names.len(), "mutant"),
+                    ))
+                } else {
+                    None
+                };
+                if let Some(reason) = refusal {
+                    connection.send(&Message::Refused {
+                        reason: reason.clone(),
+                    })?;
+                    bail!("Refused worker: {reason}");
+                }
+                connection.send(&Message::Welcome)?;
+                info!("Worker {peer} connected");
+            }
+            other => bail!("Expected hello from worker, got {other:?}"),
+        }
+        while let Some(message) = connection.receive()? {
+            match message {
+                Message::Next => {
+                    let mut state = self.state.lock().expect("lock coordinator state");
+                    let Some(id) = state.queue.pop_front() else {
+                        // Mutants still being tested might come back if their worker
+                        // disconnects, so only finish when there are none.
+                        let reply = if state.in_flight == 0 {
+                            Message::Done
+                        } else {
+                            Message::Wait
+                        };
+                        drop(state);
+                        connection.send(&reply)?;
+                        continue;
+                    };
+                    state.in_flight += 1;
+                    let scenario = Scenario::Mutant(self.mutants[id].clone());
+                    let mut log_file = state.output_dir.create_log(&scenario)?;
+                    drop(state);
+                    log_file.message(&format!("{scenario} tested by worker {peer}"));
+                    self.console.scenario_started(&scenario, log_file.path())?;
+                    in_flight.insert(id, log_file);
+                    connection.send(&Message::Test { id })?;
+                }
+                Message::Outcome {
+                    id,
+                    log,
+                    phase_results,
+                } => {
+                    let Some(log_file) = in_flight.remove(&id) else {
+                        bail!("Worker sent an outcome for mutant {id}, which it was not testing");
+                    };
+                    log_file
+                        .open_append()?
+                        .write_all(log.as_bytes())
+                        .context("write worker log")?;
+                    let scenario = Scenario::Mutant(self.mutants[id].clone());
+                    let mut outcome = ScenarioOutcome::new(&log_file, scenario.clone());
+                    for phase_result in phase_results {
+                        outcome.add_phase_result(phase_result.into());
+                    }
+                    let mut state = self.state.lock().expect("lock coordinator state");
+                    state.output_dir.add_scenario_outcome(&outcome)?;
+                    state.in_flight -= 1;
+                    drop(state);
+                    self.console
+                        .scenario_finished(&scenario, &outcome, self.options);
+                }
+                other => bail!("Unexpected message from worker: {other:?}"),
+            }
+        }
+        debug!(?peer, "Worker disconnected");
+        Ok(())
+    }
+}
+
+fn mutant_names(mutants: &[Mutant]) -> Vec<String> {
+    mutants.iter().map(|m| m.name(true, false)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn messages_are_one_line_of_json() {
+        let message = Message::Outcome {
+            id: 3,
+            log: "line one\nline two\n".to_owned(),
+            phase_results: vec![RemotePhaseResult {
+                phase: Phase::Test,
+                duration: 1.5,
+                process_status: ProcessStatus::Failure(101),
+                argv: vec!["cargo".to_owned(), "test".to_owned()],
+                packages: vec!["a".to_owned()],
+                failing_tests: Some(vec!["a test::fails".to_owned()]),
+                passing_tests: Some(vec!["a test::passes".to_owned()]),
+            }],
+        };
+        let json = serde_json::to_string(&message).unwrap();
+        assert!(!json.contains('\n'));
+        assert!(json.starts_with(r#"{"type":"Outcome","id":3,"#));
+        assert_eq!(serde_json::from_str::<Message>(&json).unwrap(), message);
+    }
+
+    #[test]
+    fn phase_result_round_trips() {
+        let phase_result = PhaseResult {
+            phase: Phase::Build,
+            duration: Duration::from_millis(2500),
+            process_status: ProcessStatus::Success,
+            argv: vec!["cargo".to_owned(), "build".to_owned()],
+            packages: Vec::new(),
+            failing_tests: None,
+            passing_tests: None,
+        };
+        let remote = RemotePhaseResult::from(&phase_result);
+        assert_eq!(remote.duration, 2.5);
+        assert_eq!(PhaseResult::from(remote), phase_result);
+    }
+}
@@ -26,5 +26,44 @@ pub const TIMEOUT: i32 = 3;
 /// The tests are already failing in an unmutated tree.
 pub const CLEAN_TESTS_FAILED: i32 = 4;
 
+/// The run reached `--max-runtime` before all the mutants were tested.
+///
+/// This takes precedence over timeouts and missed mutants in the mutants that were
+/// tested, so that automation can tell the results are incomplete.
+pub const OUT_OF_TIME: i32 = 5;
+
 /// An internal software error, from sysexit.
 pub const SOFTWARE: i32 = 70;
+
+/// Choose the exit code for a whole run, reflecting the most important problem found.
+///
+/// In order of precedence: [CLEAN_TESTS_FAILED], [OUT_OF_TIME], [TIMEOUT],
+/// [FOUND_PROBLEMS], and otherwise [SUCCESS].
+pub fn overall(baseline_failed: bool, timeouts: usize, missed: usize, not_tested: usize) -> i32 {
+    if baseline_failed {
+        CLEAN_TESTS_FAILED
+    } else if not_tested > 0 {
+        OUT_OF_TIME
+    } else if timeouts > 0 {
+        TIMEOUT
+    } else if missed > 0 {
+        FOUND_PROBLEMS
+    } else {
+        SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn overall_exit_code_precedence() {
+        assert_eq!(overall(false, 0, 0, 0), SUCCESS);
+        assert_eq!(overall(false, 0, 1, 0), FOUND_PROBLEMS);
+        assert_eq!(overall(false, 1, 1, 0), TIMEOUT);
+        assert_eq!(overall(false, 1, 1, 1), OUT_OF_TIME);
+        assert_eq!(overall(false, 0, 0, 1), OUT_OF_TIME);
+        assert_eq!(overall(true, 1, 1, 1), CLEAN_TESTS_FAILED);
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright 2024 Martin Pool
+
+//! Read the JUnit XML reports written by nextest, to learn which tests passed and failed.
+//!
+//! This only understands the subset of JUnit XML that nextest writes: a `<testcase>`
+//! element for each test, with `name` and `classname` attributes, the classname being
+//! the nextest binary id, containing a `<failure>` or `<error>` element if the test
+//! failed, or a `<skipped>` element if it didn't run.
+
+/// Return the names of the failing tests in a JUnit report, as `binary_id test_name`,
+/// the same way nextest shows them.
+pub fn failing_tests(xml: &str) -> Vec<String> {
+    testcases(xml)
+        .into_iter()
+        .filter(|(_, body)| has_element(body, "failure") || has_element(body, "error"))
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Return the names of the tests that ran and passed, in the same form as [failing_tests].
+pub fn passing_tests(xml: &str) -> Vec<String> {
+    testcases(xml)
+        .into_iter()
+        .filter(|(_, body)| {
+            !["failure", "error", "skipped"]
+                .iter()
+                .any(|element| has_element(body, element))
+        })
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Return the full name and the body of each `<testcase>` element.
+fn testcases(xml: &str) -> Vec<(String, &str)> {
+    let mut testcases = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find("<testcase") {
+        rest = &rest[start..];
+        let Some(tag_end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..tag_end];
+        let body = if tag.ends_with('/') {
+            ""
+        } else {
+            let body_end = rest.find("</testcase>").unwrap_or(rest.len());
+            &rest[tag_end..body_end]
+        };
+        rest = &rest[tag_end..];
+        let name = attribute(tag, "name").unwrap_or_default();
+        let name = match attribute(tag, "classname") {
+            Some(classname) if !classname.is_empty() => format!("{classname} {name}"),
+            _ => name,
+        };
+        testcases.push((name, body));
+    }
+    testcases
+}
+
+/// True if `body` contains a child element with this name.
+fn has_element(body: &str, element: &str) -> bool {
+    body.match_indices('<').any(|(i, _)| {
+        body[i + 1..]
+            .strip_prefix(element)
+            .and_then(|after| after.chars().next())
+            .is_some_and(|c| c == '>' || c == '/' || c.is_whitespace())
+    })
+}
+
+/// Return the unescaped value of an attribute in a start tag.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let pattern = format!(" {name}=\"");
+    let start = tag.find(&pattern)? + pattern.len();
+    let len = tag[start..].find('"')?;
+    Some(unescape(&tag[start..start + len]))
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn failing_tests_from_nextest_report() {
+        let xml = indoc! { r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <testsuites name="nextest-run" tests="4" failures="2" errors="0">
+                <testsuite name="demo" tests="3" disabled="0" errors="0" failures="1">
+                    <testcase name="test::passes" classname="demo" timestamp="2024-01-01T00:00:00Z" time="0.001">
+                    </testcase>
+                    <testcase name="test::fails&lt;u8&gt;" classname="demo" time="0.002">
+                        <failure type="test failure">thread 'test::fails' panicked at src/lib.rs:9:9</failure>
+                        <system-out></system-out>
+                    </testcase>
+                    <testcase name="test::flaky" classname="demo" time="0.003">
+                        <flakyFailure type="test failure">failed once</flakyFailure>
+                    </testcase>
+                </testsuite>
+                <testsuite name="demo::integration" tests="2" disabled="0" errors="1" failures="0">
+                    <testcase name="crashes" classname="demo::integration" time="0.1">
+                        <error type="test abort">SIGSEGV</error>
+                    </testcase>
+                    <testcase name="ok" classname="demo::integration" time="0.1"/>
+                </testsuite>
+            </testsuites>
+        "# };
+        assert_eq!(
+            failing_tests(xml),
+            ["demo test::fails<u8>", "demo::integration crashes"]
+        );
+        assert_eq!(
+            passing_tests(xml),
+            [
+                "demo test::passes",
+                "demo test::flaky",
+                "demo::integration ok"
+            ]
+        );
+    }
+
+    #[test]
+    fn skipped_tests_did_not_pass() {
+        let xml = indoc! { r#"
+            <testsuite name="demo" tests="2">
+                <testcase name="slow" classname="demo"><skipped/></testcase>
+                <testcase name="fast" classname="demo"/>
+            </testsuite>
+        "# };
+        assert_eq!(passing_tests(xml), ["demo fast"]);
+        assert!(failing_tests(xml).is_empty());
+    }
+
+    #[test]
+    fn no_failing_tests_in_empty_report() {
+        assert!(failing_tests("").is_empty());
+        assert!(failing_tests(r#"<testsuites name="nextest-run" tests="0"/>"#).is_empty());
+    }
+}
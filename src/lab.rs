@@ -1,9 +1,10 @@
-// Copyright 2021-2023 Martin Pool
+// Copyright 2021-2024 Martin Pool
 
 //! Successively apply mutations to the source code and run cargo to check, build, and test them.
 
 use std::cmp::{max, min};
-use std::sync::Mutex;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
@@ -13,11 +14,18 @@ use tracing::warn;
 #[allow(unused)]
 use tracing::{debug, debug_span, error, info, trace};
 
-use crate::cargo::run_cargo;
-use crate::console::Console;
+use crate::cargo::{doctest_filters, run_cargo, run_doctests};
+use crate::cfg::enabled_by_features;
+use crate::console::{plural, Console};
+use crate::distributed::WorkerClient;
+use crate::mutate::{package_sizes, Function};
+use crate::options::MutantOrder;
 use crate::outcome::{LabOutcome, Phase, ScenarioOutcome};
 use crate::output::OutputDir;
 use crate::package::Package;
+use crate::process::ProcessStatus;
+use crate::sandbox::check_sandbox_available;
+use crate::schema::{plan_schemata, Schema};
 use crate::*;
 
 /// Run all possible mutation experiments.
@@ -27,9 +35,13 @@ use crate::*;
 ///
 /// Before testing the mutants, the lab checks that the source tree passes its tests with no
 /// mutations applied.
+///
+/// If a `coordinator` is given, this process is a worker: rather than testing all the
+/// mutants, it tests those the coordinator assigns to it, and reports their outcomes back.
 pub fn test_mutants(
     mut mutants: Vec<Mutant>,
     workspace_dir: &Utf8Path,
+    coordinator: Option<&WorkerClient>,
     options: Options,
     console: &Console,
 ) -> Result<LabOutcome> {
@@ -40,9 +52,15 @@ pub fn test_mutants(
         .map_or(workspace_dir, |p| p.as_path());
     let output_dir = OutputDir::new(output_in_dir)?;
     console.set_debug_log(output_dir.open_debug_log()?);
+    if options.sandbox {
+        check_sandbox_available(workspace_dir)?;
+    }
 
-    if options.shuffle {
-        fastrand::shuffle(&mut mutants);
+    if coordinator.is_none() {
+        mutants = mutant_order(&mutants, options.order)
+            .into_iter()
+            .map(|i| mutants[i].clone())
+            .collect();
     }
     output_dir.write_mutants_list(&mutants)?;
     console.discovered_mutants(&mutants);
@@ -66,11 +84,28 @@ pub fn test_mutants(
                 &output_mutex,
                 &Scenario::Baseline,
                 &all_packages,
-                options.test_timeout.unwrap_or(Duration::MAX),
+                Timeouts {
+                    build: options.build_timeout.unwrap_or(Duration::MAX),
+                    test: options.test_timeout.unwrap_or(Duration::MAX),
+                },
                 &options,
                 console,
             )?;
-            if !outcome.success() {
+            if outcome.is_flaky() {
+                warn!(
+                    "Tests are flaky in an unmutated tree: they passed in {} of {} attempts",
+                    outcome
+                        .phase_results()
+                        .iter()
+                        .filter(|pr| pr.phase == Phase::Test && pr.is_success())
+                        .count(),
+                    outcome
+                        .phase_results()
+                        .iter()
+                        .filter(|pr| pr.phase == Phase::Test)
+                        .count(),
+                );
+            } else if !outcome.success() {
                 error!(
                     "cargo {} failed in an unmutated tree, so no mutants were tested",
                     outcome.last_phase(),
@@ -86,16 +121,28 @@ pub fn test_mutants(
         }
         BaselineStrategy::Skip => None,
     };
+    let test_timeouts = test_timeouts(&baseline_outcome, &options);
+    let build_timeout = build_timeout(&baseline_outcome, &options);
+    let mutants = check_doc_examples(
+        mutants,
+        &build_dir,
+        &output_mutex,
+        &test_timeouts,
+        baseline_outcome.is_some(),
+        &options,
+        console,
+    )?;
     let mut build_dirs = vec![build_dir];
-    let test_timeout = test_timeout(&baseline_outcome, &options);
 
     let jobs = max(1, min(options.jobs.unwrap_or(1), mutants.len()));
     console.build_dirs_start(jobs - 1);
     for i in 1..jobs {
+        // Copy from the first build dir, rather than the source, so that the new
+        // dirs start with everything built by the baseline.
         debug!("copy build dir {i}");
-        build_dirs.push(BuildDir::copy_from(
-            workspace_dir,
+        build_dirs.push(build_dirs[0].copy(
             options.gitignore,
+            options.share_deps,
             options.leak_dirs,
             console,
         )?);
@@ -103,90 +150,336 @@ pub fn test_mutants(
     console.build_dirs_finished();
     debug!(build_dirs = ?build_dirs);
 
+    let (schemata, mut mutants) = if options.schemata
+        && !options.check_only
+        && coordinator.is_none()
+        && options.feature_sets.is_empty()
+    {
+        plan_schemata(mutants)
+    } else {
+        if options.schemata {
+            warn!("--schemata has no effect with --check, --worker, or feature_sets");
+        }
+        (Vec::new(), mutants)
+    };
+    debug!(n_schemata = schemata.len(), n_separate = mutants.len());
+    // A worker tests whichever mutants the coordinator sends, identified by their index
+    // in this list, rather than working through the list itself.
+    let worker_mutants = if coordinator.is_some() {
+        std::mem::take(&mut mutants)
+    } else {
+        Vec::new()
+    };
+
     // Create n threads, each dedicated to one build directory. Each of them tries to take a
     // scenario to test off the queue, and then exits when there are no more left.
-    console.start_testing_mutants(mutants.len());
+    let schema_mutant_count: usize = schemata.iter().map(|schema| schema.mutants.len()).sum();
+    console.start_testing_mutants(mutants.len() + schema_mutant_count);
+    let schema_queue = Mutex::new(schemata.into_iter());
     let numbered_mutants = Mutex::new(mutants.into_iter().enumerate());
-    thread::scope(|scope| {
+    let out_of_time = || {
+        options
+            .max_runtime
+            .is_some_and(|max_runtime| start_time.elapsed() >= max_runtime)
+    };
+    thread::scope(|scope| -> Result<()> {
         let mut threads = Vec::new();
         // TODO: Maybe, make the copies in parallel on each thread, rather than up front?
         for build_dir in build_dirs {
-            threads.push(scope.spawn(|| {
+            threads.push(scope.spawn(|| -> Result<()> {
                 let build_dir = build_dir; // move it into this thread
                 trace!(thread_id = ?thread::current().id(), ?build_dir, "start thread");
                 loop {
-                    // Not a while loop so that it only holds the lock briefly.
-                    let next = numbered_mutants.lock().expect("lock mutants queue").next();
+                    // Scenarios already started are allowed to finish, but no more are started.
+                    if out_of_time() {
+                        trace!("out of time");
+                        break;
+                    }
+                    // Schemata are built first; any of their mutants that need to be tested
+                    // separately are tested in this thread as part of the schema.
+                    let next_schema = schema_queue.lock().expect("lock schema queue").next();
+                    if let Some(schema) = next_schema {
+                        let timeouts = Timeouts {
+                            build: build_timeout,
+                            test: test_timeouts.for_package(&schema.source_file.package),
+                        };
+                        test_schema(
+                            &build_dir,
+                            &output_mutex,
+                            &schema,
+                            timeouts,
+                            &options,
+                            console,
+                        )?;
+                        continue;
+                    }
+                    let next = if let Some(coordinator) = coordinator {
+                        match coordinator.next_mutant() {
+                            Ok(next) => next.map(|id| (id, worker_mutants[id].clone())),
+                            Err(err) => {
+                                error!("Failed to get a mutant from the coordinator: {err:#}");
+                                break;
+                            }
+                        }
+                    } else {
+                        // Not a while loop so that it only holds the lock briefly.
+                        numbered_mutants.lock().expect("lock mutants queue").next()
+                    };
                     if let Some((mutant_id, mutant)) = next {
                         let _span = debug_span!("mutant", id = mutant_id).entered();
                         let package = mutant.package().clone();
-                        // We don't care about the outcome; it's been collected into the output_dir.
-                        let _outcome = test_scenario(
+                        // The outcome has been collected into the output_dir, and only
+                        // needs to be sent on if this is a worker.
+                        let outcome = test_scenario(
                             &build_dir,
                             &output_mutex,
                             &Scenario::Mutant(mutant),
                             &[&package],
-                            test_timeout,
+                            Timeouts {
+                                build: build_timeout,
+                                test: test_timeouts.for_package(&package),
+                            },
                             &options,
                             console,
-                        )
-                        .expect("scenario test");
+                        )?;
+                        if let Some(coordinator) = coordinator {
+                            if let Err(err) = coordinator.report(mutant_id, &outcome) {
+                                error!("Failed to report an outcome to the coordinator: {err:#}");
+                                break;
+                            }
+                        }
                     } else {
                         trace!("no more work");
                         break;
                     }
                 }
+                Ok(())
             }));
         }
         for thread in threads {
-            thread.join().expect("join thread");
+            thread.join().expect("join thread")?;
         }
-    });
+        Ok(())
+    })?;
 
-    let output_dir = output_mutex
+    let mut output_dir = output_mutex
         .into_inner()
         .expect("final unlock mutants queue");
+    let not_tested = schema_queue
+        .into_inner()
+        .expect("final unlock schema queue")
+        .flat_map(|schema| schema.mutants)
+        .chain(
+            numbered_mutants
+                .into_inner()
+                .expect("final unlock mutants queue")
+                .map(|(_, mutant)| mutant),
+        )
+        .collect_vec();
+    if !not_tested.is_empty() {
+        warn!(
+            "Reached --max-runtime; {} not tested",
+            plural(not_tested.len(), "mutant")
+        );
+        output_dir.add_not_tested(not_tested)?;
+    }
+    output_dir.write_useless_tests()?;
     console.lab_finished(&output_dir.lab_outcome, start_time, &options);
     let lab_outcome = output_dir.take_lab_outcome();
-    if lab_outcome.total_mutants == 0 {
+    if lab_outcome.total_mutants == 0 && lab_outcome.not_tested.is_empty() {
         // This should be unreachable as we also bail out before copying
         // the tree if no mutants are generated.
         warn!("No mutants were generated");
-    } else if lab_outcome.unviable == lab_outcome.total_mutants {
+    } else if lab_outcome.total_mutants > 0 && lab_outcome.unviable == lab_outcome.total_mutants {
         warn!("No mutants were viable; perhaps there is a problem with building in a scratch directory");
     }
     Ok(lab_outcome)
 }
 
-fn test_timeout(baseline_outcome: &Option<ScenarioOutcome>, options: &Options) -> Duration {
+/// The smallest automatically-set build timeout.
+///
+/// This allows for incremental builds of mutants taking longer than the baseline build,
+/// for example when the baseline starts with a warm `target` directory.
+const MINIMUM_BUILD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Timeouts for the phases of one scenario.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Timeouts {
+    /// For each check or build command.
+    build: Duration,
+    /// For each test command.
+    test: Duration,
+}
+
+/// Choose the timeout for building mutants, from the baseline or set explicitly.
+fn build_timeout(baseline_outcome: &Option<ScenarioOutcome>, options: &Options) -> Duration {
+    if let Some(timeout) = options.build_timeout {
+        return timeout;
+    }
+    let Some(baseline_outcome) = baseline_outcome else {
+        return Duration::MAX;
+    };
+    let phase = if options.check_only {
+        Phase::Check
+    } else {
+        Phase::Build
+    };
+    let timeout = max(
+        MINIMUM_BUILD_TIMEOUT,
+        Duration::from_secs(
+            (baseline_outcome.phase_duration(phase).as_secs_f64()
+                * options.build_timeout_multiplier.unwrap_or(5.0))
+            .round() as u64,
+        ),
+    );
+    if options.show_times {
+        info!(
+            "Auto-set build timeout to {}",
+            humantime::format_duration(timeout)
+        );
+    }
+    timeout
+}
+
+/// Timeouts for testing mutants, derived from the baseline or set explicitly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TestTimeouts {
+    /// The timeout for packages that don't have their own.
+    default: Duration,
+    /// Timeouts for each package, scaled from the time to test that package in the baseline.
+    by_package: HashMap<String, Duration>,
+}
+
+impl TestTimeouts {
+    fn fixed(timeout: Duration) -> TestTimeouts {
+        TestTimeouts {
+            default: timeout,
+            by_package: HashMap::new(),
+        }
+    }
+
+    fn for_package(&self, package: &Package) -> Duration {
+        self.by_package
+            .get(&package.name)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+fn test_timeouts(baseline_outcome: &Option<ScenarioOutcome>, options: &Options) -> TestTimeouts {
     if let Some(timeout) = options.test_timeout {
-        timeout
+        TestTimeouts::fixed(timeout)
     } else if options.check_only {
-        Duration::ZERO
+        TestTimeouts::fixed(Duration::ZERO)
     } else if options.baseline == BaselineStrategy::Skip {
         warn!("An explicit timeout is recommended when using --baseline=skip; using 300 seconds by default");
-        Duration::from_secs(300)
+        TestTimeouts::fixed(Duration::from_secs(300))
     } else {
-        let timeout = max(
+        let baseline_outcome = baseline_outcome
+            .as_ref()
+            .expect("Baseline tests should have run");
+        let timeouts = auto_test_timeouts(baseline_outcome, options);
+        if options.show_times {
+            let (shortest, longest) = timeouts
+                .by_package
+                .values()
+                .copied()
+                .minmax()
+                .into_option()
+                .unwrap_or((timeouts.default, timeouts.default));
+            if shortest == longest {
+                info!(
+                    "Auto-set test timeout to {}",
+                    humantime::format_duration(longest)
+                );
+            } else {
+                info!(
+                    "Auto-set test timeouts from {} to {} for {} packages",
+                    humantime::format_duration(shortest),
+                    humantime::format_duration(longest),
+                    timeouts.by_package.len(),
+                );
+            }
+        }
+        debug!(?timeouts);
+        timeouts
+    }
+}
+
+/// Scale the time taken by the baseline tests to get timeouts for the whole tree and
+/// for each package.
+fn auto_test_timeouts(baseline_outcome: &ScenarioOutcome, options: &Options) -> TestTimeouts {
+    let scale = |baseline_duration: Duration| {
+        max(
             options.minimum_test_timeout,
             Duration::from_secs(
-                (baseline_outcome
-                    .as_ref()
-                    .expect("Baseline tests should have run")
-                    .total_phase_duration(Phase::Test)
-                    .as_secs_f64()
-                    * options.test_timeout_multiplier.unwrap_or(5.0))
-                .round() as u64,
+                (baseline_duration.as_secs_f64() * options.test_timeout_multiplier.unwrap_or(5.0))
+                    .round() as u64,
             ),
-        );
-        if options.show_times {
-            info!(
-                "Auto-set test timeout to {}",
-                humantime::format_duration(timeout)
-            );
-        }
-        timeout
+        )
+    };
+    TestTimeouts {
+        default: scale(baseline_outcome.phase_duration(Phase::Test)),
+        by_package: baseline_outcome
+            .package_phase_durations(Phase::Test)
+            .into_iter()
+            .map(|(package, duration)| (package, scale(duration)))
+            .collect(),
+    }
+}
+
+/// Return the indexes of the mutants in the order they should be tested.
+pub fn mutant_order(mutants: &[Mutant], order: MutantOrder) -> Vec<usize> {
+    let mut indexes = (0..mutants.len()).collect_vec();
+    match order {
+        MutantOrder::Random => fastrand::shuffle(&mut indexes),
+        MutantOrder::Smart => indexes = smart_order(mutants),
+        MutantOrder::Source => (),
     }
+    indexes
+}
+
+/// Order mutants so that the first ones tested give the most useful results if the run
+/// is interrupted or limited in time, returning their indexes.
+///
+/// Packages with less source, which are likely to be faster to build, come first. Within
+/// each package, one mutant from each function is tested before a second from any
+/// function, and in each of these rounds files with fewer mutants come first.
+/// Otherwise, mutants stay in source order.
+fn smart_order(mutants: &[Mutant]) -> Vec<usize> {
+    let package_size = package_sizes(mutants);
+    let file_mutants = mutants
+        .iter()
+        .counts_by(|m| m.source_file.tree_relative_path.clone());
+    let mut function_mutants: HashMap<(Utf8PathBuf, Option<(usize, usize)>), usize> =
+        HashMap::new();
+    let mut keyed = mutants
+        .iter()
+        .enumerate()
+        .map(|(index, mutant)| {
+            let function_start = mutant
+                .function
+                .as_ref()
+                .map(|f| (f.span.start.line, f.span.start.column));
+            let round = function_mutants
+                .entry((
+                    mutant.source_file.tree_relative_path.clone(),
+                    function_start,
+                ))
+                .or_default();
+            let key = (
+                package_size[mutant.package_name()],
+                mutant.package_name().to_owned(),
+                *round,
+                file_mutants[&mutant.source_file.tree_relative_path],
+                index,
+            );
+            *round += 1;
+            key
+        })
+        .collect_vec();
+    keyed.sort();
+    keyed.into_iter().map(|key| key.4).collect()
 }
 
 /// Test various phases of one scenario in a build dir.
@@ -198,7 +491,7 @@ fn test_scenario(
     output_mutex: &Mutex<OutputDir>,
     scenario: &Scenario,
     test_packages: &[&Package],
-    test_timeout: Duration,
+    timeouts: Timeouts,
     options: &Options,
     console: &Console,
 ) -> Result<ScenarioOutcome> {
@@ -224,26 +517,79 @@ fn test_scenario(
     } else {
         &[Phase::Build, Phase::Test]
     };
-    for &phase in phases {
-        console.scenario_phase_started(scenario, phase);
-        let timeout = match phase {
-            Phase::Test => test_timeout,
-            _ => Duration::MAX,
-        };
-        let phase_result = run_cargo(
-            build_dir,
-            Some(test_packages),
-            phase,
-            timeout,
-            &mut log_file,
-            options,
-            console,
-        )?;
-        let success = phase_result.is_success(); // so we can move it away
-        outcome.add_phase_result(phase_result);
-        console.scenario_phase_finished(scenario, phase);
-        if (phase == Phase::Check && options.check_only) || !success {
-            break;
+    // A mutant that fails to build under one feature set is still tested with the others,
+    // and is only unviable if it builds under none of them.
+    'feature_sets: for options in &scenario_feature_sets(scenario, options, &mut log_file) {
+        for &phase in phases {
+            let mut build_failed = false;
+            console.scenario_phase_started(scenario, phase);
+            let success = if phase == Phase::Test {
+                // With per-package timeouts, the baseline tests each package separately, as
+                // the mutants are tested, so that each mutant's timeout can be based on the
+                // time to test its own package.
+                let package_groups = if scenario.is_mutant() || !options.per_package_timeouts {
+                    vec![test_packages.to_vec()]
+                } else {
+                    test_packages
+                        .iter()
+                        .sorted_by_key(|p| &p.name)
+                        .map(|&p| vec![p])
+                        .collect_vec()
+                };
+                let mut success = true;
+                for packages in &package_groups {
+                    success = run_tests(
+                        build_dir,
+                        packages,
+                        TestSelection::All,
+                        &mut outcome,
+                        timeouts.test,
+                        &[],
+                        &mut log_file,
+                        options,
+                        console,
+                    )?;
+                    if let Some(doctests) = doctest_selection(scenario, options).filter(|_| success)
+                    {
+                        success = run_tests(
+                            build_dir,
+                            packages,
+                            doctests,
+                            &mut outcome,
+                            timeouts.test,
+                            &[],
+                            &mut log_file,
+                            options,
+                            console,
+                        )?;
+                    }
+                    if !success {
+                        break;
+                    }
+                }
+                success
+            } else {
+                let phase_result = run_cargo(
+                    build_dir,
+                    Some(test_packages),
+                    phase,
+                    timeouts.build,
+                    &[],
+                    &mut log_file,
+                    options,
+                    console,
+                )?;
+                let success = phase_result.is_success(); // so we can move it away
+                build_failed = phase_result.process_status.is_failure();
+                outcome.add_phase_result(phase_result);
+                success
+            };
+            console.scenario_phase_finished(scenario, phase);
+            if build_failed && scenario.is_mutant() {
+                continue 'feature_sets;
+            } else if !success {
+                break 'feature_sets;
+            }
         }
     }
     drop(applied);
@@ -257,10 +603,366 @@ fn test_scenario(
     Ok(outcome)
 }
 
+/// Which tests to run in a test phase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TestSelection<'a> {
+    /// Whatever the test tool runs by default, subject to the options.
+    All,
+    /// Only the doctests, or only those of the named function.
+    Doctests(Option<&'a str>),
+}
+
+/// Decide whether the doctests should be run separately for a scenario, once its other
+/// tests have passed, and if so which of them.
+fn doctest_selection<'a>(scenario: &'a Scenario, options: &Options) -> Option<TestSelection<'a>> {
+    match (options.doctests, options.test_tool) {
+        (_, TestTool::Command) => None,
+        // nextest can't run doctests, so they're run by cargo afterwards.
+        (Some(true), TestTool::Nextest) => Some(TestSelection::Doctests(None)),
+        // The function's own examples might be the only tests of it.
+        (Some(false), _) => scenario
+            .mutant()
+            .and_then(|mutant| mutant.function.as_deref())
+            .filter(|function| doc_example_may_be_only_test(function))
+            .map(|function| TestSelection::Doctests(Some(&function.function_name))),
+        _ => None,
+    }
+}
+
+/// True if the function has a doc example, and no other test code mentions it, so the
+/// example might be its only test.
+fn doc_example_may_be_only_test(function: &Function) -> bool {
+    function.has_doc_example && !function.mentioned_in_tests
+}
+
+/// With `doctests = false`, run the doc examples that might be run against mutants in the
+/// baseline build, and stop using any that fail.
+///
+/// Otherwise, a broken example would fail against every mutant in its function, and they'd
+/// all seem to be caught. If the baseline was skipped, the examples can't be checked, and
+/// so none of them are used.
+fn check_doc_examples(
+    mutants: Vec<Mutant>,
+    build_dir: &BuildDir,
+    output_mutex: &Mutex<OutputDir>,
+    test_timeouts: &TestTimeouts,
+    baseline_ran: bool,
+    options: &Options,
+    console: &Console,
+) -> Result<Vec<Mutant>> {
+    if options.doctests != Some(false) || options.test_tool == TestTool::Command {
+        return Ok(mutants);
+    }
+    let packages = mutants
+        .iter()
+        .filter(|mutant| {
+            mutant
+                .function
+                .as_deref()
+                .is_some_and(doc_example_may_be_only_test)
+        })
+        .map(|mutant| mutant.package())
+        .unique()
+        .sorted_by_key(|package| &package.name)
+        .collect_vec();
+    if packages.is_empty() {
+        return Ok(mutants);
+    }
+    // For each package, the names of the doctests that failed, or None if they can't
+    // be used at all.
+    let mut failing_doctests: HashMap<String, Option<Vec<String>>> = HashMap::new();
+    if baseline_ran {
+        let mut log_file = output_mutex
+            .lock()
+            .expect("lock output_dir to create log")
+            .create_named_log("baseline_doctests")?;
+        log_file.message("check the doc examples that might be the only tests of some functions");
+        for package in packages {
+            let phase_result = run_doctests(
+                build_dir,
+                &[package],
+                None,
+                test_timeouts.for_package(package),
+                &[],
+                &mut log_file,
+                options,
+                console,
+            )?;
+            let failing = if phase_result.is_success() {
+                Some(Vec::new())
+            } else if phase_result.process_status.is_failure() {
+                phase_result.failing_tests
+            } else {
+                None
+            };
+            failing_doctests.insert(package.name.clone(), failing);
+        }
+    }
+    let mut checked: HashMap<*const Function, Arc<Function>> = HashMap::new();
+    Ok(mutants
+        .into_iter()
+        .map(|mut mutant| {
+            let Some(function) = &mutant.function else {
+                return mutant;
+            };
+            if !doc_example_may_be_only_test(function) {
+                return mutant;
+            }
+            if let Some(checked_function) = checked.get(&Arc::as_ptr(function)) {
+                mutant.function = Some(Arc::clone(checked_function));
+                return mutant;
+            }
+            let usable = match failing_doctests.get(&mutant.package().name) {
+                Some(Some(failing)) => {
+                    let filters = doctest_filters(&function.function_name);
+                    !failing
+                        .iter()
+                        .any(|name| filters.iter().any(|filter| name.contains(filter.as_str())))
+                }
+                _ => false,
+            };
+            let checked_function = if usable {
+                Arc::clone(function)
+            } else {
+                debug!(
+                    function = function.function_name,
+                    "doc examples don't pass in the baseline; not used"
+                );
+                Arc::new(Function {
+                    has_doc_example: false,
+                    ..Function::clone(function)
+                })
+            };
+            checked.insert(Arc::as_ptr(function), Arc::clone(&checked_function));
+            mutant.function = Some(checked_function);
+            mutant
+        })
+        .collect())
+}
+
+/// Return the options for each feature set that a scenario should be built and tested with.
+///
+/// A mutant is skipped for the feature sets under which its `cfg` attributes show that its
+/// code isn't compiled, unless that would skip all of them.
+fn scenario_feature_sets(
+    scenario: &Scenario,
+    options: &Options,
+    log_file: &mut LogFile,
+) -> Vec<Options> {
+    let feature_set_options = options.feature_set_options();
+    let Some(mutant) = scenario.mutant() else {
+        return feature_set_options;
+    };
+    let (compiled, not_compiled): (Vec<Options>, Vec<Options>) =
+        feature_set_options.into_iter().partition(|options| {
+            enabled_by_features(
+                &mutant.cfg,
+                &mutant.package().active_features(&options.features),
+            ) != Some(false)
+        });
+    if compiled.is_empty() {
+        return not_compiled;
+    }
+    for options in &not_compiled {
+        log_file.message(&format!(
+            "not compiled with --features={:?}; skipped",
+            options.features.features.join(",")
+        ));
+    }
+    compiled
+}
+
+/// Run the tests, and then rerun them up to `options.retries` more times to detect flaky tests.
+///
+/// The baseline tests are rerun every time, so that flakiness is noticed before any mutants
+/// are tested. The tests for a mutant are only rerun while they fail: a single pass shows
+/// that the failure doesn't reliably catch the mutant.
+///
+/// Returns true if the tests passed in any attempt.
+#[allow(clippy::too_many_arguments)]
+fn run_tests(
+    build_dir: &BuildDir,
+    test_packages: &[&Package],
+    selection: TestSelection,
+    outcome: &mut ScenarioOutcome,
+    timeout: Duration,
+    extra_env: &[(String, String)],
+    log_file: &mut LogFile,
+    options: &Options,
+    console: &Console,
+) -> Result<bool> {
+    let (mut passed, mut failed) = (false, false);
+    for attempt in 0..=options.retries {
+        if attempt > 0 {
+            let last_status = outcome.last_phase_result();
+            let rerun = if outcome.scenario.is_mutant() {
+                last_status.is_failure()
+            } else {
+                !last_status.is_timeout()
+            };
+            if !rerun || (passed && failed) {
+                break;
+            }
+            log_file.message(&format!(
+                "retrying tests: attempt {} of {}",
+                attempt + 1,
+                options.retries + 1
+            ));
+        }
+        let phase_result = match selection {
+            TestSelection::All => run_cargo(
+                build_dir,
+                Some(test_packages),
+                Phase::Test,
+                timeout,
+                extra_env,
+                log_file,
+                options,
+                console,
+            )?,
+            TestSelection::Doctests(function_name) => run_doctests(
+                build_dir,
+                test_packages,
+                function_name,
+                timeout,
+                extra_env,
+                log_file,
+                options,
+                console,
+            )?,
+        };
+        if let (TestSelection::Doctests(_), ProcessStatus::Failure(_), None) = (
+            selection,
+            phase_result.process_status,
+            &phase_result.failing_tests,
+        ) {
+            // For example, the package has no library, so it has no doctests.
+            log_file.message("doctests did not run; ignoring their result");
+            return Ok(true);
+        }
+        passed |= phase_result.is_success();
+        failed |= phase_result.process_status.is_failure();
+        outcome.add_phase_result(phase_result);
+    }
+    Ok(passed)
+}
+
+/// Build a schema holding several mutants, and then test each of its mutants in turn
+/// against that one build.
+///
+/// If the schema doesn't build, for example because the mutants are not type-compatible
+/// when combined, then each mutant is tested separately.
+fn test_schema(
+    build_dir: &BuildDir,
+    output_mutex: &Mutex<OutputDir>,
+    schema: &Schema,
+    timeouts: Timeouts,
+    options: &Options,
+    console: &Console,
+) -> Result<()> {
+    let scenario = Scenario::Schema(schema.clone());
+    let package = schema.source_file.package.as_ref();
+    let mut schema_log = output_mutex
+        .lock()
+        .expect("lock output_dir to create log")
+        .create_log(&scenario)?;
+    schema_log.message(&scenario.to_string());
+    let applied = schema.apply(build_dir)?;
+    console.scenario_started(&scenario, schema_log.path())?;
+    console.scenario_phase_started(&scenario, Phase::Build);
+    let build_result = run_cargo(
+        build_dir,
+        Some(&[package]),
+        Phase::Build,
+        timeouts.build,
+        &[],
+        &mut schema_log,
+        options,
+        console,
+    )?;
+    console.scenario_phase_finished(&scenario, Phase::Build);
+    console.schema_build_finished(&scenario);
+    if !build_result.is_success() {
+        drop(applied);
+        debug!(
+            file = schema.source_file.tree_relative_slashes(),
+            "schema failed to build; testing its mutants separately"
+        );
+        schema_log.message("schema failed to build; testing its mutants separately");
+        for mutant in &schema.mutants {
+            let _outcome = test_scenario(
+                build_dir,
+                output_mutex,
+                &Scenario::Mutant(mutant.clone()),
+                &[package],
+                timeouts,
+                options,
+                console,
+            )?;
+        }
+        return Ok(());
+    }
+    for (i, mutant) in schema.mutants.iter().enumerate() {
+        let scenario = Scenario::Mutant(mutant.clone());
+        let mut log_file = output_mutex
+            .lock()
+            .expect("lock output_dir to create log")
+            .create_log(&scenario)?;
+        log_file.message(&scenario.to_string());
+        log_file.message(&format!("built in schema, see {}", schema_log.path()));
+        log_file.message(&format!("mutation diff:\n{}", mutant.diff()));
+        console.scenario_started(&scenario, log_file.path())?;
+        let mut outcome = ScenarioOutcome::new(&log_file, scenario.clone());
+        console.scenario_phase_started(&scenario, Phase::Test);
+        let passed = run_tests(
+            build_dir,
+            &[package],
+            TestSelection::All,
+            &mut outcome,
+            timeouts.test,
+            &schema.active_env(i),
+            &mut log_file,
+            options,
+            console,
+        )?;
+        if let Some(doctests) = doctest_selection(&scenario, options).filter(|_| passed) {
+            run_tests(
+                build_dir,
+                &[package],
+                doctests,
+                &mut outcome,
+                timeouts.test,
+                &schema.active_env(i),
+                &mut log_file,
+                options,
+                console,
+            )?;
+        }
+        console.scenario_phase_finished(&scenario, Phase::Test);
+        output_mutex
+            .lock()
+            .expect("lock output dir to add outcome")
+            .add_scenario_outcome(&outcome)?;
+        debug!(outcome = ?outcome.summary());
+        console.scenario_finished(&scenario, &outcome, options);
+    }
+    drop(applied);
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
+    use std::sync::Arc;
+
+    use pretty_assertions::assert_eq;
+
     use super::*;
     use crate::config::Config;
+    use crate::mutate::{Function, Genre};
+    use crate::outcome::PhaseResult;
+    use crate::process::ProcessStatus;
+    use crate::source::SourceFile;
+    use crate::span::Span;
 
     #[test]
     fn test_timeout_multiplier_correct_parsing() {
@@ -269,4 +971,176 @@ mod test {
 
         assert_eq!(options.test_timeout_multiplier, Some(1.5))
     }
+
+    #[test]
+    fn test_timeouts_are_scaled_from_each_package_in_the_baseline() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log_file = LogFile::create_in(tmp.path().try_into().unwrap(), "baseline").unwrap();
+        let mut outcome = ScenarioOutcome::new(&log_file, Scenario::Baseline);
+        let phase_result = |phase, secs, packages: &[&str], process_status| PhaseResult {
+            phase,
+            duration: Duration::from_secs(secs),
+            process_status,
+            argv: Vec::new(),
+            packages: packages.iter().map(|p| p.to_string()).collect(),
+            failing_tests: None,
+            passing_tests: None,
+        };
+        outcome.add_phase_result(phase_result(
+            Phase::Build,
+            100,
+            &["small", "big"],
+            ProcessStatus::Success,
+        ));
+        outcome.add_phase_result(phase_result(
+            Phase::Test,
+            2,
+            &["small"],
+            ProcessStatus::Success,
+        ));
+        outcome.add_phase_result(phase_result(
+            Phase::Test,
+            30,
+            &["big"],
+            ProcessStatus::Success,
+        ));
+        // A slower retry counts, but is not added to the first attempt.
+        outcome.add_phase_result(phase_result(
+            Phase::Test,
+            40,
+            &["big"],
+            ProcessStatus::Success,
+        ));
+        let options = Options {
+            minimum_test_timeout: Duration::from_secs(20),
+            ..Default::default()
+        };
+
+        let timeouts = auto_test_timeouts(&outcome, &options);
+        assert_eq!(timeouts.default, Duration::from_secs(210));
+        assert_eq!(
+            timeouts.by_package,
+            HashMap::from([
+                ("small".to_owned(), Duration::from_secs(20)),
+                ("big".to_owned(), Duration::from_secs(200)),
+            ])
+        );
+        let other = Package {
+            name: "other".to_owned(),
+            relative_manifest_path: "other/Cargo.toml".into(),
+            features: Default::default(),
+        };
+        assert_eq!(timeouts.for_package(&other), Duration::from_secs(210));
+    }
+
+    #[test]
+    fn build_timeout_is_scaled_from_the_baseline_build() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log_file = LogFile::create_in(tmp.path().try_into().unwrap(), "baseline").unwrap();
+        let mut outcome = ScenarioOutcome::new(&log_file, Scenario::Baseline);
+        outcome.add_phase_result(PhaseResult {
+            phase: Phase::Build,
+            duration: Duration::from_secs(30),
+            process_status: ProcessStatus::Success,
+            argv: Vec::new(),
+            packages: Vec::new(),
+            failing_tests: None,
+            passing_tests: None,
+        });
+        let baseline_outcome = Some(outcome);
+
+        let options = Options::default();
+        assert_eq!(
+            build_timeout(&baseline_outcome, &options),
+            Duration::from_secs(150)
+        );
+
+        let options = Options {
+            build_timeout_multiplier: Some(1.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            build_timeout(&baseline_outcome, &options),
+            MINIMUM_BUILD_TIMEOUT
+        );
+
+        let options = Options {
+            build_timeout: Some(Duration::from_secs(7)),
+            ..Default::default()
+        };
+        assert_eq!(
+            build_timeout(&baseline_outcome, &options),
+            Duration::from_secs(7)
+        );
+        assert_eq!(build_timeout(&None, &Options::default()), Duration::MAX);
+    }
+
+    fn source_file(package_name: &str, path: &str, code: &str) -> SourceFile {
+        SourceFile {
+            code: Arc::new(code.to_owned()),
+            package: Arc::new(Package {
+                name: package_name.to_owned(),
+                relative_manifest_path: "Cargo.toml".into(),
+                features: Default::default(),
+            }),
+            tree_relative_path: path.into(),
+            is_top: true,
+        }
+    }
+
+    fn mutant(source_file: &SourceFile, function_line: usize, replacement: &str) -> Mutant {
+        let span = Span::quad(function_line, 5, function_line, 6);
+        Mutant {
+            source_file: source_file.clone(),
+            function: Some(Arc::new(Function {
+                function_name: format!("f{function_line}"),
+                return_type: "-> u32".to_owned(),
+                span,
+                has_doc_example: false,
+                mentioned_in_tests: false,
+            })),
+            span,
+            replacement: replacement.to_owned(),
+            genre: Genre::FnValue,
+            cfg: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn smart_order_interleaves_functions_and_puts_small_packages_first() {
+        let big = source_file("big", "big/src/lib.rs", &"// big\n".repeat(100));
+        let small_a = source_file("small", "small/src/a.rs", "// a\n");
+        let small_b = source_file("small", "small/src/b.rs", "// b\n");
+        let mutants = vec![
+            mutant(&big, 1, "0"),
+            mutant(&big, 1, "1"),
+            mutant(&small_a, 1, "0"),
+            mutant(&small_a, 1, "1"),
+            mutant(&small_a, 5, "0"),
+            mutant(&small_a, 5, "1"),
+            mutant(&small_b, 1, "0"),
+        ];
+        let ordered = smart_order(&mutants)
+            .into_iter()
+            .map(|i| &mutants[i])
+            .map(|m| {
+                format!(
+                    "{}:{} {}",
+                    m.source_file.tree_relative_path, m.span.start.line, m.replacement
+                )
+            })
+            .collect_vec();
+        assert_eq!(
+            ordered,
+            [
+                "small/src/b.rs:1 0",
+                "small/src/a.rs:1 0",
+                "small/src/a.rs:5 0",
+                "small/src/a.rs:1 1",
+                "small/src/a.rs:5 1",
+                "big/src/lib.rs:1 0",
+                "big/src/lib.rs:1 1",
+            ]
+        );
+    }
 }
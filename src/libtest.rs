@@ -0,0 +1,272 @@
+// Copyright 2024 Martin Pool
+
+//! Read the output of the standard Rust test harness, libtest, to learn which tests passed
+//! and failed.
+//!
+//! After running its tests, each test binary that had failures lists them, indented,
+//! under a `failures:` line, just before its `test result:` line:
+//!
+//! ```text
+//! failures:
+//!     test::factorial_of_6
+//!
+//! test result: FAILED. 1 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out
+//! ```
+//!
+//! This is more reliable than looking at the `test ... FAILED` line for each test, which
+//! can be split up by output from tests that run concurrently or with `--nocapture`.
+//!
+//! Tests in different binaries can have the same name, so each name is qualified by the
+//! name of the binary that Cargo says it's running, like `demo test::fails`, much as
+//! nextest names tests by their binary id. Doctests are qualified by the crate name.
+
+/// Return the names of the failing tests in the output of `cargo test`, or of anything
+/// else that runs libtest binaries.
+///
+/// Returns None if there's no sign that any tests ran, for example because the tests
+/// failed to build.
+pub fn failing_tests(output: &str) -> Option<Vec<String>> {
+    let mut failing: Vec<String> = Vec::new();
+    let mut any_results = false;
+    let mut binary = None;
+    let mut lines = output.lines().peekable();
+    while let Some(line) = lines.next() {
+        if let Some(name) = binary_name(line) {
+            binary = Some(name);
+        } else if line.starts_with("test result: ") {
+            any_results = true;
+        } else if line == "failures:" {
+            // The first `failures:` heading for each binary, before the captured output,
+            // is followed by a blank line, and so lists nothing.
+            while let Some(name) = lines.peek().and_then(|l| l.strip_prefix("    ")) {
+                let name = qualified_name(binary, name);
+                if !failing.contains(&name) {
+                    failing.push(name);
+                }
+                lines.next();
+            }
+        }
+    }
+    any_results.then_some(failing)
+}
+
+/// Return the names of the tests that passed in the output of `cargo test`, from the
+/// `test NAME ... ok` line printed for each of them.
+///
+/// If the line for a test was broken up by output from other tests, it's not recognized,
+/// so this might miss some tests that did pass.
+pub fn passing_tests(output: &str) -> Vec<String> {
+    let mut passing: Vec<String> = Vec::new();
+    let mut binary = None;
+    for line in output.lines() {
+        if let Some(name) = binary_name(line) {
+            binary = Some(name);
+        } else if let Some(name) = line
+            .strip_prefix("test ")
+            .and_then(|rest| rest.strip_suffix(" ... ok"))
+        {
+            let name = qualified_name(binary, name);
+            if !passing.contains(&name) {
+                passing.push(name);
+            }
+        }
+    }
+    passing
+}
+
+/// If this line is where Cargo says it's starting a test binary, return the binary's name.
+///
+/// Cargo prints lines like `Running unittests src/lib.rs (target/debug/deps/demo-0123abcd)`,
+/// from which this returns `demo`, or `Doc-tests demo` before the doctests.
+fn binary_name(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    if let Some(crate_name) = line.strip_prefix("Doc-tests ") {
+        return Some(crate_name.trim_end());
+    }
+    let path = line
+        .strip_prefix("Running ")?
+        .trim_end()
+        .strip_suffix(')')?
+        .rsplit_once(" (")?
+        .1;
+    let file_name = path.rsplit(['/', '\\']).next()?;
+    let file_name = file_name.strip_suffix(".exe").unwrap_or(file_name);
+    Some(
+        file_name
+            .rsplit_once('-')
+            .map_or(file_name, |(name, _hash)| name),
+    )
+}
+
+/// Qualify a test name by the name of its binary, if that's known.
+fn qualified_name(binary: Option<&str>, name: &str) -> String {
+    match binary {
+        Some(binary) => format!("{binary} {name}"),
+        None => name.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn failing_tests_from_cargo_test_output() {
+        let output = indoc! { r#"
+            *** cargo test --manifest-path /tmp/x/Cargo.toml
+                Finished `test` profile [unoptimized + debuginfo] target(s) in 0.02s
+                 Running unittests src/lib.rs (target/debug/deps/demo-0123)
+
+            running 3 tests
+            test test::passes ... ok
+            test test::fails ... FAILED
+            test test::also_fails ... FAILED
+
+            failures:
+
+            ---- test::fails stdout ----
+            thread 'test::fails' panicked at src/lib.rs:20:9:
+            assertion `left == right` failed
+                left: 1
+               right: 2
+
+            ---- test::also_fails stdout ----
+            thread 'test::also_fails' panicked at src/lib.rs:25:9:
+            explicit panic
+
+            failures:
+                test::also_fails
+                test::fails
+
+            test result: FAILED. 1 passed; 2 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s
+
+            error: test failed, to rerun pass `--lib`
+        "# };
+        assert_eq!(
+            failing_tests(output),
+            Some(vec![
+                "demo test::also_fails".to_owned(),
+                "demo test::fails".to_owned()
+            ])
+        );
+    }
+
+    #[test]
+    fn failing_doctests() {
+        let output = indoc! { r#"
+               Doc-tests demo
+
+            running 1 test
+            test src/lib.rs - double (line 3) ... FAILED
+
+            failures:
+
+            ---- src/lib.rs - double (line 3) stdout ----
+            Test executable failed (exit status: 101).
+
+            failures:
+                src/lib.rs - double (line 3)
+
+            test result: FAILED. 0 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.10s
+        "# };
+        assert_eq!(
+            failing_tests(output),
+            Some(vec!["demo src/lib.rs - double (line 3)".to_owned()])
+        );
+    }
+
+    #[test]
+    fn passing_tests_have_no_failures() {
+        let output = indoc! { r#"
+            running 1 test
+            test test::passes ... ok
+
+            test result: ok. 1 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s
+        "# };
+        assert_eq!(failing_tests(output), Some(Vec::new()));
+        assert_eq!(passing_tests(output), ["test::passes"]);
+    }
+
+    #[test]
+    fn passing_doctests_and_ignored_tests() {
+        let output = indoc! { r#"
+            running 2 tests
+            test test::slow ... ignored
+            test test::fast ... ok
+
+            test result: ok. 1 passed; 0 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.00s
+
+               Doc-tests demo
+
+            running 1 test
+            test src/lib.rs - double (line 3) ... ok
+
+            test result: ok. 1 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.10s
+        "# };
+        assert_eq!(
+            passing_tests(output),
+            ["test::fast", "demo src/lib.rs - double (line 3)"]
+        );
+    }
+
+    #[test]
+    fn tests_with_the_same_name_in_different_binaries_are_distinguished() {
+        let output = indoc! { r#"
+                 Running unittests src/lib.rs (target/debug/deps/demo-0123abcd)
+
+            running 1 test
+            test test::check ... ok
+
+            test result: ok. 1 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s
+
+                 Running tests/cli.rs (target/debug/deps/cli-4567ef01)
+
+            running 2 tests
+            test test::check ... FAILED
+            test test::other ... ok
+
+            failures:
+
+            ---- test::check stdout ----
+            thread 'test::check' panicked at tests/cli.rs:8:5:
+            explicit panic
+
+            failures:
+                test::check
+
+            test result: FAILED. 1 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s
+        "# };
+        assert_eq!(
+            failing_tests(output),
+            Some(vec!["cli test::check".to_owned()])
+        );
+        assert_eq!(
+            passing_tests(output),
+            ["demo test::check", "cli test::other"]
+        );
+    }
+
+    #[test]
+    fn binary_names() {
+        assert_eq!(
+            binary_name("     Running unittests src/main.rs (target/debug/deps/cargo_mutants-25fc1e1cd6a50a07)"),
+            Some("cargo_mutants")
+        );
+        assert_eq!(
+            binary_name(r"     Running tests\cli.rs (target\debug\deps\cli-4567ef01.exe)"),
+            Some("cli")
+        );
+        assert_eq!(binary_name("   Doc-tests demo"), Some("demo"));
+        assert_eq!(binary_name("running 1 test"), None);
+    }
+
+    #[test]
+    fn no_tests_run() {
+        assert_eq!(
+            failing_tests("error[E0308]: mismatched types\nerror: could not compile `demo`\n"),
+            None
+        );
+    }
+}
@@ -0,0 +1,282 @@
+// Copyright 2024 Martin Pool
+
+//! Resource limits (rlimits) on test subprocesses.
+//!
+//! A mutant can make the tests allocate huge amounts of memory, spin in a loop,
+//! or leak file descriptors. Rather than letting that take down the whole machine,
+//! cargo-mutants can apply limits to the test processes, and then recognize tests
+//! that were stopped by a limit, so that the mutant is reported as caught for that reason.
+//!
+//! On Unix the limits are set with `setrlimit` in the child process, between `fork` and
+//! `exec`, and are inherited by everything the command starts, including the test binaries.
+//! On other platforms limits are not supported.
+//!
+//! The process that cargo-mutants starts is normally cargo or nextest, and the limit is
+//! usually hit by a test binary below it, so a limit is mostly recognized from the messages
+//! in the output, and only otherwise from the signal that killed the direct child.
+
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Seconds of CPU time a process can use after it's sent `SIGXCPU`, before it's killed.
+#[cfg(unix)]
+const CPU_KILL_GRACE_SECS: u64 = 5;
+
+/// Limits on resources used by a test process and its children.
+///
+/// Each limit is applied separately to each process.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// Maximum virtual address space, in bytes.
+    pub memory: Option<u64>,
+    /// Maximum CPU time, in seconds.
+    pub cpu_secs: Option<u64>,
+    /// Maximum number of open files.
+    pub files: Option<u64>,
+}
+
+/// A resource limit that a process ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ResourceLimit {
+    Memory,
+    Cpu,
+    Files,
+}
+
+impl fmt::Display for ResourceLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ResourceLimit::Memory => "memory limit",
+            ResourceLimit::Cpu => "CPU time limit",
+            ResourceLimit::Files => "open files limit",
+        })
+    }
+}
+
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        self.memory.is_none() && self.cpu_secs.is_none() && self.files.is_none()
+    }
+
+    /// Apply these limits to the current process.
+    ///
+    /// This is called in the child process after `fork` and before `exec`, so it must
+    /// not allocate or take locks: it only makes system calls.
+    ///
+    /// The soft limits are set, and the hard limits are left as they are, except that the
+    /// hard CPU limit is set a little above the soft limit, so that a process that carries
+    /// on after `SIGXCPU` is then killed.
+    #[cfg(unix)]
+    pub fn set_rlimits(&self) -> nix::Result<()> {
+        use nix::sys::resource::{getrlimit, setrlimit, Resource};
+
+        fn set(resource: Resource, soft: u64, hard: Option<u64>) -> nix::Result<()> {
+            let (_, old_hard) = getrlimit(resource)?;
+            let hard = hard.map_or(old_hard, |hard| hard.min(old_hard));
+            setrlimit(resource, soft.min(hard), hard)
+        }
+
+        if let Some(memory) = self.memory {
+            set(Resource::RLIMIT_AS, memory, None)?;
+        }
+        if let Some(cpu_secs) = self.cpu_secs {
+            set(
+                Resource::RLIMIT_CPU,
+                cpu_secs,
+                Some(cpu_secs.saturating_add(CPU_KILL_GRACE_SECS)),
+            )?;
+        }
+        if let Some(files) = self.files {
+            set(Resource::RLIMIT_NOFILE, files, None)?;
+        }
+        Ok(())
+    }
+
+    /// Look in the output of a failed process for signs that it hit one of these limits.
+    ///
+    /// Running out of memory in Rust normally aborts the process with a message from the
+    /// standard library; exceeding the CPU limit kills the process with `SIGXCPU`, which
+    /// cargo and nextest report by name or number; and running out of file descriptors
+    /// gives an `EMFILE` error, which is most likely reported through a panic.
+    pub fn find_exceeded(&self, output: &str) -> Option<ResourceLimit> {
+        if self.memory.is_some()
+            && output.lines().any(|line| {
+                // The message can follow the name of the test on the same line.
+                line.contains("memory allocation of ") && line.trim_end().ends_with(" bytes failed")
+            })
+        {
+            Some(ResourceLimit::Memory)
+        } else if self.cpu_secs.is_some()
+            && (output.contains("SIGXCPU") || output.contains("signal: 24"))
+        {
+            Some(ResourceLimit::Cpu)
+        } else if self.files.is_some() && output.contains("Too many open files") {
+            Some(ResourceLimit::Files)
+        } else {
+            None
+        }
+    }
+
+    /// Return the limit, if any, that explains the direct child being killed by a signal.
+    ///
+    /// Exceeding the soft CPU limit sends `SIGXCPU`, and the hard limit `SIGKILL`; but
+    /// since `SIGKILL` can come from anywhere, it's only blamed on the limit if the
+    /// process's `cpu_time` shows it used up its allowance. When an allocation fails, as it
+    /// will when the address space is exhausted, Rust programs abort, with `SIGABRT`.
+    #[cfg(unix)]
+    pub fn exceeded_by_signal(
+        &self,
+        signal: u8,
+        cpu_time: Option<Duration>,
+    ) -> Option<ResourceLimit> {
+        use nix::sys::signal::Signal;
+
+        match (Signal::try_from(i32::from(signal)).ok()?, self.cpu_secs) {
+            (Signal::SIGXCPU, Some(_)) => Some(ResourceLimit::Cpu),
+            (Signal::SIGKILL, Some(cpu_secs))
+                if cpu_time.is_some_and(|t| t >= Duration::from_secs(cpu_secs)) =>
+            {
+                Some(ResourceLimit::Cpu)
+            }
+            (Signal::SIGABRT, _) if self.memory.is_some() => Some(ResourceLimit::Memory),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(unix))]
+    #[mutants::skip] // Limits aren't supported, so can't be exceeded.
+    pub fn exceeded_by_signal(
+        &self,
+        _signal: u8,
+        _cpu_time: Option<Duration>,
+    ) -> Option<ResourceLimit> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn no_limits_is_empty() {
+        assert!(ResourceLimits::default().is_empty());
+        assert!(!ResourceLimits {
+            files: Some(256),
+            ..Default::default()
+        }
+        .is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn child_runs_with_limits() {
+        use std::os::unix::process::CommandExt;
+        use std::process::Command;
+
+        let limits = ResourceLimits {
+            memory: Some(1 << 30),
+            cpu_secs: Some(60),
+            files: Some(100),
+        };
+        let mut command = Command::new("sh");
+        command.args([
+            "-c",
+            "ulimit -S -v; ulimit -S -t; ulimit -H -t; ulimit -S -n",
+        ]);
+        unsafe {
+            command.pre_exec(move || limits.set_rlimits().map_err(std::io::Error::from));
+        }
+        let output = command.output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .collect::<Vec<_>>(),
+            ["1048576", "60", "65", "100"]
+        );
+    }
+
+    #[test]
+    fn find_exceeded_limits_in_output() {
+        let all = ResourceLimits {
+            memory: Some(1 << 30),
+            cpu_secs: Some(60),
+            files: Some(256),
+        };
+        assert_eq!(
+            all.find_exceeded(
+                "running 1 test\ntest big ... memory allocation of 1073741824 bytes failed\nerror: test failed"
+            ),
+            Some(ResourceLimit::Memory)
+        );
+        assert_eq!(
+            all.find_exceeded(
+                "process didn't exit successfully: `/tmp/x` (signal: 24, SIGXCPU: CPU time limit exceeded)"
+            ),
+            Some(ResourceLimit::Cpu)
+        );
+        assert_eq!(
+            all.find_exceeded("process didn't exit successfully: `/tmp/x` (signal: 24)"),
+            Some(ResourceLimit::Cpu)
+        );
+        assert_eq!(
+            all.find_exceeded("called `Result::unwrap()` on an `Err` value: Os { code: 24, kind: Uncategorized, message: \"Too many open files\" }"),
+            Some(ResourceLimit::Files)
+        );
+        assert_eq!(all.find_exceeded("assertion failed: x == 2"), None);
+        // Limits that weren't set aren't blamed.
+        assert_eq!(
+            ResourceLimits::default().find_exceeded("memory allocation of 10 bytes failed"),
+            None
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn limits_are_recognized_from_signals() {
+        use nix::sys::signal::Signal;
+
+        let all = ResourceLimits {
+            memory: Some(1 << 30),
+            cpu_secs: Some(60),
+            files: Some(256),
+        };
+        let signal = |s: Signal| s as u8;
+        let used = Some(Duration::from_secs(65));
+        assert_eq!(
+            all.exceeded_by_signal(signal(Signal::SIGABRT), used),
+            Some(ResourceLimit::Memory)
+        );
+        assert_eq!(
+            all.exceeded_by_signal(signal(Signal::SIGXCPU), used),
+            Some(ResourceLimit::Cpu)
+        );
+        assert_eq!(
+            all.exceeded_by_signal(signal(Signal::SIGKILL), used),
+            Some(ResourceLimit::Cpu)
+        );
+        // A process killed before using its CPU time was killed by something else.
+        assert_eq!(
+            all.exceeded_by_signal(signal(Signal::SIGKILL), Some(Duration::from_secs(3))),
+            None
+        );
+        assert_eq!(all.exceeded_by_signal(signal(Signal::SIGKILL), None), None);
+        assert_eq!(all.exceeded_by_signal(signal(Signal::SIGSEGV), used), None);
+        // Limits that weren't set aren't blamed.
+        assert_eq!(
+            ResourceLimits::default().exceeded_by_signal(signal(Signal::SIGABRT), used),
+            None
+        );
+        assert_eq!(
+            ResourceLimits {
+                memory: Some(1 << 30),
+                ..Default::default()
+            }
+            .exceeded_by_signal(signal(Signal::SIGXCPU), used),
+            None
+        );
+    }
+}
@@ -3,7 +3,7 @@
 //! Manage per-scenario log files, which contain the output from cargo
 //! and test cases, mixed with commentary from cargo-mutants.
 
-use std::fs::{File, OpenOptions};
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, Write};
 
 use anyhow::Context;
@@ -64,6 +64,25 @@ impl LogFile {
     pub fn path(&self) -> &Utf8Path {
         &self.path
     }
+
+    /// Return the current length of the log, so that what's written after this point
+    /// can later be read by [LogFile::read_from].
+    pub fn position(&self) -> Result<u64> {
+        Ok(fs::metadata(&self.path)
+            .with_context(|| format!("stat {}", self.path))?
+            .len())
+    }
+
+    /// Read the content of the log from a position returned by [LogFile::position].
+    ///
+    /// Any invalid UTF-8 is replaced.
+    pub fn read_from(&self, position: u64) -> Result<String> {
+        let content = fs::read(&self.path).with_context(|| format!("read {}", self.path))?;
+        let start = usize::try_from(position)
+            .unwrap_or(usize::MAX)
+            .min(content.len());
+        Ok(String::from_utf8_lossy(&content[start..]).into_owned())
+    }
 }
 
 fn clean_filename(s: &str) -> String {
@@ -80,6 +99,20 @@ fn clean_filename(s: &str) -> String {
 mod test {
     use super::*;
 
+    #[test]
+    fn read_from_position() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut log_file = LogFile::create_in(tmp.path().try_into().unwrap(), "a").unwrap();
+        log_file.message("before");
+        let position = log_file.position().unwrap();
+        log_file.message("after");
+        assert_eq!(log_file.read_from(position).unwrap(), "\n*** after\n");
+        assert_eq!(
+            log_file.read_from(0).unwrap(),
+            "\n*** before\n\n*** after\n"
+        );
+    }
+
     #[test]
     fn clean_filename_removes_special_characters() {
         assert_eq!(
@@ -4,18 +4,24 @@
 
 mod build_dir;
 mod cargo;
+mod cfg;
 mod config;
 mod console;
 mod copy_tree;
+mod distributed;
 mod exit_code;
 mod fnvalue;
 mod glob;
 mod in_diff;
 mod interrupt;
+mod junit;
 mod lab;
+mod libtest;
+mod limits;
 mod list;
 mod log_file;
 mod manifest;
+mod merge;
 mod mutate;
 mod options;
 mod outcome;
@@ -24,7 +30,10 @@ mod package;
 mod path;
 mod pretty;
 mod process;
+mod rustflags;
+mod sandbox;
 mod scenario;
+mod schema;
 mod shard;
 mod source;
 mod span;
@@ -41,24 +50,27 @@ use anyhow::{anyhow, ensure, Context, Result};
 use camino::{Utf8Path, Utf8PathBuf};
 use clap::builder::styling::{self};
 use clap::builder::Styles;
-use clap::{ArgAction, CommandFactory, Parser, ValueEnum};
+use clap::{ArgAction, CommandFactory, Parser, Subcommand, ValueEnum};
 use clap_complete::{generate, Shell};
 use color_print::cstr;
 use tracing::debug;
 
 use crate::build_dir::BuildDir;
 use crate::console::Console;
+use crate::distributed::{serve, WorkerClient};
 use crate::in_diff::diff_filter;
 use crate::interrupt::check_interrupted;
 use crate::lab::test_mutants;
 use crate::list::{list_files, list_mutants, FmtToIoWrite};
 use crate::log_file::LogFile;
 use crate::manifest::fix_manifest;
+use crate::merge::merge;
 use crate::mutate::{Genre, Mutant};
-use crate::options::{Colors, Options, TestTool};
+use crate::options::{Colors, MutantOrder, Options, TargetKind, TestTool};
 use crate::outcome::{Phase, ScenarioOutcome};
+use crate::rustflags::config_rustflags;
 use crate::scenario::Scenario;
-use crate::shard::Shard;
+use crate::shard::{estimate_costs, Shard, ShardStrategy};
 use crate::workspace::{PackageFilter, Workspace};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -78,6 +90,14 @@ fn clap_styles() -> Styles {
         .placeholder(styling::AnsiColor::Cyan.on_default())
 }
 
+/// Parse an environment variable setting like `NAME=VALUE`.
+fn parse_env_assignment(s: &str) -> std::result::Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_owned(), value.to_owned())),
+        _ => Err(format!("expected NAME=VALUE, got {s:?}")),
+    }
+}
+
 #[derive(Parser)]
 #[command(name = "cargo", bin_name = "cargo", styles(clap_styles()))]
 enum Cargo {
@@ -135,6 +155,10 @@ pub struct Args {
     #[arg(long, help_heading = "Filters")]
     diff: bool,
 
+    /// run doctests, or not: false runs only the lib, bin, and test targets, except for the doctests of functions with examples; true also runs doctests alongside nextest.
+    #[arg(long, action = ArgAction::Set, value_name = "BOOL", help_heading = "Execution")]
+    doctests: Option<bool>,
+
     /// rust crate directory to examine.
     #[arg(
         long,
@@ -254,6 +278,14 @@ pub struct Args {
     #[arg(long, short = 'D', help_heading = "Filters")]
     in_diff: Option<Utf8PathBuf>,
 
+    /// also mutate code under `cfg` attributes that are false for the target and the selected features.
+    #[arg(long, help_heading = "Filters")]
+    include_inactive: bool,
+
+    /// stop starting new mutants after this many seconds, and report the rest as not tested.
+    #[arg(long, help_heading = "Execution")]
+    max_runtime: Option<f64>,
+
     /// minimum timeout for tests, in seconds, as a lower bound on the auto-set time.
     #[arg(
         long,
@@ -262,23 +294,69 @@ pub struct Args {
     )]
     minimum_test_timeout: Option<f64>,
 
+    /// in a workspace, test each package separately in the baseline, and scale each mutant's timeout from the time to test its own package.
+    #[arg(long, help_heading = "Execution")]
+    per_package_timeouts: bool,
+
     /// only test mutants from these packages.
     #[arg(id = "package", long, short = 'p', help_heading = "Filters")]
     mutate_packages: Vec<String>,
 
-    /// run mutants in random order.
+    /// mutate the source of these kinds of targets: lib, bin, example, bench, custom-build; by default lib and bin.
+    #[arg(long, value_delimiter = ',', help_heading = "Filters")]
+    targets: Vec<TargetKind>,
+
+    /// experimental: build several mutants from each file together, and choose between them at runtime.
+    #[arg(long, help_heading = "Execution")]
+    schemata: bool,
+
+    /// hard-link the libraries built for dependencies between build directories, rather than copying them.
+    #[arg(long, help_heading = "Copying", group = "copy_opts")]
+    share_deps: bool,
+
+    /// order in which to test mutants: smart, random, or source.
+    #[arg(long, help_heading = "Execution", conflicts_with_all = ["shuffle", "no_shuffle"])]
+    order: Option<MutantOrder>,
+
+    /// run mutants in random order; the same as `--order=random`.
     #[arg(long, help_heading = "Execution")]
     shuffle: bool,
 
-    /// run mutants in the fixed order they occur in the source tree.
+    /// run mutants in the fixed order they occur in the source tree; the same as `--order=source`.
     #[arg(long, help_heading = "Execution")]
     no_shuffle: bool,
 
+    /// hand out mutants to workers connecting to this address, such as 0.0.0.0:7878, rather than testing them here.
+    #[arg(long, value_name = "ADDRESS", help_heading = "Distributed", conflicts_with_all = ["worker", "list"])]
+    serve: Option<String>,
+
+    /// test mutants assigned by the coordinator at this address, and report their outcomes to it.
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        help_heading = "Distributed",
+        conflicts_with = "list"
+    )]
+    worker: Option<String>,
+
     /// run only one shard of all generated mutants: specify as e.g. 1/4.
     #[arg(long, help_heading = "Execution")]
     shard: Option<Shard>,
 
-    /// tool used to run test suites: cargo or nextest.
+    /// how to divide mutants between shards: index, cost to balance their estimated run time, or hash to keep each mutant in the same shard as the code changes.
+    #[arg(long, help_heading = "Execution", requires = "shard")]
+    shard_strategy: Option<ShardStrategy>,
+
+    /// outcomes.json or mutants.out from a previous run, used to estimate costs for --shard-strategy=cost.
+    #[arg(
+        long,
+        value_name = "PATH",
+        help_heading = "Execution",
+        requires = "shard"
+    )]
+    shard_costs: Option<Utf8PathBuf>,
+
+    /// tool used to run test suites: cargo, nextest, or command to use test_command from the config.
     #[arg(long, help_heading = "Execution")]
     test_tool: Option<TestTool>,
 
@@ -286,10 +364,38 @@ pub struct Args {
     #[arg(long, short = 't', help_heading = "Execution")]
     timeout: Option<f64>,
 
+    /// rerun the baseline tests, and failing tests for each mutant, up to this many times to detect flaky tests.
+    #[arg(long, value_name = "N", help_heading = "Execution")]
+    retries: Option<usize>,
+
     /// test timeout multiplier (relative to base test time).
     #[arg(long, help_heading = "Execution")]
     timeout_multiplier: Option<f64>,
 
+    /// maximum run time for cargo build and check commands, in seconds.
+    #[arg(long, help_heading = "Execution")]
+    build_timeout: Option<f64>,
+
+    /// build timeout multiplier (relative to base build time).
+    #[arg(long, help_heading = "Execution", conflicts_with = "build_timeout")]
+    build_timeout_multiplier: Option<f64>,
+
+    /// limit the address space of each test process, in MiB (Unix only).
+    #[arg(long, value_name = "MIB", help_heading = "Execution")]
+    limit_memory: Option<u64>,
+
+    /// limit the CPU time of each test process, in seconds (Unix only).
+    #[arg(long, value_name = "SECS", help_heading = "Execution")]
+    limit_cpu: Option<u64>,
+
+    /// limit the number of files each test process can have open (Unix only).
+    #[arg(long, value_name = "N", help_heading = "Execution")]
+    limit_files: Option<u64>,
+
+    /// run tests with no network access and a private /tmp (Linux only).
+    #[arg(long, help_heading = "Execution")]
+    sandbox: bool,
+
     /// print mutations that failed to check or build.
     #[arg(long, short = 'V', help_heading = "Output")]
     unviable: bool,
@@ -311,6 +417,26 @@ pub struct Args {
     )]
     cargo_arg: Vec<String>,
 
+    /// build and test with this rustup toolchain, such as `nightly`.
+    #[arg(long, value_name = "TOOLCHAIN", help_heading = "Execution")]
+    toolchain: Option<String>,
+
+    /// build for this target triple, such as `wasm32-wasi`, and run its tests with any runner set by `CARGO_TARGET_<TRIPLE>_RUNNER`.
+    #[arg(long, value_name = "TRIPLE", help_heading = "Execution")]
+    target: Option<String>,
+
+    /// set an environment variable for all cargo invocations, as NAME=VALUE.
+    #[arg(long, value_name = "NAME=VALUE", value_parser = parse_env_assignment, help_heading = "Execution")]
+    env: Vec<(String, String)>,
+
+    /// set an environment variable for cargo test only, as NAME=VALUE.
+    #[arg(long, value_name = "NAME=VALUE", value_parser = parse_env_assignment, help_heading = "Execution")]
+    test_env: Vec<(String, String)>,
+
+    /// remove an inherited environment variable from all cargo invocations.
+    #[arg(long, value_name = "NAME", help_heading = "Execution")]
+    unset_env: Vec<String>,
+
     // The following option captures all the remaining non-option args, to
     // send to cargo.
     /// pass remaining arguments to cargo test after all options and after `--`.
@@ -319,6 +445,27 @@ pub struct Args {
 
     #[command(flatten)]
     features: Features,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Subcommands of `cargo mutants` that do something other than testing mutants.
+#[derive(Subcommand, PartialEq, Debug)]
+enum Command {
+    /// Merge several `mutants.out` directories, such as those from different shards, into one.
+    Merge(MergeArgs),
+}
+
+#[derive(clap::Args, PartialEq, Debug)]
+pub struct MergeArgs {
+    /// `mutants.out` directories to merge, or directories containing them.
+    #[arg(required = true)]
+    dirs: Vec<Utf8PathBuf>,
+
+    /// create the merged mutants.out within this directory.
+    #[arg(long, short = 'o', default_value = ".")]
+    output: Utf8PathBuf,
 }
 
 #[derive(clap::Args, PartialEq, Eq, Debug, Default, Clone)]
@@ -368,6 +515,10 @@ fn main() -> Result<()> {
     console.set_colors_enabled(args.colors);
     interrupt::install_handler();
 
+    if let Some(Command::Merge(merge_args)) = &args.command {
+        exit(merge(&merge_args.dirs, &merge_args.output, &console)?);
+    }
+
     let start_dir: &Utf8Path = if let Some(manifest_path) = &args.manifest_path {
         ensure!(manifest_path.is_file(), "Manifest path is not a file");
         manifest_path
@@ -386,7 +537,9 @@ fn main() -> Result<()> {
     };
     debug!(?config);
     debug!(?args.features);
-    let options = Options::new(&args, &config)?;
+    let mut options = Options::new(&args, &config)?;
+    options.config_rustflags = config_rustflags(&workspace.dir, &options)?;
+    options.target_dir = Some(workspace.relative_target_dir());
     debug!(?options);
     let package_filter = if !args.mutate_packages.is_empty() {
         PackageFilter::explicit(&args.mutate_packages)
@@ -409,12 +562,38 @@ fn main() -> Result<()> {
         )?;
     }
     if let Some(shard) = &args.shard {
-        mutants = shard.select(mutants);
+        let strategy = args.shard_strategy.unwrap_or_default();
+        ensure!(
+            args.shard_costs.is_none() || strategy == ShardStrategy::Cost,
+            "--shard-costs can only be used with --shard-strategy=cost"
+        );
+        mutants = match strategy {
+            ShardStrategy::Index => shard.select(mutants),
+            ShardStrategy::Cost => {
+                let costs = estimate_costs(&mutants, args.shard_costs.as_deref())?;
+                shard.select_by_cost(mutants, &costs)
+            }
+            ShardStrategy::Hash => shard.select_by_hash(mutants),
+        };
     }
     if args.list {
         list_mutants(FmtToIoWrite::new(io::stdout()), &mutants, &options)?;
+    } else if let Some(address) = &args.serve {
+        let lab_outcome = serve(address, mutants, &workspace.dir, &options, &console)?;
+        exit(lab_outcome.exit_code());
     } else {
-        let lab_outcome = test_mutants(mutants, &workspace.dir, options, &console)?;
+        let coordinator = args
+            .worker
+            .as_ref()
+            .map(|address| WorkerClient::connect(address, &mutants))
+            .transpose()?;
+        let lab_outcome = test_mutants(
+            mutants,
+            &workspace.dir,
+            coordinator.as_ref(),
+            options,
+            &console,
+        )?;
         exit(lab_outcome.exit_code());
     }
     Ok(())
@@ -0,0 +1,345 @@
+// Copyright 2024 Martin Pool
+
+//! Merge several `mutants.out` directories, such as those from different shards, into one.
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use anyhow::{bail, ensure, Context};
+use serde::Serialize;
+use serde_json::Value;
+use tracing::info;
+
+use crate::console::{plural, Console};
+use crate::exit_code;
+use crate::log_file::LogFile;
+use crate::outcome::{useless_tests, TestsSummary};
+use crate::output::{OutputDir, OUTDIR_NAME};
+use crate::*;
+
+/// Lists of mutants by outcome, as text, that are concatenated from each input.
+static LIST_FILES: &[&str] = &[
+    "caught.txt",
+    "missed.txt",
+    "timeout.txt",
+    "unviable.txt",
+    "flaky.txt",
+    "build_timeout.txt",
+];
+
+/// The contents of one `mutants.out` directory to be merged.
+struct Input {
+    path: Utf8PathBuf,
+    version: String,
+    mutants: Vec<Value>,
+    lab_outcome: Value,
+}
+
+impl Input {
+    /// Read an input, given either a `mutants.out` directory or a directory containing one.
+    fn read(dir: &Utf8Path) -> Result<Input> {
+        let path = if dir.join("outcomes.json").is_file() {
+            dir.to_owned()
+        } else if dir.join(OUTDIR_NAME).join("outcomes.json").is_file() {
+            dir.join(OUTDIR_NAME)
+        } else {
+            bail!("{dir:?} does not contain outcomes.json or {OUTDIR_NAME}/outcomes.json");
+        };
+        let read_json = |name: &str| -> Result<Value> {
+            let json_path = path.join(name);
+            serde_json::from_str(
+                &fs::read_to_string(&json_path).with_context(|| format!("read {json_path}"))?,
+            )
+            .with_context(|| format!("parse {json_path}"))
+        };
+        let version = read_json("lock.json")?["cargo_mutants_version"]
+            .as_str()
+            .with_context(|| format!("no cargo_mutants_version in {path}/lock.json"))?
+            .to_owned();
+        let Value::Array(mutants) = read_json("mutants.json")? else {
+            bail!("{path}/mutants.json is not a list");
+        };
+        let lab_outcome = read_json("outcomes.json")?;
+        Ok(Input {
+            path,
+            version,
+            mutants,
+            lab_outcome,
+        })
+    }
+
+    fn outcomes(&self) -> &[Value] {
+        self.lab_outcome["outcomes"]
+            .as_array()
+            .map_or(&[], Vec::as_slice)
+    }
+
+    fn not_tested(&self) -> &[Value] {
+        self.lab_outcome["not_tested"]
+            .as_array()
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Read the tests that ran against every scenario in this input but never failed, or
+    /// None if the input didn't know which tests ran against any mutant, in which case
+    /// it says nothing about which tests are useless.
+    fn useless_tests(&self) -> Result<Option<Vec<String>>> {
+        let any_known = self.outcomes().iter().any(|outcome| {
+            outcome["scenario"].get("Mutant").is_some() && outcome.get("failing_tests").is_some()
+        });
+        let path = self.path.join("useless_tests.txt");
+        if !any_known || !path.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(
+            fs::read_to_string(&path)
+                .with_context(|| format!("read {path}"))?
+                .lines()
+                .map(ToOwned::to_owned)
+                .collect(),
+        ))
+    }
+}
+
+/// The merged equivalent of [LabOutcome](crate::outcome::LabOutcome), with the same
+/// serialized form.
+#[derive(Debug, Default, Serialize)]
+struct MergedOutcome {
+    outcomes: Vec<Value>,
+    total_mutants: usize,
+    missed: usize,
+    caught: usize,
+    timeout: usize,
+    unviable: usize,
+    success: usize,
+    failure: usize,
+    flaky: usize,
+    build_timeout: usize,
+    not_tested: Vec<Value>,
+    /// True if the baseline failed in any input.
+    #[serde(skip)]
+    baseline_failed: bool,
+}
+
+impl MergedOutcome {
+    fn add(&mut self, outcome: Value) {
+        let summary = outcome["summary"].as_str().unwrap_or_default();
+        if outcome["scenario"].get("Mutant").is_some() {
+            self.total_mutants += 1;
+            match summary {
+                "CaughtMutant" => self.caught += 1,
+                "MissedMutant" => self.missed += 1,
+                "Timeout" => self.timeout += 1,
+                "Unviable" => self.unviable += 1,
+                "Success" => self.success += 1,
+                "Flaky" => self.flaky += 1,
+                "BuildTimeout" => self.build_timeout += 1,
+                _ => self.failure += 1,
+            }
+        } else if summary != "Success" && summary != "Flaky" {
+            self.baseline_failed = true;
+        }
+        self.outcomes.push(outcome);
+    }
+
+    fn exit_code(&self) -> i32 {
+        exit_code::overall(
+            self.baseline_failed,
+            self.timeout + self.build_timeout,
+            self.missed + self.flaky,
+            self.not_tested.len(),
+        )
+    }
+
+    fn summary_string(&self) -> String {
+        let by_outcome = [
+            (self.missed, "missed"),
+            (self.caught, "caught"),
+            (self.unviable, "unviable"),
+            (self.timeout, "timeouts"),
+            (self.build_timeout, "build timeouts"),
+            (self.flaky, "flaky"),
+            (self.success, "succeeded"),
+            (self.failure, "failed"),
+            (self.not_tested.len(), "not tested"),
+        ]
+        .into_iter()
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, name)| format!("{n} {name}"))
+        .collect::<Vec<_>>();
+        format!(
+            "{} tested: {}",
+            plural(self.total_mutants, "mutant"),
+            by_outcome.join(", ")
+        )
+    }
+}
+
+/// Merge several `mutants.out` directories into a new `mutants.out` within `output_parent`,
+/// and return the exit code for the combined results.
+///
+/// The inputs must be from the same version of cargo-mutants, and must not have tested
+/// any of the same mutants, as would be the case for different shards of one run.
+pub fn merge(
+    input_dirs: &[Utf8PathBuf],
+    output_parent: &Utf8Path,
+    console: &Console,
+) -> Result<i32> {
+    let inputs = input_dirs
+        .iter()
+        .map(|dir| Input::read(dir))
+        .collect::<Result<Vec<_>>>()?;
+    check_inputs(&inputs)?;
+    let output_path = output_parent.join(OUTDIR_NAME);
+    if output_path.exists() {
+        // The existing output directory is moved aside when the new one is created.
+        let output_path = output_path.canonicalize_utf8()?;
+        for input in &inputs {
+            ensure!(
+                input.path.canonicalize_utf8()? != output_path,
+                "Can't merge into {output_path:?}, which is one of the inputs"
+            );
+        }
+    }
+
+    let output_dir = OutputDir::new(output_parent)?;
+    let log_dir = output_dir.path().join("log");
+    let mut merged = MergedOutcome::default();
+    let mut all_mutants = Vec::new();
+    let mut mutants_caught_by_test: HashMap<String, usize> = HashMap::new();
+    let mut shard_useless_tests = Vec::new();
+    for input in &inputs {
+        if let Some(shard_useless) = input.useless_tests()? {
+            shard_useless_tests.push((shard_useless, Vec::new()));
+        }
+        all_mutants.extend(input.mutants.iter().cloned());
+        for outcome in input.outcomes() {
+            let mut outcome = outcome.clone();
+            if let Some(log_path) = outcome["log_path"].as_str() {
+                let new_log_path = copy_log(&input.path, Utf8Path::new(log_path), &log_dir)?;
+                outcome["log_path"] = Value::String(new_log_path.into_string());
+            }
+            merged.add(outcome);
+        }
+        merged.not_tested.extend(input.not_tested().iter().cloned());
+        let tests_path = input.path.join("tests.json");
+        if tests_path.is_file() {
+            let tests_summary: TestsSummary = serde_json::from_str(
+                &fs::read_to_string(&tests_path).with_context(|| format!("read {tests_path}"))?,
+            )
+            .with_context(|| format!("parse {tests_path}"))?;
+            for test in tests_summary.tests {
+                *mutants_caught_by_test.entry(test.name).or_default() += test.mutants_caught;
+            }
+        }
+        for list_name in LIST_FILES {
+            let list_path = input.path.join(list_name);
+            if list_path.is_file() {
+                OpenOptions::new()
+                    .append(true)
+                    .open(output_dir.path().join(list_name))
+                    .and_then(|mut file| file.write_all(fs::read(&list_path)?.as_slice()))
+                    .with_context(|| format!("append {list_path} to merged {list_name}"))?;
+            }
+        }
+    }
+    write_json(&output_dir.path().join("mutants.json"), &all_mutants)?;
+    write_json(&output_dir.path().join("outcomes.json"), &merged)?;
+    write_json(
+        &output_dir.path().join("tests.json"),
+        &TestsSummary::from_counts(mutants_caught_by_test),
+    )?;
+    let merged_useless = useless_tests(shard_useless_tests);
+    fs::write(
+        output_dir.path().join("useless_tests.txt"),
+        merged_useless
+            .iter()
+            .map(|name| format!("{name}\n"))
+            .collect::<String>(),
+    )
+    .context("write merged useless_tests.txt")?;
+    info!(
+        "Merged {} output directories into {}",
+        inputs.len(),
+        output_dir.path()
+    );
+    console.message(&format!("{}\n", merged.summary_string()));
+    Ok(merged.exit_code())
+}
+
+/// Check that the inputs can be merged: they're from the same version and tested
+/// different mutants.
+fn check_inputs(inputs: &[Input]) -> Result<()> {
+    ensure!(!inputs.is_empty(), "No directories to merge");
+    for input in &inputs[1..] {
+        ensure!(
+            input.version == inputs[0].version,
+            "{} is from cargo-mutants {} but {} is from {}",
+            input.path,
+            input.version,
+            inputs[0].path,
+            inputs[0].version
+        );
+    }
+    let mut seen: HashMap<String, &Utf8Path> = HashMap::new();
+    for input in inputs {
+        for mutant in &input.mutants {
+            let key = mutant.to_string();
+            if let Some(other) = seen.insert(key, &input.path) {
+                bail!(
+                    "Mutant {mutant} is in both {other} and {}; were they run with the same --shard?",
+                    input.path
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Copy a log file from an input into the merged log directory, renaming it if there
+/// is already a log of the same name, and return its new path.
+fn copy_log(input_path: &Utf8Path, log_path: &Utf8Path, log_dir: &Utf8Path) -> Result<Utf8PathBuf> {
+    let file_name = log_path
+        .file_name()
+        .with_context(|| format!("log path {log_path:?} has no file name"))?;
+    let source = input_path.join("log").join(file_name);
+    let log_file = LogFile::create_in(log_dir, file_name.trim_end_matches(".log"))?;
+    fs::copy(&source, log_file.path())
+        .with_context(|| format!("copy {source} to {}", log_file.path()))?;
+    Ok(log_file.path().to_owned())
+}
+
+fn write_json<T: Serialize>(path: &Utf8Path, value: &T) -> Result<()> {
+    serde_json::to_writer_pretty(
+        fs::File::create(path).with_context(|| format!("create {path}"))?,
+        value,
+    )
+    .with_context(|| format!("write {path}"))
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn merged_outcome_counts_mutants_and_finds_baseline_failures() {
+        let mut merged = MergedOutcome::default();
+        merged.add(json!({"scenario": "Baseline", "summary": "Success"}));
+        merged.add(json!({"scenario": {"Mutant": {}}, "summary": "CaughtMutant"}));
+        merged.add(json!({"scenario": {"Mutant": {}}, "summary": "MissedMutant"}));
+        merged.add(json!({"scenario": "Baseline", "summary": "Success"}));
+        assert_eq!(merged.total_mutants, 2);
+        assert_eq!(
+            merged.summary_string(),
+            "2 mutants tested: 1 missed, 1 caught"
+        );
+        assert_eq!(merged.exit_code(), exit_code::FOUND_PROBLEMS);
+
+        merged.add(json!({"scenario": "Baseline", "summary": "Failure"}));
+        assert_eq!(merged.exit_code(), exit_code::CLEAN_TESTS_FAILED);
+    }
+}
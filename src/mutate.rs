@@ -2,12 +2,14 @@
 
 //! Mutations to source files, and inference of interesting mutations to apply.
 
+use std::collections::HashMap;
 use std::fmt;
 use std::fs;
 use std::sync::Arc;
 
 use anyhow::{ensure, Context, Result};
 use console::{style, StyledObject};
+use itertools::Itertools;
 use serde::ser::{SerializeStruct, Serializer};
 use serde::Serialize;
 use similar::TextDiff;
@@ -15,6 +17,7 @@ use tracing::error;
 use tracing::trace;
 
 use crate::build_dir::BuildDir;
+use crate::cfg::Cfg;
 use crate::package::Package;
 use crate::source::SourceFile;
 use crate::span::Span;
@@ -50,12 +53,16 @@ pub struct Mutant {
 
     /// What general category of mutant this is.
     pub genre: Genre,
+
+    /// The predicates of the `#[cfg(...)]` attributes on the items and modules containing
+    /// the mutant, which must all be true for its code to be compiled.
+    pub cfg: Vec<Cfg>,
 }
 
 /// The function containing a mutant.
 ///
 /// This is used for both mutations of the whole function, and smaller mutations within it.
-#[derive(Eq, PartialEq, Debug, Serialize)]
+#[derive(Clone, Eq, PartialEq, Debug, Serialize)]
 pub struct Function {
     /// The function that's being mutated.
     pub function_name: String,
@@ -67,6 +74,15 @@ pub struct Function {
 
     /// The span (line/column range) of the entire function.
     pub span: Span,
+
+    /// True if the function's doc comment has an example that runs as a doctest.
+    #[serde(skip)]
+    pub has_doc_example: bool,
+
+    /// True if the function's name is mentioned by test code in its package, so its
+    /// doc examples are probably not its only tests.
+    #[serde(skip)]
+    pub mentioned_in_tests: bool,
 }
 
 impl Mutant {
@@ -239,6 +255,21 @@ impl Serialize for Mutant {
     }
 }
 
+/// The total size of the source files containing mutants, in bytes, for each package.
+///
+/// This is a rough proxy for how long each package takes to build and test.
+pub fn package_sizes(mutants: &[Mutant]) -> HashMap<String, usize> {
+    let mut sizes: HashMap<String, usize> = HashMap::new();
+    for source_file in mutants
+        .iter()
+        .map(|m| &m.source_file)
+        .unique_by(|f| &f.tree_relative_path)
+    {
+        *sizes.entry(source_file.package.name.clone()).or_default() += source_file.code().len();
+    }
+    sizes
+}
+
 /// Manages the lifetime of a mutant being applied to a build directory; when
 /// dropped, the mutant is unapplied.
 #[must_use]
@@ -282,6 +313,8 @@ mod test {
                             function_name: "main",
                             return_type: "",
                             span: Span(1, 1, 5, 2),
+                            has_doc_example: false,
+                            mentioned_in_tests: false,
                         },
                     ),
                     replacement: "()",
@@ -304,6 +337,8 @@ mod test {
                             function_name: "factorial",
                             return_type: "-> u32",
                             span: Span(7, 1, 13, 2),
+                            has_doc_example: false,
+                            mentioned_in_tests: false,
                         },
                     ),
                     replacement: "0",
@@ -17,6 +17,8 @@ use tracing::warn;
 
 use crate::config::Config;
 use crate::glob::build_glob_set;
+use crate::limits::ResourceLimits;
+use crate::rustflags::absolute_config_args;
 use crate::*;
 
 /// Options for mutation testing, based on both command-line arguments and the
@@ -51,6 +53,31 @@ pub struct Options {
     /// The minimum test timeout, as a floor on the autoset value.
     pub minimum_test_timeout: Duration,
 
+    /// Test each package separately in the baseline, so that each mutant's timeout can be
+    /// scaled from the time to test its own package.
+    pub per_package_timeouts: bool,
+
+    /// The time limit for build and check tasks, if set.
+    ///
+    /// If this is not set, there's no limit on the baseline build, and then mutants
+    /// get a multiple of the time taken by the baseline build.
+    pub build_timeout: Option<Duration>,
+
+    /// The time multiplier for build tasks, if set (relative to baseline build duration).
+    pub build_timeout_multiplier: Option<f64>,
+
+    /// How many more times to run the tests, to detect flaky tests.
+    ///
+    /// The baseline tests are always rerun this many times; tests for a mutant are rerun
+    /// only while they fail.
+    pub retries: usize,
+
+    /// Resource limits applied to test processes.
+    pub limits: ResourceLimits,
+
+    /// Run tests in a sandbox with no network access and a private `/tmp`.
+    pub sandbox: bool,
+
     pub print_caught: bool,
     pub print_unviable: bool,
 
@@ -62,11 +89,17 @@ pub struct Options {
     /// List mutants with line and column numbers.
     pub show_line_col: bool,
 
-    /// Test mutants in random order.
+    /// Build several mutants from each file into one schema, selected at runtime.
+    pub schemata: bool,
+
+    /// Hard-link dependencies' build products between build dirs, rather than copying them.
+    pub share_deps: bool,
+
+    /// The order in which to test mutants.
     ///
-    /// This is now the default, so that repeated partial runs are more likely to find
+    /// Random order is the default, so that repeated partial runs are more likely to find
     /// interesting results.
-    pub shuffle: bool,
+    pub order: MutantOrder,
 
     /// Additional arguments for every cargo invocation.
     pub additional_cargo_args: Vec<String>,
@@ -74,9 +107,45 @@ pub struct Options {
     /// Additional arguments to `cargo test`.
     pub additional_cargo_test_args: Vec<String>,
 
+    /// Environment variables to set for every cargo invocation, from the config and then
+    /// the command line, so later values override earlier ones.
+    pub env: Vec<(String, String)>,
+
+    /// Environment variables to set only for `cargo test`, in the same order.
+    pub test_env: Vec<(String, String)>,
+
+    /// Inherited environment variables to remove from every cargo invocation.
+    pub unset_env: Vec<String>,
+
     /// Selection of features for cargo.
     pub features: super::Features,
 
+    /// Build and test with this rustup toolchain, by running `cargo +TOOLCHAIN`.
+    pub toolchain: Option<String>,
+
+    /// Build for this target triple, passed to cargo as `--target`.
+    ///
+    /// (Not to be confused with [Options::targets], the kinds of Cargo targets to mutate.)
+    pub target: Option<String>,
+
+    /// Flags for rustc from `build.rustflags` and `target.*.rustflags` in Cargo config
+    /// files, which are passed on in `CARGO_ENCODED_RUSTFLAGS`.
+    ///
+    /// These aren't options as such: they're read for the source tree once it's found.
+    pub config_rustflags: Vec<String>,
+
+    /// Cargo's target directory, relative to the root of the tree if it's inside it.
+    ///
+    /// Like [Options::config_rustflags], this is read for the source tree once it's found;
+    /// if it's not known, `target` is assumed.
+    pub target_dir: Option<Utf8PathBuf>,
+
+    /// Lists of features to build and test with in turn, each in addition to [Options::features].
+    ///
+    /// If this is empty, everything is built only with [Options::features].
+    /// Use [Options::feature_set_options] to read this.
+    pub feature_sets: Vec<Vec<String>>,
+
     /// Files to examine.
     pub examine_globset: Option<GlobSet>,
 
@@ -92,6 +161,9 @@ pub struct Options {
     /// Create `mutants.out` within this directory (by default, the source directory).
     pub output_in_dir: Option<Utf8PathBuf>,
 
+    /// Don't start testing any more mutants after this much time has elapsed.
+    pub max_runtime: Option<Duration>,
+
     /// Run this many `cargo build` or `cargo test` tasks in parallel.
     pub jobs: Option<usize>,
 
@@ -109,6 +181,29 @@ pub struct Options {
 
     /// The tool to use to run tests.
     pub test_tool: TestTool,
+
+    /// The argv template to run tests with [TestTool::Command].
+    ///
+    /// May contain `{build_dir}` and `{package}` placeholders.
+    pub test_command: Vec<String>,
+
+    /// Kinds of targets whose source is mutated, or empty for the defaults.
+    ///
+    /// Use [Options::target_kinds] to read this.
+    pub targets: Vec<TargetKind>,
+
+    /// Also mutate code under `cfg` attributes that are known to be false for the target
+    /// and the selected features.
+    pub include_inactive: bool,
+
+    /// Whether to run doctests.
+    ///
+    /// If unset, doctests run if the test tool runs them by default: `cargo test` does and
+    /// nextest does not. If false, `cargo test` is told to run only the lib, bin, and test
+    /// targets, but a mutant in a function with a doc example that isn't caught by the
+    /// other tests is tested by that function's doctests. If true, doctests are also run
+    /// after nextest.
+    pub doctests: Option<bool>,
 }
 
 /// Choice of tool to use to run tests.
@@ -122,6 +217,57 @@ pub enum TestTool {
 
     /// Use `cargo nextest`.
     Nextest,
+
+    /// Run the command given by the `test_command` template.
+    Command,
+}
+
+/// A kind of Cargo target whose source files can be mutated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum TargetKind {
+    /// Libraries, of any crate type.
+    Lib,
+    /// Binaries.
+    Bin,
+    /// Examples.
+    Example,
+    /// Benchmarks.
+    Bench,
+    /// Build scripts, `build.rs`.
+    CustomBuild,
+}
+
+impl TargetKind {
+    /// The kinds of target that are mutated unless the options say otherwise.
+    pub const DEFAULT: &'static [TargetKind] = &[TargetKind::Lib, TargetKind::Bin];
+
+    /// True if this matches one of the kinds of a target from `cargo metadata`.
+    pub fn matches(&self, metadata_kind: &str) -> bool {
+        match self {
+            // Includes `rlib`, `cdylib`, etc.
+            TargetKind::Lib => metadata_kind.ends_with("lib"),
+            _ => metadata_kind == self.to_string(),
+        }
+    }
+}
+
+/// The order in which to test mutants.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, EnumString, Display, Deserialize)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum MutantOrder {
+    /// Interleave mutants from different functions, files, and packages, so that
+    /// an interrupted run has tested a bit of everything.
+    Smart,
+
+    /// Random order, the default.
+    #[default]
+    Random,
+
+    /// The order the mutants occur in the source tree.
+    Source,
 }
 
 /// Join two slices into a new vector.
@@ -183,8 +329,15 @@ impl Options {
                 .unwrap_or(20f64),
         );
 
+        let cwd: Utf8PathBuf = std::env::current_dir()
+            .context("get current directory")?
+            .try_into()
+            .context("current directory path to UTF-8")?;
         let options = Options {
-            additional_cargo_args: join_slices(&args.cargo_arg, &config.additional_cargo_args),
+            additional_cargo_args: absolute_config_args(
+                join_slices(&args.cargo_arg, &config.additional_cargo_args),
+                &cwd,
+            ),
             additional_cargo_test_args: join_slices(
                 &args.cargo_test_args,
                 &config.additional_cargo_test_args,
@@ -192,8 +345,18 @@ impl Options {
             baseline: args.baseline,
             check_only: args.check,
             colors: args.colors,
+            // Filled in from the source tree when testing starts.
+            config_rustflags: Vec::new(),
+            target_dir: None,
+            doctests: args.doctests.or(config.doctests),
             emit_json: args.json,
             emit_diffs: args.diff,
+            env: config
+                .env
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .chain(args.env.iter().cloned())
+                .collect(),
             error_values: join_slices(&args.error, &config.error_values),
             examine_names: RegexSet::new(or_slices(&args.examine_re, &config.examine_re))
                 .context("Failed to compile examine_re regex")?,
@@ -202,22 +365,80 @@ impl Options {
             examine_globset: build_glob_set(or_slices(&args.file, &config.examine_globs))?,
             exclude_globset: build_glob_set(or_slices(&args.exclude, &config.exclude_globs))?,
             features: args.features.clone(),
+            feature_sets: config.feature_sets.clone(),
             gitignore: args.gitignore,
             in_place: args.in_place,
+            include_inactive: args.include_inactive || config.include_inactive,
             jobs: args.jobs,
             leak_dirs: args.leak_dirs,
+            limits: ResourceLimits {
+                memory: args
+                    .limit_memory
+                    .or(config.limit_memory)
+                    .map(|mib| mib * 1024 * 1024),
+                cpu_secs: args.limit_cpu.or(config.limit_cpu),
+                files: args.limit_files.or(config.limit_files),
+            },
+            max_runtime: args.max_runtime.map(Duration::from_secs_f64),
             minimum_test_timeout,
+            per_package_timeouts: args.per_package_timeouts || config.per_package_timeouts,
             output_in_dir: args.output.clone(),
             print_caught: args.caught,
             print_unviable: args.unviable,
-            shuffle: !args.no_shuffle,
+            retries: args.retries.or(config.retries).unwrap_or(0),
+            sandbox: args.sandbox || config.sandbox,
+            schemata: args.schemata,
+            share_deps: args.share_deps,
+            order: args.order.unwrap_or(if args.no_shuffle {
+                MutantOrder::Source
+            } else {
+                MutantOrder::Random
+            }),
             show_line_col: args.line_col,
             show_times: !args.no_times,
             show_all_logs: args.all_logs,
+            targets: or_slices(&args.targets, &config.targets).to_vec(),
+            test_env: config
+                .test_env
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .chain(args.test_env.iter().cloned())
+                .collect(),
             test_timeout: args.timeout.map(Duration::from_secs_f64),
+            target: args.target.clone().or(config.target.clone()),
+            toolchain: args.toolchain.clone().or(config.toolchain.clone()),
+            // A multiplier on the command line overrides a fixed timeout in the config.
+            build_timeout: args
+                .build_timeout
+                .or(config
+                    .build_timeout
+                    .filter(|_| args.build_timeout_multiplier.is_none()))
+                .map(Duration::from_secs_f64),
+            build_timeout_multiplier: args
+                .build_timeout_multiplier
+                .or(config.build_timeout_multiplier),
             test_timeout_multiplier: config.timeout_multiplier.or(args.timeout_multiplier),
-            test_tool: args.test_tool.or(config.test_tool).unwrap_or_default(),
+            test_command: config.test_command.clone(),
+            // Giving a test command implies using it, unless some other tool is chosen.
+            test_tool: args.test_tool.or(config.test_tool).unwrap_or(
+                if config.test_command.is_empty() {
+                    TestTool::Cargo
+                } else {
+                    TestTool::Command
+                },
+            ),
+            unset_env: join_slices(&args.unset_env, &config.unset_env),
         };
+        anyhow::ensure!(
+            options.test_tool != TestTool::Command || !options.test_command.is_empty(),
+            "test_tool = \"command\" needs test_command to be set in the config file"
+        );
+        if options.doctests.is_some() && options.test_tool == TestTool::Command {
+            warn!("doctests has no effect with test_command");
+        }
+        if cfg!(not(unix)) && !options.limits.is_empty() {
+            warn!("Resource limits are only supported on Unix, and will be ignored");
+        }
         options.error_values.iter().for_each(|e| {
             if e.starts_with("Err(") {
                 warn!(
@@ -229,6 +450,51 @@ impl Options {
         Ok(options)
     }
 
+    /// Return the kinds of targets whose source should be mutated.
+    pub fn target_kinds(&self) -> &[TargetKind] {
+        if self.targets.is_empty() {
+            TargetKind::DEFAULT
+        } else {
+            &self.targets
+        }
+    }
+
+    /// True if features might also be selected by the additional cargo arguments, so
+    /// [Options::features] doesn't tell which are active.
+    pub fn cargo_args_select_features(&self) -> bool {
+        self.additional_cargo_args
+            .iter()
+            .chain(&self.additional_cargo_test_args)
+            .any(|arg| {
+                [
+                    "-F",
+                    "--features",
+                    "--all-features",
+                    "--no-default-features",
+                ]
+                .iter()
+                .any(|flag| arg.starts_with(flag))
+            })
+    }
+
+    /// Return options for building and testing with each of the feature sets in turn,
+    /// or just a copy of these options if there are no feature sets.
+    pub fn feature_set_options(&self) -> Vec<Options> {
+        if self.feature_sets.is_empty() {
+            return vec![self.clone()];
+        }
+        self.feature_sets
+            .iter()
+            .map(|feature_set| {
+                let mut options = self.clone();
+                if !feature_set.is_empty() {
+                    options.features.features.push(feature_set.join(","));
+                }
+                options
+            })
+            .collect()
+    }
+
     #[cfg(test)]
     pub fn from_args(args: &Args) -> Result<Options> {
         Options::new(args, &Config::default())
@@ -249,6 +515,7 @@ mod test {
     use std::io::Write;
 
     use indoc::indoc;
+    use itertools::Itertools;
     use tempfile::NamedTempFile;
 
     use super::*;
@@ -283,6 +550,25 @@ mod test {
         assert_eq!(options.baseline, BaselineStrategy::Run);
     }
 
+    #[test]
+    fn per_package_timeouts_from_arg_or_config() {
+        let args = Args::parse_from(["mutants"]);
+        let options = Options::new(&args, &Config::default()).unwrap();
+        assert!(!options.per_package_timeouts);
+
+        let args = Args::parse_from(["mutants", "--per-package-timeouts"]);
+        let options = Options::new(&args, &Config::default()).unwrap();
+        assert!(options.per_package_timeouts);
+
+        let config = Config {
+            per_package_timeouts: true,
+            ..Default::default()
+        };
+        let args = Args::parse_from(["mutants"]);
+        let options = Options::new(&args, &config).unwrap();
+        assert!(options.per_package_timeouts);
+    }
+
     #[test]
     fn test_tool_from_config() {
         let config = indoc! { r#"
@@ -296,6 +582,95 @@ mod test {
         assert_eq!(options.test_tool, TestTool::Nextest);
     }
 
+    #[test]
+    fn doctests_from_config_is_overridden_by_arg() {
+        let config = Config {
+            doctests: Some(false),
+            ..Default::default()
+        };
+        let args = Args::parse_from(["mutants"]);
+        let options = Options::new(&args, &config).unwrap();
+        assert_eq!(options.doctests, Some(false));
+
+        let args = Args::parse_from(["mutants", "--doctests=true"]);
+        let options = Options::new(&args, &config).unwrap();
+        assert_eq!(options.doctests, Some(true));
+
+        let args = Args::parse_from(["mutants"]);
+        let options = Options::new(&args, &Config::default()).unwrap();
+        assert_eq!(options.doctests, None);
+    }
+
+    #[test]
+    fn each_feature_set_adds_to_the_selected_features() {
+        let config: Config =
+            toml::from_str(r#"feature_sets = [[], ["fast"], ["fast", "small"]]"#).unwrap();
+        let args = Args::parse_from(["mutants", "--features=base", "--no-default-features"]);
+        let options = Options::new(&args, &config).unwrap();
+        let feature_set_options = options.feature_set_options();
+        assert_eq!(
+            feature_set_options
+                .iter()
+                .map(|options| options.features.features.clone())
+                .collect_vec(),
+            [
+                vec!["base"],
+                vec!["base", "fast"],
+                vec!["base", "fast,small"]
+            ]
+        );
+        assert!(feature_set_options
+            .iter()
+            .all(|options| options.features.no_default_features));
+
+        let options = Options::new(&args, &Config::default()).unwrap();
+        assert_eq!(options.feature_set_options().len(), 1);
+    }
+
+    #[test]
+    fn targets_from_arg_override_config() {
+        let config: Config = toml::from_str(r#"targets = ["lib", "custom-build"]"#).unwrap();
+        let args = Args::parse_from(["mutants"]);
+        let options = Options::new(&args, &config).unwrap();
+        assert_eq!(options.targets, [TargetKind::Lib, TargetKind::CustomBuild]);
+
+        let args = Args::parse_from(["mutants", "--targets=bin,example,bench"]);
+        let options = Options::new(&args, &config).unwrap();
+        assert_eq!(
+            options.targets,
+            [TargetKind::Bin, TargetKind::Example, TargetKind::Bench]
+        );
+    }
+
+    #[test]
+    fn target_kinds_match_cargo_metadata() {
+        assert!(TargetKind::Lib.matches("lib"));
+        assert!(TargetKind::Lib.matches("cdylib"));
+        assert!(!TargetKind::Lib.matches("proc-macro"));
+        assert!(TargetKind::CustomBuild.matches("custom-build"));
+        assert!(TargetKind::Example.matches("example"));
+        assert!(!TargetKind::Bin.matches("example"));
+    }
+
+    #[test]
+    fn build_timeout_from_config_is_overridden_by_multiplier_arg() {
+        let config = indoc! { r#"
+            build_timeout = 90
+        "#};
+        let mut config_file = NamedTempFile::new().unwrap();
+        config_file.write_all(config.as_bytes()).unwrap();
+        let config = Config::read_file(config_file.path()).unwrap();
+
+        let args = Args::parse_from(["mutants"]);
+        let options = Options::new(&args, &config).unwrap();
+        assert_eq!(options.build_timeout, Some(Duration::from_secs(90)));
+
+        let args = Args::parse_from(["mutants", "--build-timeout-multiplier", "3"]);
+        let options = Options::new(&args, &config).unwrap();
+        assert_eq!(options.build_timeout, None);
+        assert_eq!(options.build_timeout_multiplier, Some(3.0));
+    }
+
     #[test]
     fn features_arg() {
         let args = Args::try_parse_from(["mutants", "--features", "nice,shiny features"]).unwrap();
@@ -352,4 +727,49 @@ mod test {
         assert!(!options.features.no_default_features);
         assert!(options.features.all_features);
     }
+
+    #[test]
+    fn env_from_config_and_args() {
+        let config = indoc! { r#"
+            unset_env = ["SECRET"]
+
+            [env]
+            RUST_LOG = "info"
+
+            [test_env]
+            PROPTEST_CASES = "16"
+        "#};
+        let mut config_file = NamedTempFile::new().unwrap();
+        config_file.write_all(config.as_bytes()).unwrap();
+        let config = Config::read_file(config_file.path()).unwrap();
+
+        let args = Args::parse_from([
+            "mutants",
+            "--env=RUST_LOG=debug",
+            "--test-env",
+            "DATABASE_URL=postgres://localhost/x?a=b",
+            "--unset-env=TOKEN",
+        ]);
+        let options = Options::new(&args, &config).unwrap();
+        let pairs = |v: &[(&str, &str)]| {
+            v.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            options.env,
+            pairs(&[("RUST_LOG", "info"), ("RUST_LOG", "debug")])
+        );
+        assert_eq!(
+            options.test_env,
+            pairs(&[
+                ("PROPTEST_CASES", "16"),
+                ("DATABASE_URL", "postgres://localhost/x?a=b")
+            ])
+        );
+        assert_eq!(options.unset_env, ["TOKEN", "SECRET"]);
+
+        assert!(Args::try_parse_from(["mutants", "--env=NOVALUE"]).is_err());
+        assert!(Args::try_parse_from(["mutants", "--env==x"]).is_err());
+    }
 }
@@ -2,6 +2,8 @@
 
 //! The outcome of running a single mutation scenario, or a whole lab.
 
+use std::cmp::max;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt;
 use std::fs;
 use std::time::Duration;
@@ -9,9 +11,10 @@ use std::time::Instant;
 
 use anyhow::Context;
 use humantime::format_duration;
+use itertools::Itertools;
 use serde::ser::SerializeStruct;
-use serde::Serialize;
 use serde::Serializer;
+use serde::{Deserialize, Serialize};
 
 use crate::console::plural;
 use crate::exit_code;
@@ -30,7 +33,7 @@ use crate::*;
 /// 3. `cargo tests` -- do the tests pass?
 ///
 /// Some scenarios such as freshening the tree don't run the tests.
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum Phase {
     Check,
     Build,
@@ -65,6 +68,12 @@ pub struct LabOutcome {
     pub unviable: usize,
     pub success: usize,
     pub failure: usize,
+    /// Mutants whose build or check timed out.
+    pub build_timeout: usize,
+    /// Mutants whose tests both passed and failed when retried.
+    pub flaky: usize,
+    /// Mutants that were not tested because the run reached its time limit.
+    pub not_tested: Vec<Mutant>,
 }
 
 impl LabOutcome {
@@ -83,6 +92,8 @@ impl LabOutcome {
                 SummaryOutcome::Unviable => self.unviable += 1,
                 SummaryOutcome::Success => self.success += 1,
                 SummaryOutcome::Failure => self.failure += 1,
+                SummaryOutcome::Flaky => self.flaky += 1,
+                SummaryOutcome::BuildTimeout => self.build_timeout += 1,
             }
         }
         self.outcomes.push(outcome);
@@ -91,19 +102,14 @@ impl LabOutcome {
     /// Return the overall program exit code reflecting this outcome.
     pub fn exit_code(&self) -> i32 {
         // TODO: Maybe move this into an error returned from experiment()?
-        if self
-            .outcomes
-            .iter()
-            .any(|o| !o.scenario.is_mutant() && !o.success())
-        {
-            exit_code::CLEAN_TESTS_FAILED
-        } else if self.timeout > 0 {
-            exit_code::TIMEOUT
-        } else if self.missed > 0 {
-            exit_code::FOUND_PROBLEMS
-        } else {
-            exit_code::SUCCESS
-        }
+        exit_code::overall(
+            self.outcomes
+                .iter()
+                .any(|o| !o.scenario.is_mutant() && !o.success() && !o.is_flaky()),
+            self.timeout + self.build_timeout,
+            self.missed + self.flaky,
+            self.not_tested.len(),
+        )
     }
 
     /// Return an overall summary, to show at the end of the program.
@@ -130,15 +136,115 @@ impl LabOutcome {
         if self.timeout > 0 {
             by_outcome.push(format!("{} timeouts", self.timeout));
         }
+        if self.build_timeout > 0 {
+            by_outcome.push(format!("{} build timeouts", self.build_timeout));
+        }
+        if self.flaky > 0 {
+            by_outcome.push(format!("{} flaky", self.flaky));
+        }
         if self.success > 0 {
             by_outcome.push(format!("{} succeeded", self.success));
         }
         if self.failure > 0 {
             by_outcome.push(format!("{} failed", self.failure));
         }
+        if !self.not_tested.is_empty() {
+            by_outcome.push(format!("{} not tested", self.not_tested.len()));
+        }
         s.push(by_outcome.join(", "));
         s.join("")
     }
+
+    /// Summarize how many mutants each test caught, for `tests.json`.
+    pub fn tests_summary(&self) -> TestsSummary {
+        let mut caught: HashMap<String, usize> = HashMap::new();
+        for outcome in &self.outcomes {
+            if outcome.summary() == SummaryOutcome::CaughtMutant {
+                for name in outcome.failing_tests().unwrap_or_default() {
+                    *caught.entry(name).or_default() += 1;
+                }
+            }
+        }
+        TestsSummary::from_counts(caught)
+    }
+
+    /// Return the names of the tests that ran in every scenario whose test results are known,
+    /// but never failed, sorted.
+    ///
+    /// These tests might have no assertions, or might only check things that no mutant
+    /// changes.
+    pub fn useless_tests(&self) -> Vec<String> {
+        if !self
+            .outcomes
+            .iter()
+            .any(|outcome| outcome.scenario.is_mutant() && outcome.tests_run().is_some())
+        {
+            return Vec::new();
+        }
+        useless_tests(self.outcomes.iter().filter_map(|outcome| {
+            Some((
+                outcome.tests_run()?,
+                outcome.failing_tests().unwrap_or_default(),
+            ))
+        }))
+    }
+}
+
+/// Given the names of the tests that ran, and of those that failed, for each of several
+/// scenarios or runs, return the tests that ran in all of them but never failed, sorted.
+///
+/// A list of tests that were already found to be useless in one shard of a run can
+/// stand in for all the scenarios of that shard.
+pub fn useless_tests(results: impl IntoIterator<Item = (Vec<String>, Vec<String>)>) -> Vec<String> {
+    let mut ran_everywhere: Option<BTreeSet<String>> = None;
+    let mut ever_failed: HashSet<String> = HashSet::new();
+    for (tests_run, failing_tests) in results {
+        ever_failed.extend(failing_tests);
+        ran_everywhere = Some(match ran_everywhere {
+            None => tests_run.into_iter().collect(),
+            Some(names) => tests_run
+                .into_iter()
+                .filter(|name| names.contains(name))
+                .collect(),
+        });
+    }
+    ran_everywhere
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|name| !ever_failed.contains(name))
+        .collect()
+}
+
+/// How many mutants were caught by each test, as written to `tests.json`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestsSummary {
+    /// Tests that caught at least one mutant, sorted by name.
+    pub tests: Vec<TestSummary>,
+}
+
+impl TestsSummary {
+    /// Make a summary from the number of mutants caught by each test.
+    pub fn from_counts(mutants_caught: HashMap<String, usize>) -> TestsSummary {
+        TestsSummary {
+            tests: mutants_caught
+                .into_iter()
+                .sorted()
+                .map(|(name, mutants_caught)| TestSummary {
+                    name,
+                    mutants_caught,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The results from one test, across all the mutants.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestSummary {
+    /// The name of the test, as reported by the test tool.
+    pub name: String,
+    /// The number of mutants this test caught, by failing.
+    pub mutants_caught: usize,
 }
 
 /// The result of running one mutation scenario.
@@ -160,10 +266,15 @@ impl Serialize for ScenarioOutcome {
         S: Serializer,
     {
         // custom serialize to omit inessential info
-        let mut ss = serializer.serialize_struct("Outcome", 4)?;
+        let mut ss = serializer.serialize_struct("Outcome", 5)?;
         ss.serialize_field("scenario", &self.scenario)?;
         ss.serialize_field("log_path", &self.log_path)?;
         ss.serialize_field("summary", &self.summary())?;
+        if let Some(failing_tests) = self.failing_tests() {
+            ss.serialize_field("failing_tests", &failing_tests)?;
+        } else {
+            ss.skip_field("failing_tests")?;
+        }
         ss.serialize_field("phase_results", &self.phase_results)?;
         ss.end()
     }
@@ -198,17 +309,67 @@ impl ScenarioOutcome {
         &self.phase_results
     }
 
-    /// Return the total time spent in commands for one phase.
+    /// Return the names of all the tests that failed in any test phase, sorted, or None
+    /// if that's not known for any of them.
+    pub fn failing_tests(&self) -> Option<Vec<String>> {
+        let mut known = false;
+        let mut failing = Vec::new();
+        for pr in &self.phase_results {
+            if let Some(names) = &pr.failing_tests {
+                known = true;
+                failing.extend(names.iter().cloned());
+            }
+        }
+        known.then(|| failing.into_iter().sorted().dedup().collect())
+    }
+
+    /// Return the names of all the tests that ran, whether they passed or failed, or None
+    /// if that's not known for any test phase.
+    pub fn tests_run(&self) -> Option<Vec<String>> {
+        let mut known = false;
+        let mut tests_run = Vec::new();
+        for pr in &self.phase_results {
+            if let (Some(failing), Some(passing)) = (&pr.failing_tests, &pr.passing_tests) {
+                known = true;
+                tests_run.extend(failing.iter().chain(passing).cloned());
+            }
+        }
+        known.then(|| tests_run.into_iter().sorted().dedup().collect())
+    }
+
+    /// Return the time spent in one phase, counting only the slowest attempt if some
+    /// commands were retried.
     ///
     /// If the phase was not run, returns zero.
-    pub fn total_phase_duration(&self, phase: Phase) -> Duration {
+    pub fn phase_duration(&self, phase: Phase) -> Duration {
         self.phase_results
             .iter()
             .filter(|pr| pr.phase == phase)
-            .map(|pr| pr.duration)
+            .into_group_map_by(|pr| (&pr.packages, &pr.argv))
+            .values()
+            .map(|attempts| {
+                attempts
+                    .iter()
+                    .map(|pr| pr.duration)
+                    .max()
+                    .unwrap_or_default()
+            })
             .sum()
     }
 
+    /// Return the time spent in one phase for each package that was run separately,
+    /// counting only the slowest attempt if they were retried.
+    pub fn package_phase_durations(&self, phase: Phase) -> HashMap<String, Duration> {
+        let mut durations = HashMap::new();
+        for pr in self.phase_results.iter().filter(|pr| pr.phase == phase) {
+            if let [package] = pr.packages.as_slice() {
+                let duration: &mut Duration = durations.entry(package.clone()).or_default();
+                *duration = max(*duration, pr.duration);
+            }
+        }
+        durations
+    }
+
     /// True if this status indicates the user definitely needs to see the logs, because a task
     /// failed that should not have failed.
     pub fn should_show_logs(&self) -> bool {
@@ -225,31 +386,69 @@ impl ScenarioOutcome {
             .any(|pr| pr.process_status.is_timeout())
     }
 
-    pub fn check_or_build_failed(&self) -> bool {
+    /// True if a check or build command timed out.
+    pub fn build_timed_out(&self) -> bool {
         self.phase_results
             .iter()
-            .any(|pr| pr.phase != Phase::Test && pr.process_status.is_failure())
+            .any(|pr| pr.phase != Phase::Test && pr.process_status.is_timeout())
+    }
+
+    /// True if the same test command was run more than once, and both passed and failed.
+    pub fn is_flaky(&self) -> bool {
+        self.phase_results
+            .iter()
+            .filter(|pr| pr.phase == Phase::Test && !pr.process_status.is_timeout())
+            .into_group_map_by(|pr| (&pr.packages, &pr.argv))
+            .values()
+            .any(|attempts| {
+                attempts.iter().any(|pr| pr.is_success())
+                    && attempts.iter().any(|pr| pr.process_status.is_failure())
+            })
+    }
+
+    /// True if a check or build failed, and none succeeded.
+    ///
+    /// A mutant that builds under any of the feature sets is tested with those, so it's not
+    /// unviable.
+    pub fn check_or_build_failed(&self) -> bool {
+        let mut builds = self
+            .phase_results
+            .iter()
+            .filter(|pr| pr.phase != Phase::Test);
+        builds.clone().any(|pr| pr.process_status.is_failure()) && !builds.any(|pr| pr.is_success())
     }
 
     /// True if this outcome is a caught mutant: it's a mutant and the tests failed.
     pub fn mutant_caught(&self) -> bool {
         self.scenario.is_mutant()
-            && self.last_phase() == Phase::Test
-            && self.last_phase_result().is_failure()
+            && self
+                .last_test_result()
+                .is_some_and(|pr| pr.process_status.is_failure())
     }
 
     /// True if this outcome is a missed mutant: it's a mutant and the tests succeeded.
     pub fn mutant_missed(&self) -> bool {
-        self.scenario.is_mutant()
-            && self.last_phase() == Phase::Test
-            && self.last_phase_result().is_success()
+        self.scenario.is_mutant() && self.last_test_result().is_some_and(|pr| pr.is_success())
+    }
+
+    /// The result of the last test phase, which might be followed by builds under other
+    /// feature sets that failed.
+    fn last_test_result(&self) -> Option<&PhaseResult> {
+        self.phase_results
+            .iter()
+            .rev()
+            .find(|pr| pr.phase == Phase::Test)
     }
 
     pub fn summary(&self) -> SummaryOutcome {
         match self.scenario {
-            Scenario::Baseline => {
-                if self.has_timeout() {
+            Scenario::Baseline | Scenario::Schema(_) => {
+                if self.build_timed_out() {
+                    SummaryOutcome::BuildTimeout
+                } else if self.has_timeout() {
                     SummaryOutcome::Timeout
+                } else if self.is_flaky() {
+                    SummaryOutcome::Flaky
                 } else if self.success() {
                     SummaryOutcome::Success
                 } else {
@@ -259,13 +458,20 @@ impl ScenarioOutcome {
             Scenario::Mutant(_) => {
                 if self.check_or_build_failed() {
                     SummaryOutcome::Unviable
+                } else if self.build_timed_out() {
+                    SummaryOutcome::BuildTimeout
                 } else if self.has_timeout() {
                     SummaryOutcome::Timeout
+                } else if self.is_flaky() {
+                    SummaryOutcome::Flaky
                 } else if self.mutant_caught() {
                     SummaryOutcome::CaughtMutant
                 } else if self.mutant_missed() {
                     SummaryOutcome::MissedMutant
-                } else if self.success() {
+                } else if self.last_test_result().is_none()
+                    && self.phase_results.iter().any(|pr| pr.is_success())
+                {
+                    // Checked, or built, under some feature set.
                     SummaryOutcome::Success
                 } else {
                     SummaryOutcome::Failure
@@ -286,6 +492,15 @@ pub struct PhaseResult {
     pub process_status: ProcessStatus,
     /// What command was run, as an argv list.
     pub argv: Vec<String>,
+    /// The names of the packages that were built or tested, or empty for the whole workspace.
+    pub packages: Vec<String>,
+    /// The names of the tests that failed, if known.
+    pub failing_tests: Option<Vec<String>>,
+    /// The names of the tests that passed, if known.
+    ///
+    /// This can be a long list, and it's only used to find tests that never fail, so it's
+    /// not written to `outcomes.json`.
+    pub passing_tests: Option<Vec<String>>,
 }
 
 impl PhaseResult {
@@ -299,11 +514,17 @@ impl Serialize for PhaseResult {
     where
         S: Serializer,
     {
-        let mut ss = serializer.serialize_struct("PhaseResult", 4)?;
+        let mut ss = serializer.serialize_struct("PhaseResult", 6)?;
         ss.serialize_field("phase", &self.phase)?;
         ss.serialize_field("duration", &self.duration.as_secs_f64())?;
         ss.serialize_field("process_status", &self.process_status)?;
         ss.serialize_field("argv", &self.argv)?;
+        ss.serialize_field("packages", &self.packages)?;
+        if let Some(failing_tests) = &self.failing_tests {
+            ss.serialize_field("failing_tests", failing_tests)?;
+        } else {
+            ss.skip_field("failing_tests")?;
+        }
         ss.end()
     }
 }
@@ -317,4 +538,8 @@ pub enum SummaryOutcome {
     Unviable,
     Failure,
     Timeout,
+    /// The tests both passed and failed when they were retried.
+    Flaky,
+    /// The check or build timed out, before any tests were run.
+    BuildTimeout,
 }
@@ -20,7 +20,7 @@ use tracing::info;
 use crate::outcome::{LabOutcome, SummaryOutcome};
 use crate::*;
 
-const OUTDIR_NAME: &str = "mutants.out";
+pub const OUTDIR_NAME: &str = "mutants.out";
 const ROTATED_NAME: &str = "mutants.out.old";
 const LOCK_JSON: &str = "lock.json";
 const LOCK_POLL: Duration = Duration::from_millis(100);
@@ -99,6 +99,10 @@ pub struct OutputDir {
     /// A file holding a list of mutants where testing timed out, as text, one per line.
     timeout_list: File,
     unviable_list: File,
+    /// A file holding a list of mutants whose tests were flaky, as text, one per line.
+    flaky_list: File,
+    /// A file holding a list of mutants where the build timed out, as text, one per line.
+    build_timeout_list: File,
     /// The accumulated overall lab outcome.
     pub lab_outcome: LabOutcome,
 }
@@ -153,6 +157,12 @@ impl OutputDir {
         let timeout_list = list_file_options
             .open(output_dir.join("timeout.txt"))
             .context("create timeout.txt")?;
+        let flaky_list = list_file_options
+            .open(output_dir.join("flaky.txt"))
+            .context("create flaky.txt")?;
+        let build_timeout_list = list_file_options
+            .open(output_dir.join("build_timeout.txt"))
+            .context("create build_timeout.txt")?;
         Ok(OutputDir {
             path: output_dir,
             lab_outcome: LabOutcome::new(),
@@ -162,6 +172,8 @@ impl OutputDir {
             caught_list,
             timeout_list,
             unviable_list,
+            flaky_list,
+            build_timeout_list,
         })
     }
 
@@ -173,6 +185,12 @@ impl OutputDir {
         LogFile::create_in(&self.log_dir, &scenario.log_file_name_base())
     }
 
+    /// Create a log file for something other than a scenario, such as a check run
+    /// alongside the baseline.
+    pub fn create_named_log(&self, name: &str) -> Result<LogFile> {
+        LogFile::create_in(&self.log_dir, name)
+    }
+
     #[allow(dead_code)]
     /// Return the path of the `mutants.out` directory.
     pub fn path(&self) -> &Utf8Path {
@@ -190,10 +208,32 @@ impl OutputDir {
         .context("write outcomes.json")
     }
 
+    /// Write the summary of which tests caught mutants.
+    fn write_tests_summary(&self) -> Result<()> {
+        serde_json::to_writer_pretty(
+            BufWriter::new(File::create(self.path.join("tests.json"))?),
+            &self.lab_outcome.tests_summary(),
+        )
+        .context("write tests.json")
+    }
+
+    /// Write the list of tests that never caught a mutant, one per line.
+    ///
+    /// Called once, when all the scenarios have been tested.
+    pub fn write_useless_tests(&self) -> Result<()> {
+        let mut content = String::new();
+        for name in self.lab_outcome.useless_tests() {
+            content.push_str(&name);
+            content.push('\n');
+        }
+        fs::write(self.path.join("useless_tests.txt"), content).context("write useless_tests.txt")
+    }
+
     /// Add the result of testing one scenario.
     pub fn add_scenario_outcome(&mut self, scenario_outcome: &ScenarioOutcome) -> Result<()> {
         self.lab_outcome.add(scenario_outcome.to_owned());
         self.write_lab_outcome()?;
+        self.write_tests_summary()?;
         let scenario = &scenario_outcome.scenario;
         if let Scenario::Mutant(mutant) = scenario {
             let file = match scenario_outcome.summary() {
@@ -201,6 +241,8 @@ impl OutputDir {
                 SummaryOutcome::CaughtMutant => &mut self.caught_list,
                 SummaryOutcome::Timeout => &mut self.timeout_list,
                 SummaryOutcome::Unviable => &mut self.unviable_list,
+                SummaryOutcome::Flaky => &mut self.flaky_list,
+                SummaryOutcome::BuildTimeout => &mut self.build_timeout_list,
                 _ => return Ok(()),
             };
             writeln!(file, "{}", mutant.name(true, false)).context("write to list file")?;
@@ -208,6 +250,12 @@ impl OutputDir {
         Ok(())
     }
 
+    /// Record mutants that were not tested because the run ran out of time.
+    pub fn add_not_tested(&mut self, mutants: Vec<Mutant>) -> Result<()> {
+        self.lab_outcome.not_tested.extend(mutants);
+        self.write_lab_outcome()
+    }
+
     pub fn open_debug_log(&self) -> Result<File> {
         let debug_log_path = self.path.join("debug.log");
         OpenOptions::new()
@@ -289,7 +337,9 @@ mod test {
                 "",
                 "Cargo.toml",
                 "mutants.out",
+                "mutants.out/build_timeout.txt",
                 "mutants.out/caught.txt",
+                "mutants.out/flaky.txt",
                 "mutants.out/lock.json",
                 "mutants.out/log",
                 "mutants.out/missed.txt",
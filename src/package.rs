@@ -2,8 +2,12 @@
 
 //! Discover and represent cargo packages within a workspace.
 
+use std::collections::{BTreeMap, BTreeSet};
+
 use camino::Utf8PathBuf;
 
+use crate::Features;
+
 /// A package built and tested as a unit.
 #[derive(Debug, Eq, PartialEq, Hash, Clone)]
 pub struct Package {
@@ -12,4 +16,111 @@ pub struct Package {
 
     /// For Cargo, the path of the `Cargo.toml` manifest file, relative to the top of the tree.
     pub relative_manifest_path: Utf8PathBuf,
+
+    /// The features declared by the package, and the features or dependencies each one
+    /// turns on, from `cargo metadata`.
+    pub features: BTreeMap<String, Vec<String>>,
+}
+
+impl Package {
+    /// Return the names of this package's own features that are turned on by a selection of
+    /// features, including the default features and those they turn on in turn.
+    pub fn active_features(&self, features: &Features) -> BTreeSet<String> {
+        if features.all_features {
+            return self.features.keys().cloned().collect();
+        }
+        let mut queue: Vec<String> = features
+            .features
+            .iter()
+            .flat_map(|arg| arg.split([' ', ',']))
+            .filter_map(|name| match name.split_once('/') {
+                Some((package, name)) if package == self.name => Some(name),
+                Some(_) => None,
+                None => Some(name),
+            })
+            .filter(|name| !name.is_empty())
+            .map(ToOwned::to_owned)
+            .collect();
+        if !features.no_default_features && self.features.contains_key("default") {
+            queue.push("default".to_owned());
+        }
+        let mut active = BTreeSet::new();
+        while let Some(name) = queue.pop() {
+            if let Some(enables) = self.features.get(&name) {
+                // Entries like `dep:foo` or `foo/bar` are about dependencies; only plain
+                // names are other features of this package.
+                queue.extend(
+                    enables
+                        .iter()
+                        .filter(|f| !f.contains([':', '/']) && !active.contains(f.as_str()))
+                        .cloned(),
+                );
+            }
+            active.insert(name);
+        }
+        active
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn package() -> Package {
+        Package {
+            name: "pkg".to_owned(),
+            relative_manifest_path: "Cargo.toml".into(),
+            features: [
+                ("default", vec!["fast"]),
+                ("fast", vec!["simd", "dep:rayon"]),
+                ("simd", vec![]),
+                ("extra", vec!["serde/std"]),
+            ]
+            .into_iter()
+            .map(|(name, enables)| {
+                (
+                    name.to_owned(),
+                    enables.into_iter().map(ToOwned::to_owned).collect(),
+                )
+            })
+            .collect(),
+        }
+    }
+
+    fn names(active: BTreeSet<String>) -> Vec<String> {
+        active.into_iter().collect()
+    }
+
+    #[test]
+    fn default_features_are_active() {
+        assert_eq!(
+            names(package().active_features(&Features::default())),
+            ["default", "fast", "simd"]
+        );
+    }
+
+    #[test]
+    fn selected_features_are_active() {
+        let features = Features {
+            features: vec!["extra pkg/simd,other/thing".to_owned()],
+            no_default_features: true,
+            all_features: false,
+        };
+        assert_eq!(
+            names(package().active_features(&features)),
+            ["extra", "simd"]
+        );
+    }
+
+    #[test]
+    fn all_features_are_active() {
+        let features = Features {
+            all_features: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            names(package().active_features(&features)),
+            ["default", "extra", "fast", "simd"]
+        );
+    }
 }
@@ -2,26 +2,32 @@
 
 //! Manage a subprocess, with polling, timeouts, termination, and so on.
 //!
-//! This module is above the external `subprocess` crate, but has no
+//! This module is above [std::process] and the external `subprocess` crate, but has no
 //! knowledge of whether it's running Cargo or potentially something else.
 //!
 //! On Unix, the subprocess runs as its own process group, so that any
 //! grandchild processes are also signalled if it's interrupted.
+//!
+//! The subprocess can optionally be confined, with [ResourceLimits] or in a sandbox.
 
+use std::env;
 use std::ffi::OsString;
 use std::io::Read;
+use std::process::{Child, Command, ExitStatus};
 use std::thread::sleep;
 use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context};
 use camino::Utf8Path;
-use serde::Serialize;
-use subprocess::{ExitStatus, Popen, PopenConfig, Redirection};
+use serde::{Deserialize, Serialize};
+use subprocess::{Popen, PopenConfig, Redirection};
 use tracing::{debug, debug_span, error, span, trace, warn, Level};
 
 use crate::console::Console;
 use crate::interrupt::check_interrupted;
+use crate::limits::{ResourceLimit, ResourceLimits};
 use crate::log_file::LogFile;
+use crate::sandbox::sandbox_argv;
 use crate::Result;
 
 /// How long to wait for metadata-only Cargo commands.
@@ -31,24 +37,34 @@ const METADATA_TIMEOUT: Duration = Duration::from_secs(20);
 const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 pub struct Process {
-    child: Popen,
+    child: Child,
     start: Instant,
     timeout: Duration,
+    /// CPU time used by the child and the descendants it waited for, once it has exited.
+    cpu_time: Option<Duration>,
 }
 
 impl Process {
     /// Run a subprocess to completion, watching for interrupts, with a timeout, while
     /// ticking the progress bar.
+    ///
+    /// If the process fails with messages in its output showing that it, or more likely a
+    /// test binary it ran, ran into one of the resource limits, or is killed by a signal
+    /// that shows this, the status is [ProcessStatus::LimitExceeded].
+    #[allow(clippy::too_many_arguments)]
     pub fn run(
         argv: &[String],
         env: &[(String, String)],
+        unset_env: &[String],
         cwd: &Utf8Path,
         timeout: Duration,
+        confinement: Option<&Confinement>,
         log_file: &mut LogFile,
         console: &Console,
     ) -> Result<ProcessStatus> {
-        let mut child = Process::start(argv, env, cwd, timeout, log_file)?;
-        let process_status = loop {
+        let log_start = log_file.position()?;
+        let mut child = Process::start(argv, env, unset_env, cwd, timeout, confinement, log_file)?;
+        let mut process_status = loop {
             if let Some(exit_status) = child.poll()? {
                 break exit_status;
             } else {
@@ -56,43 +72,68 @@ impl Process {
                 sleep(WAIT_POLL_INTERVAL);
             }
         };
+        if let Some(limits) = confinement
+            .map(|c| c.limits)
+            .filter(|limits| !limits.is_empty())
+        {
+            let exceeded = match process_status {
+                ProcessStatus::Failure(_) => limits.find_exceeded(&log_file.read_from(log_start)?),
+                ProcessStatus::Signalled(signal) => limits
+                    .find_exceeded(&log_file.read_from(log_start)?)
+                    .or_else(|| limits.exceeded_by_signal(signal, child.cpu_time)),
+                _ => None,
+            };
+            if let Some(limit) = exceeded {
+                process_status = ProcessStatus::LimitExceeded(limit);
+            }
+        }
         log_file.message(&format!("result: {process_status:?}"));
         Ok(process_status)
     }
 
     /// Launch a process, and return an object representing the child.
+    ///
+    /// The child inherits the environment of this process, except for `unset_env`, with
+    /// `env` added; if a variable is set more than once, the last value wins.
+    ///
+    /// If a `confinement` is given, it's applied to the child and inherited by its children.
+    /// On Unix, the child is the leader of a new process group.
     pub fn start(
         argv: &[String],
         env: &[(String, String)],
+        unset_env: &[String],
         cwd: &Utf8Path,
         timeout: Duration,
+        confinement: Option<&Confinement>,
         log_file: &mut LogFile,
     ) -> Result<Process> {
         let start = Instant::now();
-        let quoted_argv = cheap_shell_quote(argv);
+        let argv = match confinement {
+            Some(confinement) if confinement.sandbox => sandbox_argv(argv),
+            _ => argv.to_vec(),
+        };
+        let quoted_argv = cheap_shell_quote(&argv);
         log_file.message(&quoted_argv);
         debug!(%quoted_argv, "start process");
-        let mut os_env = PopenConfig::current_env();
-        os_env.extend(
-            env.iter()
-                .map(|(k, v)| (OsString::from(k), OsString::from(v))),
-        );
-        let child = Popen::create(
-            argv,
-            PopenConfig {
-                stdin: Redirection::None,
-                stdout: Redirection::File(log_file.open_append()?),
-                stderr: Redirection::Merge,
-                cwd: Some(cwd.as_os_str().to_owned()),
-                env: Some(os_env),
-                ..setpgid_on_unix()
-            },
-        )
-        .with_context(|| format!("failed to spawn {}", argv.join(" ")))?;
+        let stdout = log_file.open_append()?;
+        let stderr = stdout.try_clone().context("duplicate log file handle")?;
+        let mut command = Command::new(&argv[0]);
+        command
+            .args(&argv[1..])
+            .current_dir(cwd)
+            .env_clear()
+            .envs(child_env(env::vars_os().collect(), env, unset_env))
+            .stdout(stdout)
+            .stderr(stderr);
+        configure_unix_child(&mut command, confinement.map(|c| c.limits));
+        let child = command
+            .spawn()
+            .with_context(|| format!("failed to spawn {}", argv.join(" ")))?;
         Ok(Process {
             child,
             start,
             timeout,
+            cpu_time: None,
         })
     }
 
@@ -108,13 +149,11 @@ impl Process {
             debug!("interrupted, terminating child process...");
             self.terminate()?;
             Err(e)
-        } else if let Some(status) = self.child.poll() {
-            match status {
-                _ if status.success() => Ok(Some(ProcessStatus::Success)),
-                ExitStatus::Exited(code) => Ok(Some(ProcessStatus::Failure(code))),
-                ExitStatus::Signaled(signal) => Ok(Some(ProcessStatus::Signalled(signal))),
-                ExitStatus::Undetermined | ExitStatus::Other(_) => Ok(Some(ProcessStatus::Other)),
-            }
+        } else if let Some((status, cpu_time)) =
+            try_wait_with_cpu_time(&mut self.child).context("poll child")?
+        {
+            self.cpu_time = cpu_time;
+            Ok(Some(ProcessStatus::from_exit_status(status)))
         } else {
             Ok(None)
         }
@@ -126,12 +165,11 @@ impl Process {
     ///
     /// The status might not be Timeout if this raced with a normal exit.
     fn terminate(&mut self) -> Result<()> {
-        let _span = span!(Level::DEBUG, "terminate_child", pid = self.child.pid()).entered();
+        let _span = span!(Level::DEBUG, "terminate_child", pid = self.child.id()).entered();
         debug!("terminating child process");
         terminate_child_impl(&mut self.child)?;
         trace!("wait for child after termination");
         if let Some(exit_status) = self
-            .child
             .wait_timeout(Duration::from_secs(10))
             .context("wait for child after terminating pgroup")?
         {
@@ -142,7 +180,6 @@ impl Process {
             warn!("force kill child: {:?}", kill_result);
             if kill_result.is_ok() {
                 if let Ok(Some(exit_status)) = self
-                    .child
                     .wait_timeout(Duration::from_secs(10))
                     .context("wait for child after force kill")
                 {
@@ -154,15 +191,28 @@ impl Process {
         }
         Ok(())
     }
+
+    /// Wait up to `timeout` for the child to exit, and return its status if it did.
+    fn wait_timeout(&mut self, timeout: Duration) -> std::io::Result<Option<ExitStatus>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(status) = self.child.try_wait()? {
+                return Ok(Some(status));
+            } else if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            sleep(WAIT_POLL_INTERVAL);
+        }
+    }
 }
 
 #[cfg(unix)]
 #[allow(unknown_lints, clippy::needless_pass_by_ref_mut)] // To match Windows
-fn terminate_child_impl(child: &mut Popen) -> Result<()> {
+fn terminate_child_impl(child: &mut Child) -> Result<()> {
     use nix::errno::Errno;
     use nix::sys::signal::{killpg, Signal};
 
-    let pid = nix::unistd::Pid::from_raw(child.pid().expect("child has a pid").try_into().unwrap());
+    let pid = nix::unistd::Pid::from_raw(child.id().try_into().unwrap());
     if let Err(errno) = killpg(pid, Signal::SIGTERM) {
         // It might have already exited, in which case we can proceed to wait for it.
         if errno != Errno::ESRCH {
@@ -177,8 +227,8 @@ fn terminate_child_impl(child: &mut Popen) -> Result<()> {
 // We do not yet have a way to mutate this only on Windows, and I mostly test on Unix, so it's just skipped for now.
 #[mutants::skip]
 #[cfg(not(unix))]
-fn terminate_child_impl(child: &mut Popen) -> Result<()> {
-    if let Err(e) = child.terminate() {
+fn terminate_child_impl(child: &mut Child) -> Result<()> {
+    if let Err(e) = child.kill() {
         // most likely we raced and it's already gone
         let message = format!("failed to terminate child: {}", e);
         warn!("{}", message);
@@ -187,8 +237,17 @@ fn terminate_child_impl(child: &mut Popen) -> Result<()> {
     Ok(())
 }
 
+/// Restrictions on a child process, which are inherited by everything it starts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Confinement {
+    /// Resource limits on each process.
+    pub limits: ResourceLimits,
+    /// Run in a sandbox with no network and a private `/tmp`.
+    pub sandbox: bool,
+}
+
 /// The result of running a single child process.
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ProcessStatus {
     /// Exited with status 0.
     Success,
@@ -198,11 +257,25 @@ pub enum ProcessStatus {
     Timeout,
     /// Killed by some signal.
     Signalled(u8),
+    /// Failed after running into a resource limit.
+    LimitExceeded(ResourceLimit),
     /// Unknown or unexpected situation.
     Other,
 }
 
 impl ProcessStatus {
+    fn from_exit_status(status: ExitStatus) -> ProcessStatus {
+        if status.success() {
+            ProcessStatus::Success
+        } else if let Some(code) = status.code() {
+            ProcessStatus::Failure(code as u32)
+        } else if let Some(signal) = exit_signal(status) {
+            ProcessStatus::Signalled(signal)
+        } else {
+            ProcessStatus::Other
+        }
+    }
+
     pub fn is_success(&self) -> bool {
         *self == ProcessStatus::Success
     }
@@ -212,22 +285,108 @@ impl ProcessStatus {
     }
 
     pub fn is_failure(&self) -> bool {
-        matches!(self, ProcessStatus::Failure(_))
+        matches!(
+            self,
+            ProcessStatus::Failure(_) | ProcessStatus::LimitExceeded(_)
+        )
+    }
+
+    /// If the process was stopped by a resource limit, return which one.
+    pub fn limit_exceeded(&self) -> Option<ResourceLimit> {
+        match self {
+            ProcessStatus::LimitExceeded(limit) => Some(*limit),
+            _ => None,
+        }
     }
 }
 
+/// Make the environment for a child process, from an inherited environment, with
+/// some variables removed and others set.
+fn child_env(
+    inherited: Vec<(OsString, OsString)>,
+    env: &[(String, String)],
+    unset_env: &[String],
+) -> Vec<(OsString, OsString)> {
+    // Children would see only one value for each variable, but which one is not
+    // well defined, so remove any duplicates.
+    let mut os_env: Vec<(OsString, OsString)> = inherited
+        .into_iter()
+        .filter(|(k, _)| {
+            !unset_env.iter().any(|u| k == u.as_str()) && !env.iter().any(|(e, _)| k == e.as_str())
+        })
+        .collect();
+    for (i, (k, v)) in env.iter().enumerate() {
+        if !env[i + 1..].iter().any(|(later, _)| later == k) {
+            os_env.push((OsString::from(k), OsString::from(v)));
+        }
+    }
+    os_env
+}
+
+/// Put the child in its own process group, and apply any resource limits.
 #[cfg(unix)]
-fn setpgid_on_unix() -> PopenConfig {
-    PopenConfig {
-        setpgid: true,
-        ..Default::default()
+fn configure_unix_child(command: &mut Command, limits: Option<ResourceLimits>) {
+    use std::os::unix::process::CommandExt;
+
+    command.process_group(0);
+    if let Some(limits) = limits.filter(|limits| !limits.is_empty()) {
+        // Safety: setting rlimits only makes system calls, which is safe between fork and exec.
+        unsafe {
+            command.pre_exec(move || limits.set_rlimits().map_err(std::io::Error::from));
+        }
     }
 }
 
 #[mutants::skip] // Has no effect, so can't be tested.
 #[cfg(not(unix))]
-fn setpgid_on_unix() -> PopenConfig {
-    Default::default()
+fn configure_unix_child(_command: &mut Command, _limits: Option<ResourceLimits>) {}
+
+/// Check whether the child has exited, without blocking, and if so return its status
+/// and the CPU time used by it and the descendants it waited for.
+///
+/// This reaps the child, so it must not be waited for again.
+#[cfg(unix)]
+fn try_wait_with_cpu_time(
+    child: &mut Child,
+) -> std::io::Result<Option<(ExitStatus, Option<Duration>)>> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let pid = child.id() as libc::pid_t;
+    let mut status = 0;
+    // Safety: rusage is plain data, and wait4 only writes to the pointers it's given.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    match unsafe { libc::wait4(pid, &mut status, libc::WNOHANG, &mut usage) } {
+        0 => Ok(None),
+        -1 => Err(std::io::Error::last_os_error()),
+        _ => {
+            let time = |t: libc::timeval| {
+                Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64)
+            };
+            Ok(Some((
+                ExitStatus::from_raw(status),
+                Some(time(usage.ru_utime) + time(usage.ru_stime)),
+            )))
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn try_wait_with_cpu_time(
+    child: &mut Child,
+) -> std::io::Result<Option<(ExitStatus, Option<Duration>)>> {
+    Ok(child.try_wait()?.map(|status| (status, None)))
+}
+
+#[cfg(unix)]
+fn exit_signal(status: ExitStatus) -> Option<u8> {
+    use std::os::unix::process::ExitStatusExt;
+
+    status.signal().and_then(|signal| signal.try_into().ok())
+}
+
+#[cfg(not(unix))]
+fn exit_signal(_status: ExitStatus) -> Option<u8> {
+    None
 }
 
 /// Run a command and return its stdout output as a string.
@@ -303,7 +462,9 @@ fn cheap_shell_quote<S: AsRef<str>, I: IntoIterator<Item = S>>(argv: I) -> Strin
 
 #[cfg(test)]
 mod test {
-    use super::cheap_shell_quote;
+    use std::ffi::OsString;
+
+    use super::{cheap_shell_quote, child_env};
 
     #[test]
     fn shell_quoting() {
@@ -313,4 +474,27 @@ mod test {
             r#"foo\ bar \\blah\\t \"quoted\""#
         );
     }
+
+    #[test]
+    fn child_env_sets_and_unsets_variables() {
+        let os = |k: &str, v: &str| (OsString::from(k), OsString::from(v));
+        let inherited = vec![
+            os("HOME", "/home/me"),
+            os("SECRET", "x"),
+            os("RUST_LOG", "warn"),
+        ];
+        let env = [
+            ("RUST_LOG".to_owned(), "info".to_owned()),
+            ("PROPTEST_CASES".to_owned(), "16".to_owned()),
+            ("RUST_LOG".to_owned(), "debug".to_owned()),
+        ];
+        assert_eq!(
+            child_env(inherited, &env, &["SECRET".to_owned()]),
+            [
+                os("HOME", "/home/me"),
+                os("PROPTEST_CASES", "16"),
+                os("RUST_LOG", "debug")
+            ]
+        );
+    }
 }
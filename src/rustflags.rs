@@ -0,0 +1,721 @@
+// Copyright 2024 Martin Pool
+
+//! Work out the flags Cargo would pass to rustc, so that cargo-mutants can add
+//! `--cap-lints` to them.
+//!
+//! Setting `CARGO_ENCODED_RUSTFLAGS` stops Cargo reading `build.rustflags` and
+//! `target.*.rustflags` from its config files and `--config` arguments, so those are read
+//! here instead, following
+//! <https://doc.rust-lang.org/cargo/reference/config.html#buildrustflags>.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::read_to_string;
+
+use anyhow::{anyhow, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use toml::Value;
+use tracing::debug;
+
+use crate::cfg::{enabled, rustc_command, Cfg, TargetCfg};
+use crate::Options;
+
+/// Return adjusted CARGO_ENCODED_RUSTFLAGS, including any changes to cap-lints.
+///
+/// See <https://doc.rust-lang.org/cargo/reference/environment-variables.html>
+/// <https://doc.rust-lang.org/rustc/lints/levels.html#capping-lints>
+pub fn rustflags(options: &Options) -> String {
+    let mut rustflags = effective_rustflags(options);
+    rustflags.push("--cap-lints=allow".to_owned());
+    rustflags.join("\x1f")
+}
+
+/// Return the flags that Cargo would pass to rustc, from the environment or else from
+/// its config files.
+pub fn effective_rustflags(options: &Options) -> Vec<String> {
+    env_rustflags().unwrap_or_else(|| options.config_rustflags.clone())
+}
+
+/// Return the flags from `CARGO_ENCODED_RUSTFLAGS` or `RUSTFLAGS`, if either is set,
+/// in which case Cargo doesn't look at its config files.
+fn env_rustflags() -> Option<Vec<String>> {
+    if let Some(rustflags) = env::var_os("CARGO_ENCODED_RUSTFLAGS") {
+        Some(
+            rustflags
+                .to_str()
+                .expect("CARGO_ENCODED_RUSTFLAGS is not valid UTF-8")
+                .split('\x1f')
+                .map(|s| s.to_owned())
+                .collect(),
+        )
+    } else {
+        env::var_os("RUSTFLAGS").map(|rustflags| {
+            rustflags
+                .to_str()
+                .expect("RUSTFLAGS is not valid UTF-8")
+                .split(' ')
+                .map(|s| s.to_owned())
+                .collect()
+        })
+    }
+}
+
+/// Read the rustflags that Cargo would use from its config files, from `--config`
+/// arguments passed to Cargo, and from the environment variables that override them,
+/// when building in `source_dir` for the selected toolchain and target.
+///
+/// Returns an empty list if `CARGO_ENCODED_RUSTFLAGS` or `RUSTFLAGS` is set, since they
+/// take precedence over the config files.
+pub fn config_rustflags(source_dir: &Utf8Path, options: &Options) -> Result<Vec<String>> {
+    if env_rustflags().is_some() {
+        return Ok(Vec::new());
+    }
+    let file_configs = read_config_files(source_dir)?;
+    // As in Cargo, relative paths in `--config` are relative to the current directory.
+    let cwd: Utf8PathBuf = env::current_dir()
+        .context("get current directory")?
+        .try_into()
+        .context("current directory path to UTF-8")?;
+    let arg_configs = config_args(&options.additional_cargo_args)
+        .into_iter()
+        .map(|arg| read_config_arg(arg, &cwd))
+        .collect::<Result<Vec<Value>>>()?;
+    let target = build_target(options, &file_configs, &arg_configs, |name| {
+        env::var(name).ok()
+    })?;
+    let configs = [file_configs, arg_configs].concat();
+    let target_keys = configs
+        .iter()
+        .filter_map(|config| config.get("target")?.as_table())
+        .flat_map(|targets| targets.iter())
+        .filter(|(_, target)| target.get("rustflags").is_some())
+        .map(|(key, _)| key)
+        .collect::<Vec<_>>();
+    // Don't run rustc again unless there's something that depends on the target.
+    let triple = match &target {
+        Some(target) => Some(target_triple(target).to_owned()),
+        None if target_keys.iter().any(|key| !key.starts_with("cfg("))
+            || env::vars_os().any(|(name, _)| is_target_rustflags_var(&name.to_string_lossy())) =>
+        {
+            Some(host_triple(options)?)
+        }
+        None => None,
+    };
+    let target_cfg = if target_keys.iter().any(|key| key.starts_with("cfg(")) {
+        let options = Options {
+            target,
+            ..options.clone()
+        };
+        TargetCfg::from_rustc(source_dir, &options)
+    } else {
+        TargetCfg::default()
+    };
+    let rustflags = resolve_rustflags(&configs, triple.as_deref(), &target_cfg, |name| {
+        env::var(name).ok()
+    })?;
+    debug!(?rustflags, "rustflags from Cargo config");
+    Ok(rustflags)
+}
+
+/// Return the target that Cargo will build for, if it's not the host.
+///
+/// As in Cargo, this is from the first of: `--target` given to cargo-mutants or in the
+/// arguments passed to Cargo, `build.target` in `--config` arguments, `CARGO_BUILD_TARGET`,
+/// and `build.target` in config files. If `build.target` lists several targets, the
+/// first is used.
+fn build_target(
+    options: &Options,
+    file_configs: &[Value],
+    arg_configs: &[Value],
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<Option<String>> {
+    if let Some(target) = &options.target {
+        return Ok(Some(target.clone()));
+    }
+    let mut args = options.additional_cargo_args.iter();
+    let mut from_args = None;
+    while let Some(arg) = args.next() {
+        if arg == "--target" {
+            from_args = args.next().cloned();
+        } else if let Some(target) = arg.strip_prefix("--target=") {
+            from_args = Some(target.to_owned());
+        }
+    }
+    if from_args.is_some() {
+        return Ok(from_args);
+    }
+    let config_target = |configs: &[Value]| -> Result<Option<String>> {
+        let Some(value) = configs
+            .iter()
+            .filter_map(|config| config.get("build")?.get("target"))
+            .next_back()
+        else {
+            return Ok(None);
+        };
+        match value {
+            Value::String(target) => Ok(Some(target.clone())),
+            Value::Array(targets) => targets
+                .first()
+                .map(|target| {
+                    target.as_str().map(ToOwned::to_owned).ok_or_else(|| {
+                        anyhow!("build.target: expected a string but found {target}")
+                    })
+                })
+                .transpose(),
+            _ => Err(anyhow!(
+                "build.target: expected a string or array but found {value}"
+            )),
+        }
+    };
+    if let Some(target) = config_target(arg_configs)? {
+        Ok(Some(target))
+    } else if let Some(target) = env("CARGO_BUILD_TARGET") {
+        Ok(Some(target))
+    } else {
+        config_target(file_configs)
+    }
+}
+
+/// Return the name that Cargo uses for a target in `target.<triple>` config keys: the
+/// target itself, or the file stem if it's the path of a target spec file.
+fn target_triple(target: &str) -> &str {
+    if target.ends_with(".json") {
+        Utf8Path::new(target).file_stem().unwrap_or(target)
+    } else {
+        target
+    }
+}
+
+/// True if this is the name of a `CARGO_TARGET_<triple>_RUSTFLAGS` variable.
+fn is_target_rustflags_var(name: &str) -> bool {
+    name.starts_with("CARGO_TARGET_") && name.ends_with("_RUSTFLAGS")
+}
+
+/// Return the values of `--config` options in a list of Cargo arguments.
+fn config_args(args: &[String]) -> Vec<&str> {
+    let mut values = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            if let Some(value) = args.next() {
+                values.push(value.as_str());
+            }
+        } else if let Some(value) = arg.strip_prefix("--config=") {
+            values.push(value);
+        }
+    }
+    values
+}
+
+/// Make the paths of config files in `--config` arguments absolute, relative to `cwd` as
+/// Cargo would interpret them, so that they still refer to the same files when Cargo is run
+/// in a build directory.
+pub fn absolute_config_args(args: Vec<String>, cwd: &Utf8Path) -> Vec<String> {
+    let absolute = |value: &str| {
+        let path = cwd.join(value);
+        (!value.is_empty() && path.exists()).then(|| path.to_string())
+    };
+    let mut result = Vec::with_capacity(args.len());
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            result.push(arg);
+            if let Some(value) = args.next() {
+                result.push(absolute(&value).unwrap_or(value));
+            }
+        } else if let Some(path) = arg.strip_prefix("--config=").and_then(absolute) {
+            result.push(format!("--config={path}"));
+        } else {
+            result.push(arg);
+        }
+    }
+    result
+}
+
+/// Read the value of a `--config` option, which is either the path of a config file,
+/// relative to `cwd`, or a TOML key-value pair, such as `build.rustflags=["--cfg","foo"]`.
+fn read_config_arg(arg: &str, cwd: &Utf8Path) -> Result<Value> {
+    let path = cwd.join(arg);
+    if !arg.is_empty() && path.exists() {
+        read_to_string(&path)
+            .with_context(|| format!("read {path}"))?
+            .parse::<Value>()
+            .with_context(|| format!("parse {path}"))
+    } else {
+        arg.parse::<Value>()
+            .with_context(|| format!("parse --config {arg:?}"))
+    }
+}
+
+/// Combine the rustflags from config files and `--config` arguments, given in increasing
+/// order of precedence, and from environment variables looked up by `env`.
+///
+/// As in Cargo, the flags from `target.<triple>` and every matching `target.<cfg>` are
+/// joined together, and if there are none then `build.rustflags` is used.
+///
+/// `triple` is only needed if some flags are set for a particular target triple.
+fn resolve_rustflags(
+    configs: &[Value],
+    triple: Option<&str>,
+    target_cfg: &TargetCfg,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<String>> {
+    let mut target_rustflags = Vec::new();
+    if let Some(triple) = triple {
+        let triple_env = format!(
+            "CARGO_TARGET_{}_RUSTFLAGS",
+            triple.to_uppercase().replace(['-', '.'], "_")
+        );
+        target_rustflags = string_list(
+            configs
+                .iter()
+                .filter_map(|config| config.get("target")?.get(triple)?.get("rustflags")),
+            env(&triple_env),
+        )
+        .with_context(|| format!("target.{triple}.rustflags"))?
+        .unwrap_or_default();
+    }
+    let mut cfg_keys: BTreeMap<&str, Vec<&Value>> = BTreeMap::new();
+    for config in configs {
+        if let Some(targets) = config.get("target").and_then(Value::as_table) {
+            for (key, target) in targets {
+                if let Some(rustflags) = target.get("rustflags") {
+                    cfg_keys.entry(key).or_default().push(rustflags);
+                }
+            }
+        }
+    }
+    for (key, values) in cfg_keys {
+        let Some(cfg) = Cfg::from_cfg_str(key) else {
+            continue;
+        };
+        if enabled(&[cfg], target_cfg, None) == Some(true) {
+            target_rustflags.extend(
+                string_list(values.into_iter(), None)
+                    .with_context(|| format!("target.{key}.rustflags"))?
+                    .unwrap_or_default(),
+            );
+        } else {
+            debug!(
+                ?key,
+                "rustflags for cfg that's not known to be enabled are ignored"
+            );
+        }
+    }
+    if !target_rustflags.is_empty() {
+        return Ok(target_rustflags);
+    }
+    Ok(string_list(
+        configs
+            .iter()
+            .filter_map(|config| config.get("build")?.get("rustflags")),
+        env("CARGO_BUILD_RUSTFLAGS"),
+    )
+    .context("build.rustflags")?
+    .unwrap_or_default())
+}
+
+/// Merge the values of a config key that can be either an array of strings or a
+/// space-separated string, given in increasing order of precedence, and then the value
+/// of the corresponding environment variable.
+///
+/// As in Cargo, arrays are joined together, and a string replaces any earlier values.
+///
+/// Returns None if the key is set nowhere.
+fn string_list<'a>(
+    values: impl Iterator<Item = &'a Value>,
+    env_value: Option<String>,
+) -> Result<Option<Vec<String>>> {
+    let mut result: Option<Vec<String>> = None;
+    for value in values {
+        match value {
+            Value::String(s) => {
+                result = Some(s.split_whitespace().map(ToOwned::to_owned).collect());
+            }
+            Value::Array(array) => {
+                let list = result.get_or_insert_with(Vec::new);
+                for item in array {
+                    list.push(
+                        item.as_str()
+                            .ok_or_else(|| anyhow!("expected a string but found {item}"))?
+                            .to_owned(),
+                    );
+                }
+            }
+            _ => return Err(anyhow!("expected a string or array but found {value}")),
+        }
+    }
+    if let Some(env_value) = env_value {
+        result
+            .get_or_insert_with(Vec::new)
+            .extend(env_value.split_whitespace().map(ToOwned::to_owned));
+    }
+    Ok(result)
+}
+
+/// Read the Cargo config files that apply in `source_dir`, in increasing order of
+/// precedence: the one in `CARGO_HOME`, and then those in `.cargo` directories from the
+/// root down to `source_dir`.
+///
+/// See <https://doc.rust-lang.org/cargo/reference/config.html#hierarchical-structure>.
+fn read_config_files(source_dir: &Utf8Path) -> Result<Vec<Value>> {
+    let source_dir = source_dir
+        .canonicalize_utf8()
+        .context("canonicalize source path")?;
+    let mut paths: Vec<Utf8PathBuf> = source_dir
+        .ancestors()
+        .filter_map(|dir| config_file_in(&dir.join(".cargo")))
+        .collect();
+    if let Some(home_config) = cargo_home().and_then(|home| config_file_in(&home)) {
+        if !paths.contains(&home_config) {
+            paths.push(home_config);
+        }
+    }
+    paths
+        .iter()
+        .rev()
+        .map(|path| {
+            debug!(?path, "read Cargo config");
+            read_to_string(path)
+                .with_context(|| format!("read {path}"))?
+                .parse::<Value>()
+                .with_context(|| format!("parse {path}"))
+        })
+        .collect()
+}
+
+/// Return the config file in a `.cargo` directory, if there is one.
+///
+/// If there's both `config` and `config.toml`, Cargo uses `config`.
+fn config_file_in(dir: &Utf8Path) -> Option<Utf8PathBuf> {
+    ["config", "config.toml"]
+        .into_iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+fn cargo_home() -> Option<Utf8PathBuf> {
+    if let Ok(cargo_home) = env::var("CARGO_HOME") {
+        Some(cargo_home.into())
+    } else {
+        env::var("HOME")
+            .or_else(|_| env::var("USERPROFILE"))
+            .ok()
+            .map(|home| Utf8PathBuf::from(home).join(".cargo"))
+    }
+}
+
+/// Ask rustc for the host target triple, which Cargo builds for if there's no `--target`.
+fn host_triple(options: &Options) -> Result<String> {
+    let output = rustc_command(options)
+        .arg("-vV")
+        .output()
+        .context("run rustc -vV")?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(|host| host.trim().to_owned())
+        .ok_or_else(|| anyhow!("rustc -vV didn't report the host triple"))
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn parse_configs(configs: &[&str]) -> Vec<Value> {
+        configs.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    fn linux_cfg() -> TargetCfg {
+        TargetCfg::parse("unix\ntarget_os=\"linux\"\n")
+    }
+
+    #[test]
+    fn build_rustflags_are_merged() {
+        let configs = parse_configs(&[
+            indoc! { r#"
+                [build]
+                rustflags = ["--cfg", "home"]
+            "# },
+            indoc! { r#"
+                [build]
+                rustflags = ["--cfg", "tokio_unstable"]
+            "# },
+        ]);
+        let rustflags = resolve_rustflags(
+            &configs,
+            Some("x86_64-unknown-linux-gnu"),
+            &linux_cfg(),
+            |_| None,
+        )
+        .unwrap();
+        assert_eq!(rustflags, ["--cfg", "home", "--cfg", "tokio_unstable"]);
+
+        let rustflags = resolve_rustflags(
+            &configs,
+            Some("x86_64-unknown-linux-gnu"),
+            &linux_cfg(),
+            |name| (name == "CARGO_BUILD_RUSTFLAGS").then(|| "-Ctarget-cpu=native".to_owned()),
+        )
+        .unwrap();
+        assert_eq!(
+            rustflags,
+            [
+                "--cfg",
+                "home",
+                "--cfg",
+                "tokio_unstable",
+                "-Ctarget-cpu=native"
+            ]
+        );
+    }
+
+    #[test]
+    fn string_rustflags_replace_earlier_values() {
+        let configs = parse_configs(&[
+            "build.rustflags = [\"--cfg\", \"home\"]",
+            "build.rustflags = \"--cfg  tokio_unstable\"",
+        ]);
+        assert_eq!(
+            resolve_rustflags(
+                &configs,
+                Some("x86_64-unknown-linux-gnu"),
+                &linux_cfg(),
+                |_| None
+            )
+            .unwrap(),
+            ["--cfg", "tokio_unstable"]
+        );
+    }
+
+    #[test]
+    fn target_rustflags_take_precedence_over_build() {
+        let configs = parse_configs(&[indoc! { r#"
+            [build]
+            rustflags = ["--cfg", "build"]
+
+            [target.x86_64-unknown-linux-gnu]
+            rustflags = ["--cfg", "triple"]
+
+            [target.'cfg(unix)']
+            rustflags = ["--cfg", "unix"]
+
+            [target.'cfg(windows)']
+            rustflags = ["--cfg", "windows"]
+        "# }]);
+        assert_eq!(
+            resolve_rustflags(
+                &configs,
+                Some("x86_64-unknown-linux-gnu"),
+                &linux_cfg(),
+                |_| None
+            )
+            .unwrap(),
+            ["--cfg", "triple", "--cfg", "unix"]
+        );
+        // Nothing matches this target, so build.rustflags is used.
+        assert_eq!(
+            resolve_rustflags(
+                &configs,
+                Some("x86_64-pc-windows-msvc"),
+                &TargetCfg::parse("target_os=\"windows\"\n"),
+                |_| None
+            )
+            .unwrap(),
+            ["--cfg", "build"]
+        );
+        assert_eq!(
+            resolve_rustflags(
+                &configs,
+                Some("wasm32-wasip1"),
+                &TargetCfg::default(),
+                |name| {
+                    (name == "CARGO_TARGET_WASM32_WASIP1_RUSTFLAGS").then(|| "--cfg env".to_owned())
+                }
+            )
+            .unwrap(),
+            ["--cfg", "env"]
+        );
+    }
+
+    #[test]
+    fn triple_is_not_needed_without_target_rustflags() {
+        let configs = parse_configs(&[indoc! { r#"
+            [build]
+            rustflags = ["--cfg", "build"]
+
+            [target.'cfg(unix)']
+            rustflags = ["--cfg", "unix"]
+        "# }]);
+        assert_eq!(
+            resolve_rustflags(&configs, None, &linux_cfg(), |_| None).unwrap(),
+            ["--cfg", "unix"]
+        );
+        assert_eq!(
+            resolve_rustflags(&configs, None, &TargetCfg::default(), |_| None).unwrap(),
+            ["--cfg", "build"]
+        );
+    }
+
+    #[test]
+    fn config_args_are_found_in_cargo_args() {
+        let args = [
+            "--offline",
+            "--config",
+            "build.rustflags=[\"--cfg\", \"a\"]",
+            "--config=extra.toml",
+            "--release",
+        ]
+        .map(String::from);
+        assert_eq!(
+            config_args(&args),
+            ["build.rustflags=[\"--cfg\", \"a\"]", "extra.toml"]
+        );
+    }
+
+    #[test]
+    fn config_arg_is_a_file_or_toml() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap();
+        std::fs::write(
+            dir.join("extra.toml"),
+            "build.rustflags = [\"--cfg\", \"file\"]\n",
+        )
+        .unwrap();
+        let configs = [
+            read_config_arg("extra.toml", dir).unwrap(),
+            read_config_arg("build.rustflags=[\"--cfg\", \"arg\"]", dir).unwrap(),
+        ];
+        assert_eq!(
+            resolve_rustflags(&configs, None, &linux_cfg(), |_| None).unwrap(),
+            ["--cfg", "file", "--cfg", "arg"]
+        );
+        assert!(read_config_arg("missing.toml", dir).is_err());
+    }
+
+    #[test]
+    fn build_target_is_found_in_options_args_and_config() {
+        let file_configs = parse_configs(&["build.target = \"aarch64-unknown-linux-gnu\""]);
+        let arg_configs =
+            parse_configs(&["build.target = [\"wasm32-wasip1\", \"x86_64-pc-windows-msvc\"]"]);
+        let mut options = Options::default();
+        let no_env = |_: &str| None;
+        let env = |name: &str| {
+            (name == "CARGO_BUILD_TARGET").then(|| "riscv64gc-unknown-linux-gnu".to_owned())
+        };
+        assert_eq!(build_target(&options, &[], &[], no_env).unwrap(), None);
+        assert_eq!(
+            build_target(&options, &file_configs, &[], no_env)
+                .unwrap()
+                .as_deref(),
+            Some("aarch64-unknown-linux-gnu")
+        );
+        // The environment overrides config files, but not `--config` arguments.
+        assert_eq!(
+            build_target(&options, &file_configs, &[], env)
+                .unwrap()
+                .as_deref(),
+            Some("riscv64gc-unknown-linux-gnu")
+        );
+        assert_eq!(
+            build_target(&options, &file_configs, &arg_configs, env)
+                .unwrap()
+                .as_deref(),
+            Some("wasm32-wasip1")
+        );
+        options.additional_cargo_args =
+            vec!["--target".to_owned(), "i686-linux-android".to_owned()];
+        assert_eq!(
+            build_target(&options, &file_configs, &arg_configs, env)
+                .unwrap()
+                .as_deref(),
+            Some("i686-linux-android")
+        );
+        options.target = Some("thumbv7em-none-eabihf".to_owned());
+        assert_eq!(
+            build_target(&options, &file_configs, &arg_configs, env)
+                .unwrap()
+                .as_deref(),
+            Some("thumbv7em-none-eabihf")
+        );
+    }
+
+    #[test]
+    fn target_rustflags_apply_to_configured_build_target() {
+        let configs = parse_configs(&[indoc! { r#"
+            [build]
+            target = "wasm32-wasip1"
+            rustflags = ["--cfg", "build"]
+
+            [target.wasm32-wasip1]
+            rustflags = ["--cfg", "wasm"]
+
+            [target.x86_64-unknown-linux-gnu]
+            rustflags = ["--cfg", "host"]
+        "# }]);
+        let target = build_target(&Options::default(), &configs, &[], |_| None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            resolve_rustflags(
+                &configs,
+                Some(target_triple(&target)),
+                &TargetCfg::default(),
+                |_| None
+            )
+            .unwrap(),
+            ["--cfg", "wasm"]
+        );
+    }
+
+    #[test]
+    fn target_spec_file_is_named_by_its_stem() {
+        assert_eq!(target_triple("wasm32-wasip1"), "wasm32-wasip1");
+        assert_eq!(target_triple("specs/my-board.json"), "my-board");
+    }
+
+    #[test]
+    fn config_arg_paths_are_made_absolute() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cwd = Utf8Path::from_path(tmp.path()).unwrap();
+        std::fs::write(cwd.join("extra.toml"), "").unwrap();
+        let args = [
+            "--config",
+            "extra.toml",
+            "--config=extra.toml",
+            "--config=build.jobs=2",
+            "--release",
+        ]
+        .map(String::from)
+        .to_vec();
+        assert_eq!(
+            absolute_config_args(args, cwd),
+            [
+                "--config".to_owned(),
+                cwd.join("extra.toml").to_string(),
+                format!("--config={}", cwd.join("extra.toml")),
+                "--config=build.jobs=2".to_owned(),
+                "--release".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_rustflags_type_is_an_error() {
+        let configs = parse_configs(&["build.rustflags = 3"]);
+        let err = resolve_rustflags(
+            &configs,
+            Some("x86_64-unknown-linux-gnu"),
+            &linux_cfg(),
+            |_| None,
+        )
+        .unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "build.rustflags: expected a string or array but found 3"
+        );
+    }
+}
@@ -0,0 +1,99 @@
+// Copyright 2024 Martin Pool
+
+//! Run tests in a sandbox with no network access and a private `/tmp`.
+//!
+//! Mutated code can make tests write to unexpected places or talk to real services.
+//! On Linux, the tests can be run inside new unprivileged user, network, and mount
+//! namespaces, created by `unshare` from util-linux. Inside, a fresh tmpfs is mounted
+//! on `/tmp`, and the build directory is bind-mounted back into it if it was under `/tmp`.
+//! The network namespace has only a loopback interface.
+
+use camino::Utf8Path;
+
+use crate::Result;
+
+/// Arguments to `unshare` to create the namespaces, as an unprivileged user.
+const UNSHARE_ARGV: &[&str] = &[
+    "unshare",
+    "--user",
+    "--map-root-user",
+    "--net",
+    "--mount",
+    "--",
+];
+
+/// Name given to the wrapper shell, as `$0`, so that it's recognizable in logs and process lists.
+const WRAPPER_NAME: &str = "cargo-mutants-sandbox";
+
+/// Shell script run inside the namespaces to set up the sandbox, and then exec the command.
+///
+/// The shell's working directory is still the original build directory after `/tmp` is
+/// covered up, so `/proc/self/cwd` can be used to mount it back at the same path.
+const SETUP_SCRIPT: &str = r#"set -e
+mount -t tmpfs cargo-mutants-tmp /tmp
+case "$PWD" in
+/tmp/*)
+    mkdir -p "$PWD"
+    mount --no-canonicalize --bind /proc/self/cwd "$PWD"
+    cd "$PWD"
+    ;;
+esac
+ip link set lo up 2>/dev/null || true
+export TMPDIR=/tmp CARGO_NET_OFFLINE=true
+exec "$@""#;
+
+/// Return an argv that runs the given argv inside the sandbox.
+pub fn sandbox_argv(argv: &[String]) -> Vec<String> {
+    UNSHARE_ARGV
+        .iter()
+        .chain(&["sh", "-c", SETUP_SCRIPT, WRAPPER_NAME])
+        .map(|s| s.to_string())
+        .chain(argv.iter().cloned())
+        .collect()
+}
+
+/// Check that the sandbox can be created on this machine, so that we don't only find
+/// out after every test fails.
+#[cfg(target_os = "linux")]
+pub fn check_sandbox_available(cwd: &Utf8Path) -> Result<()> {
+    use anyhow::Context;
+
+    use crate::process::get_command_output;
+
+    let argv = sandbox_argv(&["true".to_owned()]);
+    let argv = argv.iter().map(String::as_str).collect::<Vec<_>>();
+    get_command_output(&argv, cwd)
+        .map(|_| ())
+        .context("The test sandbox needs `unshare` and unprivileged user namespaces")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn check_sandbox_available(_cwd: &Utf8Path) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "The test sandbox is only supported on Linux"
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sandbox_argv_runs_command_through_unshare() {
+        let argv = sandbox_argv(&["cargo".to_owned(), "test".to_owned()]);
+        assert_eq!(
+            argv[..6],
+            [
+                "unshare",
+                "--user",
+                "--map-root-user",
+                "--net",
+                "--mount",
+                "--"
+            ]
+        );
+        assert_eq!(argv[6..8], ["sh", "-c"]);
+        assert_eq!(argv[8], SETUP_SCRIPT);
+        assert_eq!(argv[9..], [WRAPPER_NAME, "cargo", "test"]);
+    }
+}
@@ -1,8 +1,9 @@
-// Copyright 2021-2023 Martin Pool
+// Copyright 2021-2024 Martin Pool
 
 use serde::Serialize;
 use std::fmt;
 
+use crate::schema::Schema;
 use crate::Mutant;
 
 /// A scenario is either a freshening build in the source tree, a baseline test with no mutations, or a mutation test.
@@ -12,6 +13,8 @@ pub enum Scenario {
     Baseline,
     /// Build with a mutation applied.
     Mutant(Mutant),
+    /// Build with several mutants compiled in, to later test them one at a time.
+    Schema(Schema),
 }
 
 impl fmt::Display for Scenario {
@@ -19,6 +22,12 @@ impl fmt::Display for Scenario {
         match self {
             Scenario::Baseline => f.write_str("baseline"),
             Scenario::Mutant(mutant) => f.write_str(&mutant.name(true, false)),
+            Scenario::Schema(schema) => write!(
+                f,
+                "schema of {} mutants in {}",
+                schema.mutants.len(),
+                schema.source_file.tree_relative_slashes()
+            ),
         }
     }
 }
@@ -31,7 +40,7 @@ impl Scenario {
     /// Return a reference to the mutant, if there is one.
     pub fn mutant(&self) -> Option<&Mutant> {
         match self {
-            Scenario::Baseline => None,
+            Scenario::Baseline | Scenario::Schema(_) => None,
             Scenario::Mutant(mutant) => Some(mutant),
         }
     }
@@ -40,6 +49,9 @@ impl Scenario {
         match self {
             Scenario::Baseline => "baseline".into(),
             Scenario::Mutant(mutant) => mutant.log_file_name_base(),
+            Scenario::Schema(schema) => {
+                format!("{}_schema", schema.source_file.tree_relative_slashes())
+            }
         }
     }
 }
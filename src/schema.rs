@@ -0,0 +1,322 @@
+// Copyright 2024 Martin Pool
+
+//! Mutant schemata: compile several mutants from one file into a single build,
+//! and choose which one is active at runtime.
+//!
+//! Each mutant in a schema is inserted behind a runtime test of an environment
+//! variable, so that one `cargo build` can serve to test many mutants, by running
+//! the tests repeatedly with different values of the variable.
+//!
+//! Mutants that might not compile when combined with each other, or that can't
+//! be expressed as a runtime switch, are left to be tested one at a time. If the
+//! schema as a whole fails to build, its mutants are also tested one at a time.
+
+use std::fs;
+
+use anyhow::{ensure, Context};
+use itertools::Itertools;
+use serde::ser::{SerializeStruct, Serializer};
+use serde::Serialize;
+use tracing::{error, trace};
+
+use crate::build_dir::BuildDir;
+use crate::mutate::{Genre, Mutant};
+use crate::source::SourceFile;
+use crate::span::{LineColumn, Span};
+use crate::{Result, MUTATION_MARKER_COMMENT};
+
+/// The environment variable that selects which mutant in a schema is active.
+///
+/// The value is the 1-based index of the mutant within the schema; if it's unset or
+/// doesn't match any mutant, the original code runs.
+pub const ACTIVE_MUTANT_ENV: &str = "CARGO_MUTANTS_ACTIVE_MUTANT";
+
+/// The name of the static, in each switch, holding the index read from [ACTIVE_MUTANT_ENV].
+const ACTIVE_MUTANT_STATIC: &str = "CARGO_MUTANTS_ACTIVE_MUTANT";
+
+/// The name of the local variable holding the active index within each switch, chosen to
+/// be unlikely to shadow anything in the original code.
+const ACTIVE_MUTANT_LOCAL: &str = "__cargo_mutants_active_mutant";
+
+/// Several mutants from one source file, built together.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Schema {
+    /// The file containing all the mutants.
+    pub source_file: SourceFile,
+
+    /// The mutants, ordered by their position in the file.
+    pub mutants: Vec<Mutant>,
+}
+
+impl Schema {
+    /// Return the text of the whole file with every mutant inserted behind a
+    /// runtime switch.
+    pub fn mutated_code(&self) -> String {
+        let mut code = self.source_file.code().to_owned();
+        let spans = self.mutants.iter().map(|m| m.span).dedup().collect_vec();
+        // Replace spans from the end of the file backwards, so that the line and
+        // column positions of earlier spans are still valid.
+        for span in spans.into_iter().rev() {
+            // The variable is read once, by the first switch that runs, rather than every
+            // time the mutated code runs, which might be in a hot loop.
+            let mut switch = format!(
+                "{{ static {ACTIVE_MUTANT_STATIC}: ::std::sync::OnceLock<usize> = ::std::sync::OnceLock::new(); \
+                let {ACTIVE_MUTANT_LOCAL} = *{ACTIVE_MUTANT_STATIC}.get_or_init(|| \
+                ::std::env::var({ACTIVE_MUTANT_ENV:?}).ok().and_then(|v| v.parse().ok()).unwrap_or(0)); "
+            );
+            for (i, mutant) in self
+                .mutants
+                .iter()
+                .enumerate()
+                .filter(|(_, m)| m.span == span)
+            {
+                switch.push_str(&format!(
+                    "if {ACTIVE_MUTANT_LOCAL} == {id} {{ {replacement} {MUTATION_MARKER_COMMENT} }} else ",
+                    id = i + 1,
+                    replacement = mutant.replacement,
+                ));
+            }
+            switch.push_str(&format!("{{ {} }} }}", span.extract(&code)));
+            code = span.replace(&code, &switch);
+        }
+        code
+    }
+
+    /// Return the environment variables that activate the mutant at `index` within
+    /// this schema.
+    pub fn active_env(&self, index: usize) -> Vec<(String, String)> {
+        vec![(ACTIVE_MUTANT_ENV.to_owned(), (index + 1).to_string())]
+    }
+
+    /// Write the schema into a build directory, until the result is dropped.
+    pub fn apply<'a>(&'a self, build_dir: &'a BuildDir) -> Result<AppliedSchema<'a>> {
+        trace!(path = ?self.source_file.tree_relative_path, "Apply schema");
+        self.write_in_dir(build_dir, &self.mutated_code())?;
+        Ok(AppliedSchema {
+            schema: self,
+            build_dir,
+        })
+    }
+
+    fn write_in_dir(&self, build_dir: &BuildDir, code: &str) -> Result<()> {
+        let path = build_dir.path().join(&self.source_file.tree_relative_path);
+        // for safety, don't follow symlinks
+        ensure!(path.is_file(), "{path:?} is not a file");
+        fs::write(&path, code.as_bytes())
+            .with_context(|| format!("failed to write schema code to {path:?}"))
+    }
+}
+
+impl Serialize for Schema {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut ss = serializer.serialize_struct("Schema", 2)?;
+        ss.serialize_field("file", &self.source_file.tree_relative_slashes())?;
+        ss.serialize_field("mutants", &self.mutants)?;
+        ss.end()
+    }
+}
+
+/// Restores the original source when dropped.
+#[must_use]
+pub struct AppliedSchema<'a> {
+    schema: &'a Schema,
+    build_dir: &'a BuildDir,
+}
+
+impl Drop for AppliedSchema<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self
+            .schema
+            .write_in_dir(self.build_dir, self.schema.source_file.code())
+        {
+            error!("Failed to unapply schema: {}", e);
+        }
+    }
+}
+
+/// Divide mutants into schemata, each holding several mutants from one file,
+/// and the remaining mutants that must be tested one at a time.
+///
+/// At present only mutants that replace a whole function body are combined,
+/// and only when that body does not overlap any other mutated body, such as
+/// when functions are nested.
+pub fn plan_schemata(mutants: Vec<Mutant>) -> (Vec<Schema>, Vec<Mutant>) {
+    let mut by_file: Vec<Vec<Mutant>> = Vec::new();
+    let mut singles = Vec::new();
+    for mutant in mutants {
+        if mutant.genre != Genre::FnValue {
+            singles.push(mutant);
+        } else if let Some(group) = by_file
+            .iter_mut()
+            .find(|g| g[0].source_file.tree_relative_path == mutant.source_file.tree_relative_path)
+        {
+            group.push(mutant);
+        } else {
+            by_file.push(vec![mutant]);
+        }
+    }
+    let mut schemata = Vec::new();
+    for group in by_file {
+        let (mut combined, mut separate): (Vec<Mutant>, Vec<Mutant>) =
+            group.iter().cloned().partition(|m| {
+                !group
+                    .iter()
+                    .any(|other| other.span != m.span && spans_overlap(&other.span, &m.span))
+            });
+        if combined.len() < 2 {
+            singles.append(&mut combined);
+        } else {
+            combined.sort_by_key(|m| position(m.span.start));
+            schemata.push(Schema {
+                source_file: combined[0].source_file.clone(),
+                mutants: combined,
+            });
+        }
+        singles.append(&mut separate);
+    }
+    (schemata, singles)
+}
+
+fn position(lc: LineColumn) -> (usize, usize) {
+    (lc.line, lc.column)
+}
+
+fn spans_overlap(a: &Span, b: &Span) -> bool {
+    position(a.start) < position(b.end) && position(b.start) < position(a.end)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use camino::Utf8PathBuf;
+    use indoc::indoc;
+    use itertools::Itertools;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::mutate::Function;
+    use crate::package::Package;
+
+    fn source_file(code: &str) -> SourceFile {
+        SourceFile {
+            code: Arc::new(code.to_owned()),
+            package: Arc::new(Package {
+                name: "unimportant".to_owned(),
+                relative_manifest_path: "Cargo.toml".into(),
+                features: Default::default(),
+            }),
+            tree_relative_path: Utf8PathBuf::from("src/lib.rs"),
+            is_top: true,
+        }
+    }
+
+    fn mutant(source_file: &SourceFile, span: Span, replacement: &str, genre: Genre) -> Mutant {
+        Mutant {
+            source_file: source_file.clone(),
+            function: Some(Arc::new(Function {
+                function_name: "f".to_owned(),
+                return_type: "-> u32".to_owned(),
+                span,
+                has_doc_example: false,
+                mentioned_in_tests: false,
+            })),
+            span,
+            replacement: replacement.to_owned(),
+            genre,
+            cfg: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn schema_code_switches_between_mutants() {
+        let source_file = source_file(indoc! { "
+            fn one() -> u32 {
+                1
+            }
+
+            fn two() -> u32 {
+                2
+            }
+        "});
+        let body_one = Span::quad(2, 5, 2, 6);
+        let body_two = Span::quad(6, 5, 6, 6);
+        let (schemata, singles) = plan_schemata(vec![
+            mutant(&source_file, body_one, "0", Genre::FnValue),
+            mutant(&source_file, body_one, "3", Genre::FnValue),
+            mutant(&source_file, body_two, "0", Genre::FnValue),
+        ]);
+        assert!(singles.is_empty());
+        assert_eq!(schemata.len(), 1);
+        assert_eq!(
+            schemata[0].mutated_code(),
+            indoc! { r#"
+                fn one() -> u32 {
+                    { static CARGO_MUTANTS_ACTIVE_MUTANT: ::std::sync::OnceLock<usize> = ::std::sync::OnceLock::new(); let __cargo_mutants_active_mutant = *CARGO_MUTANTS_ACTIVE_MUTANT.get_or_init(|| ::std::env::var("CARGO_MUTANTS_ACTIVE_MUTANT").ok().and_then(|v| v.parse().ok()).unwrap_or(0)); if __cargo_mutants_active_mutant == 1 { 0 /* ~ changed by cargo-mutants ~ */ } else if __cargo_mutants_active_mutant == 2 { 3 /* ~ changed by cargo-mutants ~ */ } else { 1 } }
+                }
+
+                fn two() -> u32 {
+                    { static CARGO_MUTANTS_ACTIVE_MUTANT: ::std::sync::OnceLock<usize> = ::std::sync::OnceLock::new(); let __cargo_mutants_active_mutant = *CARGO_MUTANTS_ACTIVE_MUTANT.get_or_init(|| ::std::env::var("CARGO_MUTANTS_ACTIVE_MUTANT").ok().and_then(|v| v.parse().ok()).unwrap_or(0)); if __cargo_mutants_active_mutant == 3 { 0 /* ~ changed by cargo-mutants ~ */ } else { 2 } }
+                }
+            "# }
+        );
+        assert_eq!(
+            schemata[0].active_env(2),
+            [("CARGO_MUTANTS_ACTIVE_MUTANT".to_owned(), "3".to_owned())]
+        );
+    }
+
+    #[test]
+    fn overlapping_and_binary_mutants_are_tested_separately() {
+        let source_file = source_file(indoc! { "
+            fn outer() -> u32 {
+                fn inner() -> u32 { 1 + 2 }
+                inner()
+            }
+
+            fn other() -> u32 {
+                2
+            }
+        "});
+        let outer_body = Span::quad(2, 5, 3, 12);
+        let inner_body = Span::quad(2, 25, 2, 30);
+        let add_op = Span::quad(2, 27, 2, 28);
+        let other_body = Span::quad(7, 5, 7, 6);
+        let (schemata, singles) = plan_schemata(vec![
+            mutant(&source_file, outer_body, "0", Genre::FnValue),
+            mutant(&source_file, inner_body, "0", Genre::FnValue),
+            mutant(&source_file, add_op, "-", Genre::BinaryOperator),
+            mutant(&source_file, other_body, "0", Genre::FnValue),
+            mutant(&source_file, other_body, "1", Genre::FnValue),
+        ]);
+        assert_eq!(schemata.len(), 1);
+        assert_eq!(
+            schemata[0]
+                .mutants
+                .iter()
+                .map(|m| &m.replacement)
+                .collect_vec(),
+            ["0", "1"]
+        );
+        assert_eq!(
+            singles.iter().map(|m| m.span).collect_vec(),
+            [add_op, outer_body, inner_body]
+        );
+    }
+
+    #[test]
+    fn single_mutant_in_file_is_not_a_schema() {
+        let source_file = source_file("fn one() -> u32 {\n    1\n}\n");
+        let (schemata, singles) = plan_schemata(vec![mutant(
+            &source_file,
+            Span::quad(2, 5, 2, 6),
+            "0",
+            Genre::FnValue,
+        )]);
+        assert!(schemata.is_empty());
+        assert_eq!(singles.len(), 1);
+    }
+}
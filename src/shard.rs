@@ -2,9 +2,17 @@
 
 //! Sharding parameters.
 
+use std::collections::HashMap;
+use std::fs::read_to_string;
 use std::str::FromStr;
 
-use anyhow::{anyhow, ensure, Context, Error};
+use anyhow::{anyhow, ensure, Context, Error, Result};
+use camino::Utf8Path;
+use serde_json::Value;
+use strum::{Display, EnumString};
+use tracing::debug;
+
+use crate::mutate::{package_sizes, Mutant};
 
 /// Select mutants for a particular shard of the total list.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -24,6 +32,196 @@ impl Shard {
             .filter_map(|(i, m)| if i % self.n == self.k { Some(m) } else { None })
             .collect()
     }
+
+    /// Select the mutants for this shard so that every shard has about the same total cost.
+    ///
+    /// `costs` gives the estimated cost of each mutant, in the same order. Every shard must
+    /// be given the same mutants and costs, so that they agree on the assignment.
+    pub fn select_by_cost<M, I: IntoIterator<Item = M>>(
+        &self,
+        mutants: I,
+        costs: &[f64],
+    ) -> Vec<M> {
+        // Assign the most expensive mutants first, each to the shard with the least work
+        // so far. Ties are broken by index so the result is deterministic.
+        let mut by_cost = (0..costs.len()).collect::<Vec<usize>>();
+        by_cost.sort_by(|&a, &b| costs[b].total_cmp(&costs[a]).then(a.cmp(&b)));
+        let mut shard_totals = vec![0.0f64; self.n];
+        let mut assignment = vec![0; costs.len()];
+        for i in by_cost {
+            let (lightest, _) = shard_totals
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                .expect("n > 0");
+            assignment[i] = lightest;
+            shard_totals[lightest] += costs[i];
+        }
+        mutants
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, m)| (assignment.get(i) == Some(&self.k)).then_some(m))
+            .collect()
+    }
+
+    /// Select the mutants for this shard by a hash of their file, function, and
+    /// replacement, so that each mutant stays in the same shard when other code changes.
+    ///
+    /// Shards will have roughly, but not exactly, the same number of mutants.
+    pub fn select_by_hash(&self, mutants: Vec<Mutant>) -> Vec<Mutant> {
+        mutants
+            .into_iter()
+            .filter(|m| mutant_hash(m) % self.n as u64 == self.k as u64)
+            .collect()
+    }
+}
+
+/// A hash of the parts of a mutant that don't change when other code moves around.
+///
+/// This uses FNV-1a rather than the standard library's hasher, whose output is not
+/// guaranteed to be the same across Rust versions or platforms.
+fn mutant_hash(mutant: &Mutant) -> u64 {
+    let function_name = mutant
+        .function
+        .as_ref()
+        .map_or("", |f| f.function_name.as_str());
+    fnv1a(
+        [
+            mutant.source_file.tree_relative_slashes().as_str(),
+            function_name,
+            mutant.replacement.as_str(),
+        ]
+        .join("\0")
+        .as_bytes(),
+    )
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// How to divide mutants between shards.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum ShardStrategy {
+    /// Take every n-th mutant, so that all shards have the same number of mutants.
+    #[default]
+    Index,
+
+    /// Balance the estimated time to test the mutants in each shard.
+    Cost,
+
+    /// Assign mutants by a hash of their file, function, and replacement, so that they
+    /// stay in the same shard as the code changes.
+    Hash,
+}
+
+/// Estimate the relative cost of testing each mutant.
+///
+/// If `previous` is given, it's the `outcomes.json` from an earlier run, or a directory
+/// containing one, and the cost is the time spent testing the same mutant in that run.
+/// Mutants that weren't tested there are estimated from the average for the same
+/// package, or else the average overall.
+///
+/// Without any previous timings, the cost is the size of the source for the package
+/// containing the mutant, since larger packages tend to take longer to build and test.
+pub fn estimate_costs(mutants: &[Mutant], previous: Option<&Utf8Path>) -> Result<Vec<f64>> {
+    let timings = match previous {
+        Some(path) => PreviousTimings::read(path)?,
+        None => PreviousTimings::default(),
+    };
+    if timings.by_mutant.is_empty() {
+        let sizes = package_sizes(mutants);
+        return Ok(mutants
+            .iter()
+            .map(|m| sizes[m.package_name()] as f64)
+            .collect());
+    }
+    Ok(mutants.iter().map(|m| timings.estimate(m)).collect())
+}
+
+/// Time spent testing each mutant in a previous run.
+#[derive(Debug, Default)]
+struct PreviousTimings {
+    /// Seconds for each mutant, keyed by [timing_key].
+    by_mutant: HashMap<String, f64>,
+    /// Mean seconds per mutant in each package.
+    package_mean: HashMap<String, f64>,
+    /// Mean seconds per mutant overall.
+    mean: f64,
+}
+
+impl PreviousTimings {
+    fn read(path: &Utf8Path) -> Result<PreviousTimings> {
+        let path = if path.is_dir() {
+            path.join("outcomes.json")
+        } else {
+            path.to_owned()
+        };
+        let outcomes: Value = serde_json::from_str(
+            &read_to_string(&path).with_context(|| format!("read previous outcomes {path}"))?,
+        )
+        .with_context(|| format!("parse previous outcomes {path}"))?;
+        Ok(PreviousTimings::from_outcomes(&outcomes))
+    }
+
+    fn from_outcomes(outcomes: &Value) -> PreviousTimings {
+        let mut by_mutant = HashMap::new();
+        let mut package_totals: HashMap<String, (f64, usize)> = HashMap::new();
+        for outcome in outcomes["outcomes"].as_array().into_iter().flatten() {
+            let Some(mutant) = outcome["scenario"].get("Mutant") else {
+                continue;
+            };
+            let seconds = outcome["phase_results"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|phase| phase["duration"].as_f64())
+                .sum::<f64>();
+            let package = mutant["package"].as_str().unwrap_or_default().to_owned();
+            let total = package_totals.entry(package).or_default();
+            total.0 += seconds;
+            total.1 += 1;
+            by_mutant.insert(timing_key(mutant), seconds);
+        }
+        let mean = if by_mutant.is_empty() {
+            0.0
+        } else {
+            by_mutant.values().sum::<f64>() / by_mutant.len() as f64
+        };
+        debug!(n_mutants = by_mutant.len(), mean, "read previous timings");
+        PreviousTimings {
+            by_mutant,
+            package_mean: package_totals
+                .into_iter()
+                .map(|(package, (seconds, count))| (package, seconds / count as f64))
+                .collect(),
+            mean,
+        }
+    }
+
+    fn estimate(&self, mutant: &Mutant) -> f64 {
+        let key = timing_key(&serde_json::to_value(mutant).expect("serialize mutant"));
+        self.by_mutant
+            .get(&key)
+            .or_else(|| self.package_mean.get(mutant.package_name()))
+            .copied()
+            .unwrap_or(self.mean)
+    }
+}
+
+/// Identify a serialized mutant by its file, function, replacement, and span, so that
+/// timings still match if other fields are added or changed between versions.
+fn timing_key(mutant: &Value) -> String {
+    serde_json::json!([
+        mutant["file"],
+        mutant["function"]["function_name"],
+        mutant["replacement"],
+        mutant["span"],
+    ])
+    .to_string()
 }
 
 impl FromStr for Shard {
@@ -75,6 +273,92 @@ mod tests {
         );
     }
 
+    #[test]
+    fn shard_strategy_from_str() {
+        assert_eq!(
+            ShardStrategy::from_str("cost").unwrap(),
+            ShardStrategy::Cost
+        );
+        assert_eq!(
+            ShardStrategy::from_str("index").unwrap(),
+            ShardStrategy::Index
+        );
+        assert_eq!(
+            ShardStrategy::from_str("hash").unwrap(),
+            ShardStrategy::Hash
+        );
+        assert!(ShardStrategy::from_str("size").is_err());
+    }
+
+    #[test]
+    fn select_by_cost_balances_shards() {
+        let costs = [10.0, 1.0, 1.0, 1.0, 5.0, 4.0, 1.0, 1.0];
+        let shards = (0..2)
+            .map(|k| Shard { k, n: 2 }.select_by_cost(0..costs.len(), &costs))
+            .collect::<Vec<_>>();
+        assert_eq!(shards[0], [0, 2, 6]);
+        assert_eq!(shards[1], [1, 3, 4, 5, 7]);
+        for shard in &shards {
+            assert_eq!(shard.iter().map(|&i| costs[i]).sum::<f64>(), 12.0);
+        }
+    }
+
+    #[test]
+    fn previous_timings_estimate_from_mutant_package_or_overall_mean() {
+        let timings = PreviousTimings::from_outcomes(&serde_json::json!({
+            "outcomes": [
+                {
+                    "scenario": "Baseline",
+                    "phase_results": [{"phase": "Build", "duration": 100.0}],
+                },
+                {
+                    "scenario": {"Mutant": {
+                        "package": "a",
+                        "file": "src/lib.rs",
+                        "function": {"function_name": "f", "return_type": ""},
+                        "span": {"start": {"line": 1, "column": 1}, "end": {"line": 1, "column": 2}},
+                        "replacement": "x",
+                        "genre": "FnValue",
+                    }},
+                    "phase_results": [
+                        {"phase": "Build", "duration": 2.0},
+                        {"phase": "Test", "duration": 4.0},
+                    ],
+                },
+                {
+                    "scenario": {"Mutant": {"package": "a", "replacement": "y"}},
+                    "phase_results": [{"phase": "Build", "duration": 2.0}],
+                },
+                {
+                    "scenario": {"Mutant": {"package": "b", "replacement": "z"}},
+                    "phase_results": [{"phase": "Build", "duration": 7.0}],
+                },
+            ]
+        }));
+        assert_eq!(timings.by_mutant.len(), 3);
+        // Fields other than the file, function, replacement, and span don't matter.
+        let key = timing_key(&serde_json::json!({
+            "package": "a",
+            "file": "src/lib.rs",
+            "function": {"function_name": "f", "return_type": "-> bool"},
+            "span": {"start": {"line": 1, "column": 1}, "end": {"line": 1, "column": 2}},
+            "replacement": "x",
+            "genre": "BinaryOperator",
+            "cfg": [],
+        }));
+        assert_eq!(timings.by_mutant[&key], 6.0);
+        assert_eq!(timings.package_mean["a"], 4.0);
+        assert_eq!(timings.package_mean["b"], 7.0);
+        assert_eq!(timings.mean, 5.0);
+    }
+
+    #[test]
+    fn fnv1a_matches_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+
     #[test]
     fn shard_select() {
         assert_eq!(
@@ -101,6 +101,7 @@ mod test {
             &Arc::new(Package {
                 name: "imaginary-package".to_owned(),
                 relative_manifest_path: "whatever/Cargo.toml".into(),
+                features: Default::default(),
             }),
             true,
         )
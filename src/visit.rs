@@ -7,19 +7,21 @@
 //! e.g. for cargo they are identified from the targets. The tree walker then
 //! follows `mod` statements to recursively visit other referenced files.
 
-use std::collections::VecDeque;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use std::vec;
 
 use anyhow::Context;
-use proc_macro2::{Ident, TokenStream};
+use proc_macro2::{Delimiter, Ident, Spacing, TokenStream, TokenTree};
 use quote::{quote, ToTokens};
 use syn::ext::IdentExt;
 use syn::spanned::Spanned;
 use syn::visit::Visit;
 use syn::{Attribute, BinOp, Block, Expr, ItemFn, ReturnType, Signature};
-use tracing::{debug, debug_span, trace, trace_span, warn};
+use tracing::{debug, debug_span, info, trace, trace_span, warn};
 
+use crate::cfg::{enabled, Cfg, TargetCfg};
+use crate::console::plural;
 use crate::fnvalue::return_type_replacements;
 use crate::mutate::Function;
 use crate::pretty::ToPrettyString;
@@ -52,25 +54,43 @@ pub fn walk_tree(
         .map(|e| syn::parse_str(e).with_context(|| format!("Failed to parse error value {e:?}")))
         .collect::<Result<Vec<Expr>>>()?;
     console.walk_tree_start();
-    let mut file_queue: VecDeque<SourceFile> = top_source_files.iter().cloned().collect();
+    // Each file is queued along with the `cfg` predicates on the `mod` statements that lead to it.
+    let mut file_queue: VecDeque<(SourceFile, Vec<Cfg>)> = top_source_files
+        .iter()
+        .map(|source_file| (source_file.clone(), Vec::new()))
+        .collect();
     let mut mutants = Vec::new();
     let mut files: Vec<SourceFile> = Vec::new();
-    while let Some(source_file) = file_queue.pop_front() {
+    // Names mentioned by test code, by package name.
+    let mut test_mentions: HashMap<String, TestMentions> = HashMap::new();
+    while let Some((source_file, cfg)) = file_queue.pop_front() {
         console.walk_tree_update(files.len(), mutants.len());
         check_interrupted()?;
-        let (mut file_mutants, external_mods) = walk_file(&source_file, &error_exprs)?;
+        let FileDiscovery {
+            mutants: mut file_mutants,
+            external_mods,
+            test_mentions: file_test_mentions,
+            external_test_mods,
+        } = walk_file(&source_file, &cfg, &error_exprs)?;
         // We'll still walk down through files that don't match globs, so that
         // we have a chance to find modules underneath them. However, we won't
         // collect any mutants from them, and they don't count as "seen" for
         // `--list-files`.
-        for mod_name in &external_mods {
-            if let Some(mod_path) = find_mod_source(workspace_dir, &source_file, mod_name)? {
-                file_queue.push_back(SourceFile::new(
-                    workspace_dir,
-                    mod_path,
-                    &source_file.package,
-                    false,
-                )?)
+        for (mod_name, mod_cfg) in external_mods {
+            if let Some(mod_path) = find_mod_source(workspace_dir, &source_file, &mod_name)? {
+                file_queue.push_back((
+                    SourceFile::new(workspace_dir, mod_path, &source_file.package, false)?,
+                    mod_cfg,
+                ))
+            }
+        }
+        let package_test_mentions = test_mentions
+            .entry(source_file.package.name.clone())
+            .or_default();
+        package_test_mentions.extend(file_test_mentions);
+        for mod_name in external_test_mods {
+            if let Some(mod_path) = find_mod_source(workspace_dir, &source_file, &mod_name)? {
+                package_test_mentions.extend(mentions_in_file(&workspace_dir.join(mod_path))?);
             }
         }
         let path = &source_file.tree_relative_path;
@@ -89,20 +109,386 @@ pub fn walk_tree(
         mutants.append(&mut file_mutants);
         files.push(source_file);
     }
+    mark_functions_mentioned_in_tests(&mut mutants, workspace_dir, &mut test_mentions)?;
     mutants.retain(|m| {
         let name = m.name(true, false);
         (options.examine_names.is_empty() || options.examine_names.is_match(&name))
             && (options.exclude_names.is_empty() || !options.exclude_names.is_match(&name))
     });
+    if !options.include_inactive {
+        remove_inactive_mutants(&mut mutants, workspace_dir, options);
+    }
     console.walk_tree_done();
     Ok(Discovered { mutants, files })
 }
 
+/// Mark the functions containing mutants that are mentioned by name in the package's
+/// test code, including its integration tests in `tests/`, so that their doc examples
+/// aren't taken to be their only tests.
+///
+/// See [TestMentions::mentions] for what counts as mentioning a function.
+fn mark_functions_mentioned_in_tests(
+    mutants: &mut [Mutant],
+    workspace_dir: &Utf8Path,
+    test_mentions: &mut HashMap<String, TestMentions>,
+) -> Result<()> {
+    let mut integration_tests_read: HashSet<String> = HashSet::new();
+    // Functions are shared between mutants, and stay shared when they're replaced.
+    let mut marked: HashMap<*const Function, Arc<Function>> = HashMap::new();
+    for mutant in mutants {
+        let Some(function) = &mutant.function else {
+            continue;
+        };
+        if !function.has_doc_example {
+            continue;
+        }
+        if let Some(marked_function) = marked.get(&Arc::as_ptr(function)) {
+            mutant.function = Some(Arc::clone(marked_function));
+            continue;
+        }
+        let package = mutant.package();
+        let package_test_mentions = test_mentions.entry(package.name.clone()).or_default();
+        if integration_tests_read.insert(package.name.clone()) {
+            let tests_dir = workspace_dir
+                .join(&package.relative_manifest_path)
+                .parent()
+                .expect("manifest path has a parent")
+                .join("tests");
+            for path in rust_files_under(&tests_dir)? {
+                package_test_mentions.extend(mentions_in_file(&path)?);
+            }
+        }
+        if package_test_mentions.mentions(&function.function_name) {
+            let marked_function = Arc::new(Function {
+                mentioned_in_tests: true,
+                ..Function::clone(function)
+            });
+            marked.insert(Arc::as_ptr(function), Arc::clone(&marked_function));
+            mutant.function = Some(marked_function);
+        }
+    }
+    Ok(())
+}
+
+/// Return all the `.rs` files under a directory, which need not exist.
+fn rust_files_under(dir: &Utf8Path) -> Result<Vec<Utf8PathBuf>> {
+    let mut files = Vec::new();
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+    for entry in dir
+        .read_dir_utf8()
+        .with_context(|| format!("read directory {dir}"))?
+    {
+        let path = entry.context("read directory entry")?.into_path();
+        if path.is_dir() {
+            files.extend(rust_files_under(&path)?);
+        } else if path.extension() == Some("rs") {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Return the names mentioned anywhere in a source file.
+fn mentions_in_file(path: &Utf8Path) -> Result<TestMentions> {
+    let code = std::fs::read_to_string(path).with_context(|| format!("read {path}"))?;
+    match code.parse::<TokenStream>() {
+        Ok(tokens) => Ok(mentions_in_tokens(tokens)),
+        Err(err) => {
+            debug!(?path, ?err, "failed to tokenize test file; ignored");
+            Ok(TestMentions::default())
+        }
+    }
+}
+
+fn mentions_in_tokens(tokens: TokenStream) -> TestMentions {
+    let mut mentions = TestMentions::default();
+    mentions.add_tokens(tokens, &[]);
+    mentions
+}
+
+/// The names that test code mentions, which show which functions it probably calls.
+///
+/// Without type information, a call like `v.len()` can't be resolved to any particular
+/// `len` method, so methods are only taken to be mentioned if their type is too.
+#[derive(Debug, Default)]
+struct TestMentions {
+    /// The paths in the code, like `demo::Stack::new`, including those imported by `use`,
+    /// with the prefixes of `use` groups expanded.
+    paths: HashSet<Vec<String>>,
+    /// The names of methods called or fields accessed with `.name`.
+    methods: HashSet<String>,
+}
+
+impl TestMentions {
+    fn extend(&mut self, other: TestMentions) {
+        self.paths.extend(other.paths);
+        self.methods.extend(other.methods);
+    }
+
+    /// Collect the paths and method names from some tokens, prefixing the paths with the
+    /// path of the `use` group that contains them, if any.
+    fn add_tokens(&mut self, tokens: TokenStream, prefix: &[String]) {
+        let mut tokens = tokens.into_iter().peekable();
+        // True after a single `.`, but not after a `..` range.
+        let mut after_dot = false;
+        let mut after_joint_dot = false;
+        while let Some(token) = tokens.next() {
+            match token {
+                TokenTree::Ident(ident) if after_dot => {
+                    self.methods.insert(ident.unraw().to_string());
+                    after_dot = false;
+                }
+                TokenTree::Ident(ident) => {
+                    let mut path = prefix.to_vec();
+                    path.push(ident.unraw().to_string());
+                    // Follow `::` to the rest of the path, or to a `use` group.
+                    while matches!(tokens.peek(), Some(TokenTree::Punct(p)) if p.as_char() == ':') {
+                        tokens.next();
+                        if !matches!(tokens.peek(), Some(TokenTree::Punct(p)) if p.as_char() == ':')
+                        {
+                            break;
+                        }
+                        tokens.next();
+                        match tokens.peek() {
+                            Some(TokenTree::Ident(ident)) => {
+                                path.push(ident.unraw().to_string());
+                                tokens.next();
+                            }
+                            Some(TokenTree::Group(group))
+                                if group.delimiter() == Delimiter::Brace =>
+                            {
+                                self.add_tokens(group.stream(), &path);
+                                tokens.next();
+                                break;
+                            }
+                            _ => break,
+                        }
+                    }
+                    self.paths.insert(path);
+                }
+                TokenTree::Group(group) => {
+                    self.add_tokens(group.stream(), &[]);
+                    after_dot = false;
+                }
+                TokenTree::Punct(punct) => {
+                    let is_dot = punct.as_char() == '.';
+                    after_dot = is_dot && punct.spacing() == Spacing::Alone && !after_joint_dot;
+                    after_joint_dot = is_dot && punct.spacing() == Spacing::Joint;
+                    continue;
+                }
+                TokenTree::Literal(_) => after_dot = false,
+            }
+            after_joint_dot = false;
+        }
+    }
+
+    /// True if the test code mentions a function, given its name qualified by the
+    /// modules and types that contain it, like `stack::Stack::push`.
+    ///
+    /// The function is mentioned by a path that ends with its own name and the name of
+    /// its type or module, like `Stack::push` or `use crate::stack::Stack::push`. It's also
+    /// mentioned if the test code names its type or module anywhere, for example by
+    /// importing it, and calls a function or method with the same name: so, `push` on
+    /// `Stack` is mentioned by `let mut s = Stack::default(); s.push(1);`. A function
+    /// that's not inside a type or an inline module is mentioned by any path ending in its name.
+    fn mentions(&self, function_name: &str) -> bool {
+        let (qualifier, name) = match function_name.rsplit_once("::") {
+            Some((namespace, name)) => (Some(namespace_name(namespace)), name),
+            None => (None, function_name),
+        };
+        match qualifier {
+            None => self
+                .paths
+                .iter()
+                .any(|path| path.last().is_some_and(|last| last == name)),
+            Some(qualifier) => {
+                let qualified = [qualifier.to_owned(), name.to_owned()];
+                let qualifier_named = || {
+                    self.paths
+                        .iter()
+                        .any(|path| path.iter().any(|segment| segment == qualifier))
+                };
+                let name_called =
+                    || self.methods.contains(name) || self.paths.contains(&qualified[1..]);
+                self.paths.iter().any(|path| path.ends_with(&qualified))
+                    || (qualifier_named() && name_called())
+            }
+        }
+    }
+}
+
+/// Return the name by which test code would refer to the innermost module or type in a
+/// function's namespace, like `Stack` from `stack::Stack<T>` or
+/// `stack::<impl Display for Stack<T>>`.
+fn namespace_name(namespace: &str) -> &str {
+    let innermost = without_generics(namespace);
+    let type_name = if innermost.is_empty() || innermost.ends_with("::") {
+        // The innermost part is a trait impl, like `<impl Display for Stack<T>>`.
+        let impl_body = &namespace[innermost.len() + 1..namespace.len() - 1];
+        let self_type = impl_body
+            .rsplit_once(" for ")
+            .map_or(impl_body, |(_, self_type)| self_type);
+        without_generics(self_type.trim())
+    } else {
+        innermost
+    };
+    type_name
+        .rsplit("::")
+        .next()
+        .expect("rsplit returns at least one part")
+}
+
+/// Remove the generic arguments at the end of a name, like the `<T>` of `Stack<T>`.
+fn without_generics(name: &str) -> &str {
+    if !name.ends_with('>') {
+        return name;
+    }
+    let mut depth = 0;
+    for (i, c) in name.char_indices().rev() {
+        match c {
+            '>' => depth += 1,
+            '<' => {
+                depth -= 1;
+                if depth == 0 {
+                    return &name[..i];
+                }
+            }
+            _ => (),
+        }
+    }
+    name
+}
+
+/// Remove mutants in code that the `cfg` attributes on the items containing it show isn't
+/// compiled for the target with any of the feature sets.
+///
+/// The features of each package include those that other packages turn on for it,
+/// including through dev-dependencies, since Cargo unifies them when building the tests.
+fn remove_inactive_mutants(mutants: &mut Vec<Mutant>, workspace_dir: &Utf8Path, options: &Options) {
+    if mutants.iter().all(|mutant| mutant.cfg.is_empty()) {
+        return;
+    }
+    let target_cfg = TargetCfg::from_rustc(workspace_dir, options);
+    let feature_set_options = options.feature_set_options();
+    let features_known = !options.cargo_args_select_features();
+    let unified_features = if features_known {
+        feature_set_options
+            .iter()
+            .map(|options| {
+                unified_features(workspace_dir, &options.features).unwrap_or_else(|err| {
+                    warn!("Failed to find the features turned on by dependencies: {err:#}");
+                    HashMap::new()
+                })
+            })
+            .collect::<Vec<_>>()
+    } else {
+        Vec::new()
+    };
+    let mut active_features: HashMap<String, Vec<BTreeSet<String>>> = HashMap::new();
+    let n_before = mutants.len();
+    mutants.retain(|mutant| {
+        let package = mutant.package();
+        let feature_sets = active_features
+            .entry(package.name.clone())
+            .or_insert_with(|| {
+                feature_set_options
+                    .iter()
+                    .enumerate()
+                    .map(|(i, options)| {
+                        let mut features = package.active_features(&options.features);
+                        if let Some(unified) = unified_features
+                            .get(i)
+                            .and_then(|unified| unified.get(&package.name))
+                        {
+                            features.extend(unified.iter().cloned());
+                        }
+                        features
+                    })
+                    .collect()
+            });
+        let active = feature_sets.iter().any(|features| {
+            enabled(&mutant.cfg, &target_cfg, features_known.then_some(features)) != Some(false)
+        });
+        if !active {
+            debug!(mutant = mutant.name(true, false), "not compiled; skipped");
+        }
+        active
+    });
+    let n_inactive = n_before - mutants.len();
+    if n_inactive > 0 {
+        info!(
+            "Skipped {} in code that isn't compiled for this target and features; use --include-inactive to mutate it",
+            plural(n_inactive, "mutant")
+        );
+    }
+}
+
+/// Return the features that Cargo turns on for each package in the workspace, when
+/// everything in it is built, including the tests, with the given feature arguments.
+///
+/// This includes features turned on by other packages that depend on it, or by its own
+/// dev-dependencies.
+fn unified_features(
+    workspace_dir: &Utf8Path,
+    features: &Features,
+) -> Result<HashMap<String, BTreeSet<String>>> {
+    let mut command = cargo_metadata::MetadataCommand::new();
+    command.manifest_path(workspace_dir.join("Cargo.toml"));
+    if features.no_default_features {
+        command.features(cargo_metadata::CargoOpt::NoDefaultFeatures);
+    }
+    if features.all_features {
+        command.features(cargo_metadata::CargoOpt::AllFeatures);
+    }
+    if !features.features.is_empty() {
+        command.features(cargo_metadata::CargoOpt::SomeFeatures(
+            features.features.clone(),
+        ));
+    }
+    check_interrupted()?;
+    let metadata = command.exec().context("run cargo metadata")?;
+    let resolve = metadata
+        .resolve
+        .as_ref()
+        .context("cargo metadata has no dependency resolution")?;
+    let workspace_members: HashSet<_> = metadata.workspace_members.iter().collect();
+    Ok(resolve
+        .nodes
+        .iter()
+        .filter(|node| workspace_members.contains(&node.id))
+        .map(|node| {
+            (
+                metadata[&node.id].name.clone(),
+                node.features.iter().cloned().collect(),
+            )
+        })
+        .collect())
+}
+
+/// What was found by walking one source file.
+struct FileDiscovery {
+    /// The mutants in the file.
+    mutants: Vec<Mutant>,
+
+    /// The names of modules referenced by `mod` statements that should be visited later,
+    /// with the predicates that apply to them.
+    external_mods: Vec<(String, Vec<Cfg>)>,
+
+    /// Names mentioned by test code in the file.
+    test_mentions: TestMentions,
+
+    /// The names of test modules referenced by `#[cfg(test)] mod` statements, whose
+    /// files are also test code.
+    external_test_mods: Vec<String>,
+}
+
 /// Find all possible mutants in a source file.
 ///
-/// Returns the mutants found, and the names of modules referenced by `mod` statements
-/// that should be visited later.
-fn walk_file(source_file: &SourceFile, error_exprs: &[Expr]) -> Result<(Vec<Mutant>, Vec<String>)> {
+/// `cfg` is the predicates that apply to the whole file, from the `mod` statement that
+/// refers to it.
+fn walk_file(source_file: &SourceFile, cfg: &[Cfg], error_exprs: &[Expr]) -> Result<FileDiscovery> {
     let _span = debug_span!("source_file", path = source_file.tree_relative_slashes()).entered();
     debug!("visit source file");
     let syn_file = syn::parse_str::<syn::File>(source_file.code())
@@ -113,10 +499,18 @@ fn walk_file(source_file: &SourceFile, error_exprs: &[Expr]) -> Result<(Vec<Muta
         mutants: Vec::new(),
         namespace_stack: Vec::new(),
         fn_stack: Vec::new(),
+        cfg_stack: vec![cfg.to_vec(), Cfg::from_attrs(&syn_file.attrs)],
         source_file: source_file.clone(),
+        test_mentions: TestMentions::default(),
+        external_test_mods: Vec::new(),
     };
     visitor.visit_file(&syn_file);
-    Ok((visitor.mutants, visitor.external_mods))
+    Ok(FileDiscovery {
+        mutants: visitor.mutants,
+        external_mods: visitor.external_mods,
+        test_mentions: visitor.test_mentions,
+        external_test_mods: visitor.external_test_mods,
+    })
 }
 
 /// `syn` visitor that recursively traverses the syntax tree, accumulating places
@@ -137,8 +531,18 @@ struct DiscoveryVisitor<'o> {
     /// The functions we're inside.
     fn_stack: Vec<Arc<Function>>,
 
-    /// The names from `mod foo;` statements that should be visited later.
-    external_mods: Vec<String>,
+    /// The predicates of `#[cfg(...)]` attributes on each of the items we're inside.
+    cfg_stack: Vec<Vec<Cfg>>,
+
+    /// The names from `mod foo;` statements that should be visited later, with the `cfg`
+    /// predicates that apply to them.
+    external_mods: Vec<(String, Vec<Cfg>)>,
+
+    /// Names mentioned by the test code that's skipped.
+    test_mentions: TestMentions,
+
+    /// The names from `#[cfg(test)] mod foo;` statements.
+    external_test_mods: Vec<String>,
 
     /// Parsed error expressions, from the config file or command line.
     error_exprs: &'o [Expr],
@@ -149,6 +553,7 @@ impl<'o> DiscoveryVisitor<'o> {
         &mut self,
         function_name: &Ident,
         return_type: &ReturnType,
+        attrs: &[Attribute],
         span: proc_macro2::Span,
     ) -> Arc<Function> {
         self.namespace_stack.push(function_name.to_string());
@@ -157,8 +562,11 @@ impl<'o> DiscoveryVisitor<'o> {
             function_name: function_name.to_owned(),
             return_type: return_type.to_pretty_string(),
             span: span.into(),
+            has_doc_example: doc_has_example(attrs),
+            mentioned_in_tests: false,
         });
         self.fn_stack.push(Arc::clone(&function));
+        self.cfg_stack.push(Cfg::from_attrs(attrs));
         function
     }
 
@@ -171,6 +579,7 @@ impl<'o> DiscoveryVisitor<'o> {
             Some(function),
             "Function stack mismatch"
         );
+        self.cfg_stack.pop().expect("cfg stack should not be empty");
     }
 
     /// Record that we generated some mutants.
@@ -181,6 +590,7 @@ impl<'o> DiscoveryVisitor<'o> {
             span,
             replacement: replacement.to_pretty_string(),
             genre,
+            cfg: self.cfg_stack.concat(),
         })
     }
 
@@ -219,15 +629,18 @@ impl<'o> DiscoveryVisitor<'o> {
         }
     }
 
-    /// Call a function with a namespace pushed onto the stack.
+    /// Call a function with a namespace, and the `cfg` predicates from its attributes,
+    /// pushed onto the stacks.
     ///
     /// This is used when recursively descending into a namespace.
-    fn in_namespace<F, T>(&mut self, name: &str, f: F) -> T
+    fn in_namespace<F, T>(&mut self, name: &str, attrs: &[Attribute], f: F) -> T
     where
         F: FnOnce(&mut Self) -> T,
     {
         self.namespace_stack.push(name.to_owned());
+        self.cfg_stack.push(Cfg::from_attrs(attrs));
         let r = f(self);
+        self.cfg_stack.pop().expect("cfg stack should not be empty");
         assert_eq!(self.namespace_stack.pop().unwrap(), name);
         r
     }
@@ -244,10 +657,15 @@ impl<'ast> Visit<'ast> for DiscoveryVisitor<'_> {
         )
         .entered();
         trace!("visit fn");
+        if attrs_are_test(&i.attrs) {
+            self.test_mentions
+                .extend(mentions_in_tokens(i.to_token_stream()));
+            return;
+        }
         if fn_sig_excluded(&i.sig) || attrs_excluded(&i.attrs) || block_is_empty(&i.block) {
             return;
         }
-        let function = self.enter_function(&i.sig.ident, &i.sig.output, i.span());
+        let function = self.enter_function(&i.sig.ident, &i.sig.output, &i.attrs, i.span());
         self.collect_fn_mutants(&i.sig, &i.block);
         syn::visit::visit_item_fn(self, i);
         self.leave_function(function);
@@ -271,7 +689,7 @@ impl<'ast> Visit<'ast> for DiscoveryVisitor<'_> {
         {
             return;
         }
-        let function = self.enter_function(&i.sig.ident, &i.sig.output, i.span());
+        let function = self.enter_function(&i.sig.ident, &i.sig.output, &i.attrs, i.span());
         self.collect_fn_mutants(&i.sig, &i.block);
         syn::visit::visit_impl_item_fn(self, i);
         self.leave_function(function);
@@ -293,7 +711,7 @@ impl<'ast> Visit<'ast> for DiscoveryVisitor<'_> {
             if block_is_empty(block) {
                 return;
             }
-            let function = self.enter_function(&i.sig.ident, &i.sig.output, i.span());
+            let function = self.enter_function(&i.sig.ident, &i.sig.output, &i.attrs, i.span());
             self.collect_fn_mutants(&i.sig, block);
             syn::visit::visit_trait_item_fn(self, i);
             self.leave_function(function);
@@ -316,7 +734,7 @@ impl<'ast> Visit<'ast> for DiscoveryVisitor<'_> {
         } else {
             type_name
         };
-        self.in_namespace(&name, |v| syn::visit::visit_item_impl(v, i));
+        self.in_namespace(&name, &i.attrs, |v| syn::visit::visit_item_impl(v, i));
     }
 
     /// Visit `trait Foo { ... }`
@@ -326,13 +744,22 @@ impl<'ast> Visit<'ast> for DiscoveryVisitor<'_> {
         if attrs_excluded(&i.attrs) {
             return;
         }
-        self.in_namespace(&name, |v| syn::visit::visit_item_trait(v, i));
+        self.in_namespace(&name, &i.attrs, |v| syn::visit::visit_item_trait(v, i));
     }
 
     /// Visit `mod foo { ... }` or `mod foo;`.
     fn visit_item_mod(&mut self, node: &'ast syn::ItemMod) {
         let mod_name = &node.ident.unraw().to_string();
         let _span = trace_span!("mod", line = node.mod_token.span.start().line, mod_name).entered();
+        if attrs_are_test(&node.attrs) {
+            trace!("test mod");
+            self.test_mentions
+                .extend(mentions_in_tokens(node.to_token_stream()));
+            if node.content.is_none() {
+                self.external_test_mods.push(mod_name.to_owned());
+            }
+            return;
+        }
         if attrs_excluded(&node.attrs) {
             trace!("mod excluded by attrs");
             return;
@@ -341,9 +768,13 @@ impl<'ast> Visit<'ast> for DiscoveryVisitor<'_> {
         // statement referring to an external file. We remember the module
         // name and then later look for the file.
         if node.content.is_none() {
-            self.external_mods.push(mod_name.to_owned());
+            let mut cfg = self.cfg_stack.concat();
+            cfg.extend(Cfg::from_attrs(&node.attrs));
+            self.external_mods.push((mod_name.to_owned(), cfg));
         }
-        self.in_namespace(mod_name, |v| syn::visit::visit_item_mod(v, node));
+        self.in_namespace(mod_name, &node.attrs, |v| {
+            syn::visit::visit_item_mod(v, node)
+        });
     }
 
     /// Visit `a op b` expressions.
@@ -470,6 +901,13 @@ fn fn_sig_excluded(sig: &syn::Signature) -> bool {
     }
 }
 
+/// True if the attrs mark test code, with `#[cfg(test)]` or `#[test]`.
+fn attrs_are_test(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .any(|attr| attr_is_cfg_test(attr) || attr_is_test(attr))
+}
+
 /// True if any of the attrs indicate that we should skip this node and everything inside it.
 fn attrs_excluded(attrs: &[Attribute]) -> bool {
     attrs
@@ -477,6 +915,66 @@ fn attrs_excluded(attrs: &[Attribute]) -> bool {
         .any(|attr| attr_is_cfg_test(attr) || attr_is_test(attr) || attr_is_mutants_skip(attr))
 }
 
+/// True if the doc comment in these attributes has a code block that rustdoc will run as
+/// a doctest.
+///
+/// Code blocks are Rust unless they name some other language, and they don't run if
+/// they're marked `ignore`, `no_run`, or `compile_fail`.
+fn doc_has_example(attrs: &[Attribute]) -> bool {
+    let mut doc = String::new();
+    for attr in attrs {
+        if let syn::Meta::NameValue(syn::MetaNameValue {
+            path,
+            value:
+                Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(lit),
+                    ..
+                }),
+            ..
+        }) = &attr.meta
+        {
+            if path.is_ident("doc") {
+                doc.push_str(&lit.value());
+                doc.push('\n');
+            }
+        }
+    }
+    let mut in_block = false;
+    for line in doc.lines() {
+        let line = line.trim_start();
+        let Some(info) = line
+            .strip_prefix("```")
+            .or_else(|| line.strip_prefix("~~~"))
+        else {
+            continue;
+        };
+        if in_block {
+            in_block = false;
+            continue;
+        }
+        in_block = true;
+        let mut is_rust = true;
+        let mut runs = true;
+        for token in info
+            .trim_matches('`')
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|t| !t.is_empty())
+        {
+            match token {
+                "rust" | "should_panic" | "test_harness" => (),
+                "no_run" | "compile_fail" => runs = false,
+                _ if token.starts_with("ignore") => runs = false,
+                _ if token.starts_with("edition") => (),
+                _ => is_rust = false,
+            }
+        }
+        if is_rust && runs {
+            return true;
+        }
+    }
+    false
+}
+
 /// True if the block (e.g. the contents of a function) is empty.
 fn block_is_empty(block: &syn::Block) -> bool {
     block.stmts.is_empty()
@@ -553,6 +1051,20 @@ mod test {
     use crate::package::Package;
     use crate::source::SourceFile;
 
+    /// Make a top-level `src/lib.rs` in package `demo` containing this code.
+    fn source_file(code: &str) -> SourceFile {
+        SourceFile {
+            code: Arc::new(code.to_owned()),
+            package: Arc::new(Package {
+                name: "demo".to_owned(),
+                relative_manifest_path: "Cargo.toml".into(),
+                features: Default::default(),
+            }),
+            tree_relative_path: Utf8PathBuf::from("src/lib.rs"),
+            is_top: true,
+        }
+    }
+
     /// We should not generate mutants that produce the same tokens as the
     /// source.
     #[test]
@@ -565,11 +1077,14 @@ mod test {
             package: Arc::new(Package {
                 name: "unimportant".to_owned(),
                 relative_manifest_path: "Cargo.toml".into(),
+                features: Default::default(),
             }),
             tree_relative_path: Utf8PathBuf::from("src/lib.rs"),
             is_top: true,
         };
-        let (mutants, _files) = walk_file(&source_file, &[]).expect("walk_file");
+        let mutants = walk_file(&source_file, &[], &[])
+            .expect("walk_file")
+            .mutants;
         let mutant_names = mutants.iter().map(|m| m.name(false, false)).collect_vec();
         // It would be good to suggest replacing this with 'false', breaking a key behavior,
         // but bad to replace it with 'true', changing nothing.
@@ -579,6 +1094,237 @@ mod test {
         );
     }
 
+    #[test]
+    fn functions_with_runnable_doc_examples_are_recognized() {
+        let code = indoc! { r#"
+            /// Doubles a number.
+            ///
+            /// ```
+            /// assert_eq!(demo::double(2), 4);
+            /// ```
+            pub fn double(x: u32) -> u32 { x * 2 }
+
+            /// ```edition2021,should_panic
+            /// demo::halve(1);
+            /// ```
+            pub fn halve(x: u32) -> u32 { x / 2 }
+
+            /// Only examples that don't run.
+            ///
+            /// ```ignore
+            /// demo::triple(1);
+            /// ```
+            ///
+            /// ```text
+            /// 3
+            /// ```
+            ///
+            /// ```no_run
+            /// demo::triple(2);
+            /// ```
+            pub fn triple(x: u32) -> u32 { x * 3 }
+
+            /// No examples at all.
+            pub fn square(x: u32) -> u32 { x * x }
+        "# };
+        let source_file = source_file(code);
+        let mutants = walk_file(&source_file, &[], &[])
+            .expect("walk_file")
+            .mutants;
+        let with_examples = mutants
+            .iter()
+            .filter_map(|m| m.function.as_ref())
+            .filter(|f| f.has_doc_example)
+            .map(|f| f.function_name.as_str())
+            .unique()
+            .collect_vec();
+        assert_eq!(with_examples, ["double", "halve"]);
+    }
+
+    #[test]
+    fn identifiers_in_test_code_are_collected() {
+        let code = indoc! { r#"
+            pub fn double(x: u32) -> u32 { x * 2 }
+
+            pub fn triple(x: u32) -> u32 { x * 3 }
+
+            #[test]
+            fn doubles() {
+                assert_eq!(double(2), 4);
+            }
+
+            #[cfg(test)]
+            mod test {
+                use super::r#triple;
+            }
+
+            #[cfg(test)]
+            mod more_tests;
+        "# };
+        let source_file = source_file(code);
+        let discovery = walk_file(&source_file, &[], &[]).expect("walk_file");
+        assert!(discovery.test_mentions.mentions("double"));
+        assert!(discovery.test_mentions.mentions("triple"));
+        assert!(!discovery.test_mentions.mentions("x"));
+        assert_eq!(discovery.external_test_mods, ["more_tests"]);
+        assert!(discovery.external_mods.is_empty());
+        assert_eq!(discovery.mutants.len(), 8);
+    }
+
+    #[test]
+    fn methods_with_common_names_are_mentioned_only_with_their_type() {
+        let code = indoc! { r#"
+            pub struct Stack(Vec<u32>);
+
+            impl Stack {
+                pub fn len(&self) -> usize { self.0.len() }
+                pub fn push(&mut self, x: u32) { self.0.push(x) }
+                pub fn get(&self, i: usize) -> Option<u32> { self.0.get(i).copied() }
+            }
+
+            pub mod queue {
+                pub struct Queue;
+                impl Queue {
+                    pub fn len(&self) -> usize { 0 }
+                    pub fn is_empty(&self) -> bool { true }
+                }
+                impl std::fmt::Display for Queue {
+                    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result { Ok(()) }
+                }
+            }
+
+            #[cfg(test)]
+            mod test {
+                use super::queue::{Queue, self};
+
+                #[test]
+                fn queue_is_empty() {
+                    let v = vec![1, 2, 3];
+                    for i in 0..v.len() {
+                        assert_eq!(v.get(i), Some(&(i as u32 + 1)));
+                    }
+                    assert!(Queue.is_empty());
+                }
+            }
+        "# };
+        let discovery = walk_file(&source_file(code), &[], &[]).expect("walk_file");
+        let mentions = discovery.test_mentions;
+        // `len` and `get` are called on a `Vec`, and `Stack` isn't mentioned.
+        assert!(!mentions.mentions("Stack::len"));
+        assert!(!mentions.mentions("Stack::get"));
+        assert!(!mentions.mentions("Stack::push"));
+        // `Queue` is imported, and its methods might be the ones called.
+        assert!(mentions.mentions("queue::Queue::is_empty"));
+        assert!(mentions.mentions("queue::Queue::len"));
+        assert!(!mentions.mentions("queue::<impl Display for Queue>::fmt"));
+    }
+
+    #[test]
+    fn methods_are_mentioned_by_qualified_paths_and_use_groups() {
+        let mentions = mentions_in_tokens(quote! {
+            use demo::stack::{Stack::push, queue::{Queue}};
+            #[test]
+            fn t() {
+                let s = demo::Stack::<u32>::default();
+                let n = other::Counter::count();
+            }
+        });
+        assert!(mentions.mentions("stack::Stack::push"));
+        assert!(!mentions.mentions("queue::<impl Display for Queue>::fmt"));
+        assert!(mentions.mentions("Counter::count"));
+        assert!(!mentions.mentions("Counter::reset"));
+        assert!(mentions.mentions("<impl Default for Stack<T>>::default"));
+    }
+
+    #[test]
+    fn namespace_names() {
+        assert_eq!(namespace_name("Stack"), "Stack");
+        assert_eq!(namespace_name("stack::Stack<T>"), "Stack");
+        assert_eq!(namespace_name("stack::Stack<Vec<T>>"), "Stack");
+        assert_eq!(namespace_name("<impl Display for Stack<T>>"), "Stack");
+        assert_eq!(
+            namespace_name("stack::<impl Iterator for std::vec::IntoIter<u8>>"),
+            "IntoIter"
+        );
+        assert_eq!(namespace_name("stack::inner"), "inner");
+    }
+
+    #[test]
+    fn cfg_attrs_on_containing_items_are_recorded() {
+        let code = indoc! { r#"
+            #![cfg(unix)]
+
+            #[cfg(feature = "fast")]
+            mod fast {
+                pub fn go() -> u32 { 1 }
+            }
+
+            #[cfg(feature = "net")]
+            mod net;
+
+            pub struct S;
+
+            #[cfg(not(feature = "small"))]
+            impl S {
+                #[cfg(feature = "extra")]
+                pub fn extra(&self) -> bool { true }
+            }
+
+            pub fn plain() -> bool { true }
+        "# };
+        let source_file = source_file(code);
+        let parent_cfg = [Cfg::KeyValue("feature".into(), "top".into())];
+        let FileDiscovery {
+            mutants,
+            external_mods,
+            ..
+        } = walk_file(&source_file, &parent_cfg, &[]).expect("walk_file");
+        let function_cfg = mutants
+            .iter()
+            .map(|m| {
+                (
+                    m.function.as_ref().unwrap().function_name.as_str(),
+                    m.cfg
+                        .iter()
+                        .map(|cfg| format!("{cfg:?}"))
+                        .collect_vec()
+                        .join(", "),
+                )
+            })
+            .unique()
+            .collect_vec();
+        assert_eq!(
+            function_cfg,
+            [
+                (
+                    "fast::go",
+                    r#"KeyValue("feature", "top"), Name("unix"), KeyValue("feature", "fast")"#
+                        .to_owned()
+                ),
+                (
+                    "S::extra",
+                    r#"KeyValue("feature", "top"), Name("unix"), Not(KeyValue("feature", "small")), KeyValue("feature", "extra")"#
+                        .to_owned()
+                ),
+                (
+                    "plain",
+                    r#"KeyValue("feature", "top"), Name("unix")"#.to_owned()
+                ),
+            ]
+        );
+        assert_eq!(
+            external_mods,
+            [(
+                "net".to_owned(),
+                vec![
+                    Cfg::KeyValue("feature".into(), "top".into()),
+                    Cfg::Name("unix".into()),
+                    Cfg::KeyValue("feature".into(), "net".into()),
+                ]
+            )]
+        );
+    }
+
     /// As a generic protection against regressions in discovery, the the mutants
     /// generated from `cargo-mutants` own tree against a checked-in list.
     ///
@@ -589,9 +1335,15 @@ mod test {
     #[test]
     fn expected_mutants_for_own_source_tree() {
         let config = Config::read_file(Path::new("./.cargo/mutants.toml")).expect("Read config");
-        let args =
-            Args::try_parse_from(["mutants", "--list", "--line-col=false", "--colors=never"])
-                .expect("Parse args");
+        // Code for other platforms is included, so that the list is the same everywhere.
+        let args = Args::try_parse_from([
+            "mutants",
+            "--list",
+            "--line-col=false",
+            "--colors=never",
+            "--include-inactive",
+        ])
+        .expect("Parse args");
         let options = Options::new(&args, &config).expect("Build options");
         let mut list_output = String::new();
         let console = Console::new();
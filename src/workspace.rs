@@ -14,7 +14,7 @@ use crate::cargo::cargo_bin;
 use crate::console::Console;
 use crate::interrupt::check_interrupted;
 use crate::mutate::Mutant;
-use crate::options::Options;
+use crate::options::{Options, TargetKind};
 use crate::package::Package;
 use crate::process::get_command_output;
 use crate::source::SourceFile;
@@ -122,18 +122,32 @@ impl Workspace {
         Ok(Workspace { dir, metadata })
     }
 
+    /// Return Cargo's target directory for this workspace, from `cargo metadata`: relative to
+    /// the workspace root if it's inside it, so that it also applies to copies of the tree.
+    pub fn relative_target_dir(&self) -> Utf8PathBuf {
+        let target_dir = &self.metadata.target_directory;
+        target_dir
+            .strip_prefix(&self.metadata.workspace_root)
+            .unwrap_or(target_dir)
+            .to_owned()
+    }
+
     /// Find packages to mutate, subject to some filtering.
     #[allow(dead_code)]
     pub fn packages(&self, package_filter: &PackageFilter) -> Result<Vec<Arc<Package>>> {
         Ok(self
-            .package_tops(package_filter)?
+            .package_tops(package_filter, TargetKind::DEFAULT)?
             .into_iter()
             .map(|pt| pt.package)
             .collect())
     }
 
-    /// Find all the packages and their top source files.
-    fn package_tops(&self, package_filter: &PackageFilter) -> Result<Vec<PackageTop>> {
+    /// Find all the packages and their top source files, from targets of the given kinds.
+    fn package_tops(
+        &self,
+        package_filter: &PackageFilter,
+        targets: &[TargetKind],
+    ) -> Result<Vec<PackageTop>> {
         let mut tops = Vec::new();
         let package_filter = package_filter.resolve_auto(&self.metadata)?;
         for package_metadata in self
@@ -165,10 +179,11 @@ impl Workspace {
             let package = Arc::new(Package {
                 name: package_metadata.name.clone(),
                 relative_manifest_path,
+                features: package_metadata.features.clone(),
             });
             tops.push(PackageTop {
                 package,
-                top_sources: direct_package_sources(&self.dir, package_metadata)?,
+                top_sources: direct_package_sources(&self.dir, package_metadata, targets)?,
             });
         }
         if let PackageFilter::Explicit(ref names) = package_filter {
@@ -181,13 +196,17 @@ impl Workspace {
         Ok(tops)
     }
 
-    /// Find all the top source files for selected packages.
-    fn top_sources(&self, package_filter: &PackageFilter) -> Result<Vec<SourceFile>> {
+    /// Find all the top source files for selected packages and kinds of target.
+    fn top_sources(
+        &self,
+        package_filter: &PackageFilter,
+        targets: &[TargetKind],
+    ) -> Result<Vec<SourceFile>> {
         let mut sources = Vec::new();
         for PackageTop {
             package,
             top_sources,
-        } in self.package_tops(package_filter)?
+        } in self.package_tops(package_filter, targets)?
         {
             for source_path in top_sources {
                 sources.push(SourceFile::new(
@@ -210,7 +229,7 @@ impl Workspace {
     ) -> Result<Discovered> {
         walk_tree(
             &self.dir,
-            &self.top_sources(package_filter)?,
+            &self.top_sources(package_filter, options.target_kinds())?,
             options,
             console,
         )
@@ -234,11 +253,12 @@ impl Workspace {
 fn direct_package_sources(
     workspace_root: &Utf8Path,
     package_metadata: &cargo_metadata::Package,
+    targets: &[TargetKind],
 ) -> Result<Vec<Utf8PathBuf>> {
     let mut found = Vec::new();
     let pkg_dir = package_metadata.manifest_path.parent().unwrap();
     for target in &package_metadata.targets {
-        if should_mutate_target(target) {
+        if should_mutate_target(target, targets) {
             if let Ok(relpath) = target
                 .src_path
                 .strip_prefix(workspace_root)
@@ -264,8 +284,11 @@ fn direct_package_sources(
     Ok(found)
 }
 
-fn should_mutate_target(target: &cargo_metadata::Target) -> bool {
-    target.kind.iter().any(|k| k.ends_with("lib") || k == "bin")
+fn should_mutate_target(target: &cargo_metadata::Target, targets: &[TargetKind]) -> bool {
+    target
+        .kind
+        .iter()
+        .any(|kind| targets.iter().any(|t| t.matches(kind)))
 }
 
 /// Return the path of the workspace or package directory enclosing a given directory.
@@ -308,7 +331,7 @@ mod test {
     use itertools::Itertools;
 
     use crate::console::Console;
-    use crate::options::Options;
+    use crate::options::{Options, TargetKind};
     use crate::workspace::PackageFilter;
 
     use super::Workspace;
@@ -351,7 +374,7 @@ mod test {
         );
         assert_eq!(
             workspace
-                .top_sources(&PackageFilter::All)
+                .top_sources(&PackageFilter::All, TargetKind::DEFAULT)
                 .unwrap()
                 .iter()
                 .map(|sf| sf.tree_relative_path.clone())
@@ -414,7 +437,7 @@ mod test {
                 .collect_vec(),
             ["main"]
         );
-        let top_sources = workspace.top_sources(&filter).unwrap();
+        let top_sources = workspace.top_sources(&filter, TargetKind::DEFAULT).unwrap();
         println!("{top_sources:#?}");
         assert_eq!(
             top_sources
@@ -0,0 +1,16 @@
+//! If `should_stop` is mutated to return false, evaluating `READY` loops forever,
+//! so the build never finishes.
+
+pub const fn should_stop() -> bool {
+    true
+}
+
+pub const READY: bool = {
+    while !should_stop() {}
+    true
+};
+
+#[test]
+fn ready() {
+    assert!(READY);
+}
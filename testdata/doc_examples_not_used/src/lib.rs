@@ -0,0 +1,23 @@
+//! Functions with doc examples that shouldn't be run against their mutants with
+//! `doctests = false`: one because the example fails in the unmutated tree, and the
+//! other because it's mentioned by an integration test.
+
+/// Add one to a number.
+///
+/// This example is wrong, so it fails whether or not the function is mutated.
+///
+/// ```
+/// assert_eq!(cargo_mutants_testdata_doc_examples_not_used::add_one(1), 3);
+/// ```
+pub fn add_one(x: u32) -> u32 {
+    x + 1
+}
+
+/// Halve a number.
+///
+/// ```
+/// assert_eq!(cargo_mutants_testdata_doc_examples_not_used::halve(6), 3);
+/// ```
+pub fn halve(x: u32) -> u32 {
+    x / 2
+}
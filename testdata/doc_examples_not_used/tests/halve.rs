@@ -0,0 +1,9 @@
+//! This test mentions `halve`, so its doc example isn't taken to be its only test, even
+//! though this checks nothing.
+
+use cargo_mutants_testdata_doc_examples_not_used::halve;
+
+#[test]
+fn halve_runs() {
+    let _ = halve(7);
+}
@@ -0,0 +1,24 @@
+//! A tree where one function is tested only by the example in its documentation,
+//! and another only by a unit test.
+
+/// Double a number.
+///
+/// ```
+/// assert_eq!(cargo_mutants_testdata_doctests::double(3), 6);
+/// ```
+pub fn double(x: u32) -> u32 {
+    x * 2
+}
+
+/// Triple a number.
+pub fn triple(x: u32) -> u32 {
+    x * 3
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn triple_two() {
+        assert_eq!(super::triple(2), 6);
+    }
+}
@@ -0,0 +1,24 @@
+//! A tree whose tests pass only with some environment variables set and others unset,
+//! to exercise the `env`, `test_env`, and `unset_env` config keys.
+
+use std::env;
+
+pub fn greeting() -> String {
+    format!("hello {}", env::var("GREETING_NAME").unwrap_or_default())
+}
+
+#[test]
+fn greeting_uses_name_from_test_env() {
+    assert_eq!(greeting(), "hello world");
+}
+
+/// `env` applies to the build, as well as to the tests.
+#[test]
+fn build_env_is_set() {
+    assert_eq!(option_env!("CARGO_MUTANTS_TESTDATA_BUILD_ENV"), Some("set"));
+}
+
+#[test]
+fn inherited_variable_is_unset() {
+    assert!(env::var_os("CARGO_MUTANTS_TESTDATA_UNWANTED").is_none());
+}
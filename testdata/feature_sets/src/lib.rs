@@ -0,0 +1,46 @@
+//! Some code here is only compiled, or only tested, with the `fast` feature.
+
+pub fn add_one(x: u32) -> u32 {
+    x + 1
+}
+
+/// This is only tested with the `fast` feature.
+pub fn triple(x: u32) -> u32 {
+    x * 3
+}
+
+#[cfg(feature = "fast")]
+pub fn double(x: u32) -> u32 {
+    x << 1
+}
+
+/// Only `Default` without the `fast` feature, so replacing the result with
+/// `Default::default()` only builds without it.
+#[cfg_attr(not(feature = "fast"), derive(Default))]
+pub struct Speed(pub u32);
+
+pub fn speed() -> Speed {
+    Speed(1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn add_one_to_two() {
+        assert_eq!(add_one(2), 3);
+    }
+
+    #[test]
+    fn speed_is_one() {
+        assert_eq!(speed().0, 1);
+    }
+
+    #[test]
+    #[cfg(feature = "fast")]
+    fn double_and_triple() {
+        assert_eq!(double(3), 6);
+        assert_eq!(triple(3), 9);
+    }
+}
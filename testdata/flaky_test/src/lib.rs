@@ -0,0 +1,29 @@
+//! A tree with a test that alternately fails and passes, to exercise `--retries`.
+
+use std::path::Path;
+
+pub fn triple(a: i32) -> i32 {
+    a * 3
+}
+
+/// This function isn't tested, except by the flaky test.
+pub fn is_ready() -> bool {
+    true
+}
+
+#[test]
+fn triple_2_is_6() {
+    assert_eq!(triple(2), 6);
+}
+
+/// Fails if the marker file doesn't exist, creating it, and passes if it does, removing it.
+#[test]
+fn flaky() {
+    let marker = Path::new(env!("CARGO_MANIFEST_DIR")).join("flaky.marker");
+    if marker.exists() {
+        std::fs::remove_file(&marker).unwrap();
+    } else {
+        std::fs::write(&marker, b"").unwrap();
+        panic!("flaky test failed this time");
+    }
+}
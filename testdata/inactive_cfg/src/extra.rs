@@ -0,0 +1,3 @@
+pub fn extra() -> bool {
+    true
+}
@@ -0,0 +1,22 @@
+//! Only some of this code is compiled on any one platform, or without the `extra` feature.
+
+#[cfg(feature = "extra")]
+pub mod extra;
+
+#[cfg(any(unix, windows))]
+pub fn platform() -> &'static str {
+    "desktop"
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn platform() -> &'static str {
+    "elsewhere"
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn platform_is_named() {
+        assert_eq!(super::platform(), "desktop");
+    }
+}
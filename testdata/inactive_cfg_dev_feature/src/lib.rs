@@ -0,0 +1,15 @@
+//! The `extra` feature isn't on by default, but it is when the tests are built, because of
+//! the dev-dependency on this crate.
+
+#[cfg(feature = "extra")]
+pub fn extra() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn extra_is_on_in_tests() {
+        assert!(super::extra());
+    }
+}
@@ -0,0 +1,12 @@
+//! A tree where one mutant makes the test allocate a gigabyte, to exercise `--limit-memory`.
+
+/// Return the number of KiB in a number of bytes.
+pub fn kib(bytes: usize) -> usize {
+    bytes / 1024
+}
+
+#[test]
+fn buffer_of_one_mib_has_1024_kib() {
+    let buf = vec![0u8; kib(1 << 20)];
+    assert_eq!(buf.len(), 1024);
+}
@@ -0,0 +1,17 @@
+//! This crate only builds with `--cfg mutants_testdata`, which is set by
+//! `build.rustflags` in `.cargo/config.toml`.
+
+#[cfg(not(mutants_testdata))]
+compile_error!("rustflags from .cargo/config.toml were not used");
+
+pub fn double(x: i32) -> i32 {
+    x * 2
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn double() {
+        assert_eq!(super::double(3), 6);
+    }
+}
@@ -0,0 +1,37 @@
+//! A tree whose tests only pass in a sandbox, to exercise `--sandbox`.
+
+use std::fs::OpenOptions;
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+pub fn double(a: i32) -> i32 {
+    a * 2
+}
+
+#[test]
+fn double_3_is_6() {
+    assert_eq!(double(3), 6);
+}
+
+/// Creates a file in `/tmp`, which fails if any earlier run, such as the baseline,
+/// left it in the same `/tmp`.
+#[test]
+fn tmp_is_private() {
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open("/tmp/cargo-mutants-testdata-sandbox.marker")
+        .expect("create marker in /tmp");
+}
+
+#[test]
+fn network_is_unreachable() {
+    let addr = "192.0.2.1:80".parse().unwrap();
+    assert!(TcpStream::connect_timeout(&addr, Duration::from_secs(5)).is_err());
+}
+
+#[test]
+fn loopback_works() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+}
@@ -0,0 +1,9 @@
+//! A benchmark, which isn't run by `cargo test`.
+
+fn work(n: u64) -> u64 {
+    (0..n).sum()
+}
+
+fn main() {
+    println!("{}", work(1000));
+}
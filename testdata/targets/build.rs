@@ -0,0 +1,18 @@
+//! Generate a constant that's checked by the library's tests.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn answer() -> u32 {
+    6 * 7
+}
+
+fn main() {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(
+        Path::new(&out_dir).join("answer.rs"),
+        format!("pub const ANSWER: u32 = {};\n", answer()),
+    )
+    .unwrap();
+}
@@ -0,0 +1,14 @@
+//! An example with its own tests, run by `cargo test` because it has `test = true`.
+
+fn greeting(name: &str) -> String {
+    format!("Hello, {name}!")
+}
+
+fn main() {
+    println!("{}", greeting("world"));
+}
+
+#[test]
+fn greeting_includes_name() {
+    assert_eq!(greeting("crab"), "Hello, crab!");
+}
@@ -0,0 +1,11 @@
+//! A library whose constant is computed by its build script.
+
+include!(concat!(env!("OUT_DIR"), "/answer.rs"));
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn answer_is_computed_by_build_script() {
+        assert_eq!(super::ANSWER, 42);
+    }
+}
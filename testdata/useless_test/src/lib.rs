@@ -0,0 +1,21 @@
+//! A tree where one test does the work of catching mutants, and another runs the
+//! same code but makes no assertions.
+
+pub fn double(x: u32) -> u32 {
+    x * 2
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn double_three_is_six() {
+        assert_eq!(double(3), 6);
+    }
+
+    #[test]
+    fn double_runs() {
+        double(1);
+    }
+}
@@ -1,6 +1,6 @@
 // Copyright 2023 Martin Pool
 
-use std::fs::write;
+use std::fs::{read_dir, read_to_string, write};
 
 mod util;
 use util::{copy_of_testdata, run};
@@ -43,3 +43,41 @@ fn tree_with_symlink() {
         .assert()
         .success();
 }
+
+/// Build dirs are copied from the baseline build, so cargo sees their `target/` as fresh,
+/// and testing a mutant there rebuilds only the mutated package, not its dependencies.
+#[test]
+fn build_dirs_copied_from_baseline_build_are_fresh() {
+    let tmp = copy_of_testdata("workspace");
+    run()
+        .args(["mutants", "-j2", "-f", "main/src/main.rs", "-d"])
+        .arg(tmp.path())
+        .assert()
+        .success();
+    let log_dir = tmp.path().join("mutants.out/log");
+    let manifest_path = |log: &str| {
+        log.split_whitespace()
+            .skip_while(|word| *word != "--manifest-path")
+            .nth(1)
+            .expect("log has a manifest path")
+            .to_owned()
+    };
+    let baseline_manifest_path =
+        manifest_path(&read_to_string(log_dir.join("baseline.log")).unwrap());
+    let mut copied_dir_logs = 0;
+    for entry in read_dir(&log_dir).unwrap() {
+        let log = read_to_string(entry.unwrap().path()).unwrap();
+        if manifest_path(&log) == baseline_manifest_path {
+            continue;
+        }
+        copied_dir_logs += 1;
+        println!("{log}");
+        assert!(log.contains("Compiling main "));
+        assert!(!log.contains("Compiling cargo_mutants_testdata_workspace_utils "));
+        assert!(!log.contains("Compiling mutants "));
+    }
+    assert!(
+        copied_dir_logs > 0,
+        "no mutants were tested in the copied build dir"
+    );
+}
@@ -220,9 +220,11 @@ fn exclude_re_overrides_config() {
 fn tree_fails_without_needed_feature() {
     // The point of this tree is to check that Cargo features can be turned on,
     // but let's make sure it does fail as intended if they're not.
+    // (Without the feature, the only mutants are in code that isn't compiled, so
+    // they're only tested at all with --include-inactive.)
     let testdata = copy_of_testdata("fails_without_feature");
     run()
-        .args(["mutants", "-d"])
+        .args(["mutants", "--include-inactive", "-d"])
         .arg(testdata.path())
         .assert()
         .failure()
@@ -262,3 +264,55 @@ fn additional_cargo_test_args() {
         .assert()
         .success();
 }
+
+#[test]
+fn env_and_test_env_are_set_and_unset_env_removed() {
+    let testdata = copy_of_testdata("env_vars");
+    write_config_file(
+        &testdata,
+        indoc! { r#"
+            unset_env = ["CARGO_MUTANTS_TESTDATA_UNWANTED"]
+
+            [env]
+            CARGO_MUTANTS_TESTDATA_BUILD_ENV = "set"
+
+            [test_env]
+            GREETING_NAME = "world"
+        "# },
+    );
+    run()
+        .args(["mutants", "--no-times", "-d"])
+        .arg(testdata.path())
+        .env("CARGO_MUTANTS_TESTDATA_UNWANTED", "1")
+        .env("GREETING_NAME", "inherited")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ok       Unmutated baseline"))
+        .stdout(predicate::str::contains("2 mutants tested: 2 caught"));
+}
+
+#[test]
+fn env_args_override_config() {
+    let testdata = copy_of_testdata("env_vars");
+    write_config_file(
+        &testdata,
+        indoc! { r#"
+            [test_env]
+            GREETING_NAME = "nobody"
+        "# },
+    );
+    run()
+        .args([
+            "mutants",
+            "--no-times",
+            "--env=CARGO_MUTANTS_TESTDATA_BUILD_ENV=set",
+            "--test-env=GREETING_NAME=world",
+            "--unset-env=CARGO_MUTANTS_TESTDATA_UNWANTED",
+            "-d",
+        ])
+        .arg(testdata.path())
+        .env("CARGO_MUTANTS_TESTDATA_UNWANTED", "1")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ok       Unmutated baseline"));
+}
@@ -0,0 +1,194 @@
+// Copyright 2024 Martin Pool
+
+//! Test `--serve` and `--worker`: distributing mutants from a coordinator to workers.
+
+use std::fs::read_to_string;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use itertools::Itertools;
+use pretty_assertions::assert_eq;
+
+mod util;
+use util::{copy_of_testdata, run, MAIN_BINARY};
+
+#[test]
+fn coordinator_collects_outcomes_from_workers() {
+    // Find a free port; there's a small chance something else takes it before the
+    // coordinator starts listening.
+    let address = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .to_string();
+    let coordinator_dir = copy_of_testdata("factorial");
+    let mut coordinator = Command::new(MAIN_BINARY.as_os_str())
+        .args(["mutants", "--no-times", "--serve", &address])
+        .current_dir(coordinator_dir.path())
+        .env_remove("CARGO_MUTANTS_JOBS")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let worker_dir = copy_of_testdata("factorial");
+    // The worker retries until the coordinator is listening.
+    run()
+        .args(["mutants", "--no-times", "--worker", &address])
+        .current_dir(worker_dir.path())
+        .assert()
+        .code(2);
+    assert_eq!(coordinator.wait().unwrap().code(), Some(2));
+
+    let read_list = |name: &str| {
+        read_to_string(coordinator_dir.path().join("mutants.out").join(name))
+            .unwrap()
+            .lines()
+            .map(ToOwned::to_owned)
+            .sorted()
+            .collect_vec()
+    };
+    assert_eq!(
+        read_list("missed.txt"),
+        ["src/bin/factorial.rs:2:5: replace main with ()"]
+    );
+    assert_eq!(read_list("caught.txt").len(), 4);
+    // The coordinator has the logs from the worker.
+    let log = read_to_string(
+        coordinator_dir
+            .path()
+            .join("mutants.out/log/src__bin__factorial.rs_line_2_col_5.log"),
+    )
+    .unwrap();
+    assert!(log.contains("tested by worker"));
+    assert!(log.contains("test result: ok"));
+}
+
+#[test]
+fn coordinator_refuses_worker_with_different_mutants() {
+    let address = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .to_string();
+    let coordinator_dir = copy_of_testdata("factorial");
+    let mut coordinator = Command::new(MAIN_BINARY.as_os_str())
+        .args(["mutants", "--serve", &address])
+        .current_dir(coordinator_dir.path())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let worker_dir = copy_of_testdata("factorial");
+    run()
+        .args(["mutants", "--worker", &address, "--exclude-re", "main"])
+        .current_dir(worker_dir.path())
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("Coordinator refused this worker"));
+    coordinator.kill().unwrap();
+    coordinator.wait().unwrap();
+}
+
+#[test]
+fn mutants_from_a_worker_that_dies_are_tested_by_another() {
+    let address = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .to_string();
+    let coordinator_dir = copy_of_testdata("factorial");
+    let mut coordinator = Command::new(MAIN_BINARY.as_os_str())
+        .args(["mutants", "--no-times", "--serve", &address])
+        .current_dir(coordinator_dir.path())
+        .env_remove("CARGO_MUTANTS_JOBS")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let worker_dir = copy_of_testdata("factorial");
+    let names = run()
+        .args(["mutants", "--list"])
+        .current_dir(worker_dir.path())
+        .output()
+        .unwrap()
+        .stdout;
+    let names = String::from_utf8(names)
+        .unwrap()
+        .lines()
+        .map(ToOwned::to_owned)
+        .collect_vec();
+    assert_eq!(names.len(), 5);
+
+    // A fake worker takes one mutant and holds it, so the real worker finds the queue
+    // empty while that mutant is still in flight.
+    let deadline = Instant::now() + Duration::from_secs(60);
+    let stream = loop {
+        match TcpStream::connect(&address) {
+            Ok(stream) => break stream,
+            Err(_) if Instant::now() < deadline => sleep(Duration::from_millis(100)),
+            Err(err) => panic!("connect to coordinator: {err}"),
+        }
+    };
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut exchange = |message: serde_json::Value| {
+        writeln!(&stream, "{message}").unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        serde_json::from_str::<serde_json::Value>(&line).unwrap()
+    };
+    let hello = serde_json::json!({
+        "type": "Hello",
+        "version": env!("CARGO_PKG_VERSION"),
+        "mutants": names,
+    });
+    assert_eq!(exchange(hello)["type"], "Welcome");
+    assert_eq!(
+        exchange(serde_json::json!({"type": "Next"}))["type"],
+        "Test"
+    );
+
+    let mut worker = Command::new(MAIN_BINARY.as_os_str())
+        .args(["mutants", "--no-times", "--worker", &address])
+        .current_dir(worker_dir.path())
+        .env_remove("CARGO_MUTANTS_JOBS")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let count_outcomes = || {
+        ["caught.txt", "missed.txt"]
+            .iter()
+            .map(|name| {
+                read_to_string(coordinator_dir.path().join("mutants.out").join(name))
+                    .map_or(0, |content| content.lines().count())
+            })
+            .sum::<usize>()
+    };
+    let deadline = Instant::now() + Duration::from_secs(300);
+    while count_outcomes() < 4 {
+        assert!(
+            Instant::now() < deadline,
+            "worker did not test the other mutants"
+        );
+        assert!(
+            worker.try_wait().unwrap().is_none(),
+            "worker exited while a mutant was still in flight"
+        );
+        sleep(Duration::from_millis(100));
+    }
+    // The fake worker dies without reporting its mutant, which goes to the real worker.
+    stream.shutdown(Shutdown::Both).unwrap();
+    let wait = |child: &mut std::process::Child| loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            return status.code();
+        }
+        assert!(Instant::now() < deadline, "process did not finish");
+        sleep(Duration::from_millis(100));
+    };
+    assert_eq!(wait(&mut worker), Some(2));
+    assert_eq!(wait(&mut coordinator), Some(2));
+    assert_eq!(count_outcomes(), 5);
+}
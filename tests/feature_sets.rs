@@ -0,0 +1,86 @@
+// Copyright 2024 Martin Pool
+
+//! Test building and testing each mutant with several sets of features.
+
+use std::fs::{create_dir, read_to_string, write};
+
+use predicates::prelude::*;
+use pretty_assertions::assert_eq;
+
+mod util;
+use util::{copy_of_testdata, run};
+
+#[test]
+fn code_only_tested_with_a_feature_is_missed_without_feature_sets() {
+    let tmp = copy_of_testdata("feature_sets");
+    run()
+        .args(["mutants", "--no-times", "--no-shuffle", "-d"])
+        .arg(tmp.path())
+        .assert()
+        .code(2)
+        // The mutants in code that's only compiled with the feature aren't tested at all.
+        .stdout(predicate::str::contains(
+            "9 mutants tested: 4 missed, 5 caught",
+        ));
+}
+
+#[test]
+fn mutants_are_caught_by_tests_under_any_feature_set() {
+    let tmp = copy_of_testdata("feature_sets");
+    create_dir(tmp.path().join(".cargo")).unwrap();
+    write(
+        tmp.path().join(".cargo/mutants.toml"),
+        r#"feature_sets = [[], ["fast"]]"#,
+    )
+    .unwrap();
+    run()
+        .args(["mutants", "--no-times", "--no-shuffle", "-d"])
+        .arg(tmp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("12 mutants tested: 12 caught"));
+
+    let log_dir = tmp.path().join("mutants.out/log");
+    let baseline_log = read_to_string(log_dir.join("baseline.log")).unwrap();
+    // The baseline is tested with every feature set.
+    assert_eq!(baseline_log.matches(" test --manifest-path").count(), 2);
+    assert!(baseline_log.contains(" --features=fast\n"));
+
+    // Mutants in code that's compiled without features are caught by the first
+    // feature set, and so aren't tested with the second.
+    let add_one_log = read_to_string(log_dir.join("src__lib.rs_line_4_col_7.log")).unwrap();
+    assert!(!add_one_log.contains("--features=fast"));
+
+    // Mutants only tested with the feature are caught by the second set.
+    let triple_log = read_to_string(log_dir.join("src__lib.rs_line_9_col_7.log")).unwrap();
+    assert!(triple_log.contains(" test --manifest-path"));
+    assert!(triple_log.contains(" --features=fast\n"));
+
+    // Mutants in code that isn't compiled without the feature are only tested with it.
+    let double_log = read_to_string(log_dir.join("src__lib.rs_line_14_col_7.log")).unwrap();
+    assert!(double_log.contains("not compiled with --features=\"\"; skipped"));
+    assert_eq!(double_log.matches(" build --tests ").count(), 1);
+}
+
+#[test]
+fn mutant_that_builds_under_only_one_feature_set_is_tested_with_it() {
+    let tmp = copy_of_testdata("feature_sets");
+    create_dir(tmp.path().join(".cargo")).unwrap();
+    write(
+        tmp.path().join(".cargo/mutants.toml"),
+        r#"feature_sets = [["fast"], []]"#,
+    )
+    .unwrap();
+    run()
+        .args(["mutants", "--no-times", "--no-shuffle", "-F", "speed", "-d"])
+        .arg(tmp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 mutant tested: 1 caught"));
+    let log_dir = tmp.path().join("mutants.out/log");
+    let speed_log = read_to_string(log_dir.join("src__lib.rs_line_23_col_5.log")).unwrap();
+    // It doesn't build with the feature, but does without it, and then the tests catch it.
+    assert!(speed_log.contains(" --features=fast\n"));
+    assert_eq!(speed_log.matches(" build --tests ").count(), 2);
+    assert_eq!(speed_log.matches(" test --manifest-path").count(), 1);
+}
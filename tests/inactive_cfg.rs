@@ -0,0 +1,86 @@
+// Copyright 2024 Martin Pool
+
+//! Test that code under `cfg` attributes that are false for the target and features isn't
+//! mutated.
+
+use indoc::indoc;
+use predicates::prelude::*;
+use pretty_assertions::assert_eq;
+
+mod util;
+use util::{copy_of_testdata, run};
+
+fn list_mutants(args: &[&str]) -> Vec<String> {
+    let tmp = copy_of_testdata("inactive_cfg");
+    let output = run()
+        .args(["mutants", "--list", "-d"])
+        .arg(tmp.path())
+        .args(args)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+#[test]
+fn inactive_code_is_not_mutated() {
+    let tmp = copy_of_testdata("inactive_cfg");
+    run()
+        .args(["mutants", "--list", "-d"])
+        .arg(tmp.path())
+        .assert()
+        .success()
+        .stdout(predicate::eq(indoc! { r#"
+            src/lib.rs:8:5: replace platform -> &'static str with ""
+            src/lib.rs:8:5: replace platform -> &'static str with "xyzzy"
+        "# }))
+        .stderr(predicate::str::contains(
+            "Skipped 3 mutants in code that isn't compiled for this target and features",
+        ));
+}
+
+#[test]
+fn code_behind_selected_features_is_mutated() {
+    let extra = "src/extra.rs:2:5: replace extra -> bool with false";
+    assert!(list_mutants(&["--features=extra"]).contains(&extra.to_owned()));
+    // Features passed straight to cargo aren't understood, so code behind any feature
+    // is mutated.
+    assert!(list_mutants(&["--cargo-arg=--features=extra"]).contains(&extra.to_owned()));
+    assert!(!list_mutants(&[]).contains(&extra.to_owned()));
+}
+
+#[test]
+fn include_inactive_mutates_everything() {
+    assert_eq!(list_mutants(&["--include-inactive"]).len(), 5);
+}
+
+#[test]
+fn only_active_mutants_are_tested() {
+    let tmp = copy_of_testdata("inactive_cfg");
+    run()
+        .args(["mutants", "--no-times", "-d"])
+        .arg(tmp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2 mutants tested: 2 caught"));
+}
+
+#[test]
+fn features_turned_on_by_dev_dependencies_are_active() {
+    let tmp = copy_of_testdata("inactive_cfg_dev_feature");
+    run()
+        .args(["mutants", "--no-times", "--caught", "-d"])
+        .arg(tmp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "caught   src/lib.rs:6:5: replace extra -> bool with false",
+        ))
+        .stdout(predicate::str::contains("1 mutant tested: 1 caught"));
+}
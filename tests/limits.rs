@@ -0,0 +1,91 @@
+// Copyright 2024 Martin Pool
+
+//! Test `--limit-memory` and other resource limits on tests.
+
+#![cfg(unix)]
+
+use std::fs::read_to_string;
+
+use predicates::prelude::*;
+use pretty_assertions::assert_eq;
+
+mod util;
+use util::{copy_of_testdata, run};
+
+#[test]
+fn mutant_exceeding_memory_limit_is_caught_with_reason() {
+    let tmp = copy_of_testdata("memory_hog");
+    // The test binary is aborted below `cargo test`, so the limit is recognized from
+    // the message in the output rather than from cargo's exit status.
+    run()
+        .args([
+            "mutants",
+            "--no-times",
+            "--caught",
+            "--limit-memory=512",
+            "-d",
+        ])
+        .arg(tmp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "caught   src/lib.rs:5:11: replace / with * in kib (memory limit exceeded)",
+        ))
+        .stdout(predicate::str::contains(
+            "caught   src/lib.rs:5:11: replace / with % in kib\n",
+        ))
+        .stdout(predicate::str::contains("4 mutants tested: 4 caught"));
+
+    let outcomes: serde_json::Value = read_to_string(tmp.path().join("mutants.out/outcomes.json"))
+        .unwrap()
+        .parse()
+        .unwrap();
+    let limited = outcomes["outcomes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|o| {
+            o["phase_results"][1]["process_status"]
+                .get("LimitExceeded")
+                .is_some()
+        })
+        .map(|o| {
+            (
+                o["scenario"]["Mutant"]["replacement"].as_str().unwrap(),
+                o["summary"].as_str().unwrap(),
+                o["phase_results"][1]["process_status"].clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        limited,
+        [(
+            "*",
+            "CaughtMutant",
+            serde_json::json!({"LimitExceeded": "Memory"})
+        )]
+    );
+}
+
+#[test]
+fn limits_are_not_applied_to_builds() {
+    let tmp = copy_of_testdata("memory_hog");
+    // Far too little memory for anything to run, but the build still succeeds because
+    // the limits only apply to tests.
+    run()
+        .args(["mutants", "--no-times", "--limit-memory=1", "-d"])
+        .arg(tmp.path())
+        .assert()
+        .code(4)
+        .stdout(predicate::str::contains("FAILED   Unmutated baseline"));
+    let outcomes: serde_json::Value = read_to_string(tmp.path().join("mutants.out/outcomes.json"))
+        .unwrap()
+        .parse()
+        .unwrap();
+    let baseline = &outcomes["outcomes"][0];
+    assert_eq!(baseline["scenario"], "Baseline");
+    assert_eq!(baseline["phase_results"][0]["phase"], "Build");
+    assert_eq!(baseline["phase_results"][0]["process_status"], "Success");
+    assert_eq!(baseline["phase_results"][1]["phase"], "Test");
+    assert_ne!(baseline["phase_results"][1]["process_status"], "Success");
+}
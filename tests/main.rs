@@ -138,6 +138,66 @@ fn small_well_tested_tree_is_clean() {
     assert!(log_content.contains("factorial(6) = 0"));
 }
 
+#[test]
+fn failing_tests_are_recorded_for_each_caught_mutant() {
+    let tmp_src_dir = copy_of_testdata("small_well_tested");
+    run()
+        .arg("mutants")
+        .args(["--no-times", "--no-shuffle"])
+        .current_dir(tmp_src_dir.path())
+        .assert()
+        .success();
+    let outcomes: serde_json::Value =
+        read_to_string(tmp_src_dir.path().join("mutants.out/outcomes.json"))
+            .unwrap()
+            .parse()
+            .unwrap();
+    let outcomes = outcomes["outcomes"].as_array().unwrap();
+    assert_eq!(outcomes[0]["scenario"], "Baseline");
+    assert_eq!(outcomes[0]["failing_tests"], serde_json::json!([]));
+    for outcome in &outcomes[1..] {
+        assert_eq!(outcome["summary"], "CaughtMutant");
+        assert_eq!(
+            outcome["failing_tests"],
+            serde_json::json!(["cargo_mutants_testdata_small_well_tested test::test_factorial"])
+        );
+    }
+    let tests_json: serde_json::Value =
+        read_to_string(tmp_src_dir.path().join("mutants.out/tests.json"))
+            .unwrap()
+            .parse()
+            .unwrap();
+    assert_eq!(
+        tests_json,
+        serde_json::json!({
+            "tests": [
+                {
+                    "name": "cargo_mutants_testdata_small_well_tested test::test_factorial",
+                    "mutants_caught": outcomes.len() - 1
+                },
+            ]
+        })
+    );
+}
+
+#[test]
+fn tests_that_never_fail_are_listed_as_useless() {
+    let tmp_src_dir = copy_of_testdata("useless_test");
+    run()
+        .arg("mutants")
+        .args(["--no-times", "--no-shuffle"])
+        .current_dir(tmp_src_dir.path())
+        .assert()
+        .success()
+        .stdout(contains(
+            "1 test ran against every mutant but never failed; see useless_tests.txt",
+        ));
+    assert_eq!(
+        read_to_string(tmp_src_dir.path().join("mutants.out/useless_tests.txt")).unwrap(),
+        "cargo_mutants_testdata_useless_test test::double_runs\n"
+    );
+}
+
 #[test]
 fn test_small_well_tested_tree_with_baseline_skip() {
     let tmp_src_dir = copy_of_testdata("small_well_tested");
@@ -210,6 +270,26 @@ fn well_tested_tree_finds_no_problems() {
     check_text_list_output(tmp_src_dir.path(), "well_tested_tree_finds_no_problems");
 }
 
+#[test]
+fn max_runtime_reports_remaining_mutants_as_not_tested() {
+    let tmp_src_dir = copy_of_testdata("factorial");
+    run()
+        .args(["mutants", "--no-times", "--max-runtime=0"])
+        .current_dir(tmp_src_dir.path())
+        .assert()
+        .code(5)
+        .stdout(contains("0 mutants tested: 5 not tested"));
+    let outcomes: serde_json::Value =
+        fs::read_to_string(tmp_src_dir.path().join("mutants.out/outcomes.json"))
+            .unwrap()
+            .parse()
+            .unwrap();
+    assert_eq!(outcomes["total_mutants"], 0);
+    assert_eq!(outcomes["not_tested"].as_array().unwrap().len(), 5);
+    // The baseline still runs, so that the tree is known to work.
+    assert_eq!(outcomes["outcomes"].as_array().unwrap().len(), 1);
+}
+
 #[test]
 fn well_tested_tree_check_only() {
     let tmp_src_dir = copy_of_testdata("well_tested");
@@ -235,6 +315,25 @@ fn well_tested_tree_check_only_shuffled() {
     // Caution: No assertions about output here, we just check that it runs.
 }
 
+#[test]
+fn well_tested_tree_check_only_smart_order() {
+    let tmp_src_dir = copy_of_testdata("well_tested");
+    run()
+        .args(["mutants", "--check", "--no-times", "--order=smart"])
+        .current_dir(tmp_src_dir.path())
+        .assert()
+        .success();
+}
+
+#[test]
+fn order_conflicts_with_shuffle() {
+    run()
+        .args(["mutants", "--order=source", "--shuffle"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
 #[test]
 fn unviable_mutation_of_struct_with_no_default() {
     let tmp_src_dir = copy_of_testdata("struct_with_no_default");
@@ -548,6 +647,92 @@ fn already_failing_doctests_can_be_skipped_with_cargo_arg() {
         .code(0);
 }
 
+#[test]
+fn already_failing_doctests_can_be_skipped_with_doctests_false() {
+    let tmp_src_dir = copy_of_testdata("already_failing_doctests");
+    run()
+        .arg("mutants")
+        .args(["--doctests=false", "--no-times"])
+        .current_dir(tmp_src_dir.path())
+        .env_remove("RUST_BACKTRACE")
+        .assert()
+        .code(0);
+    let baseline_log =
+        read_to_string(tmp_src_dir.path().join("mutants.out/log/baseline.log")).unwrap();
+    assert!(baseline_log.contains(" test --lib --bins --tests "));
+}
+
+#[test]
+fn doctests_false_still_runs_examples_of_functions_otherwise_untested() {
+    let tmp_src_dir = copy_of_testdata("doctests");
+    run()
+        .arg("mutants")
+        .args(["--doctests=false", "--no-times", "--no-shuffle"])
+        .current_dir(tmp_src_dir.path())
+        .assert()
+        .code(0)
+        .stdout(contains("8 mutants tested: 8 caught"));
+    let outcomes: serde_json::Value =
+        read_to_string(tmp_src_dir.path().join("mutants.out/outcomes.json"))
+            .unwrap()
+            .parse()
+            .unwrap();
+    for outcome in &outcomes["outcomes"].as_array().unwrap()[1..] {
+        let function = outcome["scenario"]["Mutant"]["function"]["function_name"]
+            .as_str()
+            .unwrap();
+        let test_argvs = outcome["phase_results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|pr| pr["phase"] == "Test")
+            .map(|pr| pr["argv"][2].as_str().unwrap())
+            .collect_vec();
+        match function {
+            // Only caught by its doctest, which runs after the other tests miss it.
+            "double" => {
+                assert_eq!(test_argvs, ["--lib", "--doc"]);
+                assert_eq!(
+                    outcome["failing_tests"],
+                    serde_json::json!([
+                        "cargo_mutants_testdata_doctests src/lib.rs - double (line 6)"
+                    ])
+                );
+            }
+            // Caught by its unit test, and has no doc example.
+            "triple" => assert_eq!(test_argvs, ["--lib"]),
+            _ => panic!("unexpected function {function}"),
+        }
+    }
+}
+
+#[test]
+fn doctests_false_skips_examples_that_fail_in_baseline_or_are_not_only_tests() {
+    let tmp_src_dir = copy_of_testdata("doc_examples_not_used");
+    run()
+        .arg("mutants")
+        .args(["--doctests=false", "--no-times", "--no-shuffle"])
+        .current_dir(tmp_src_dir.path())
+        .assert()
+        .code(2)
+        .stdout(contains("8 mutants tested: 8 missed"));
+    let log_dir = tmp_src_dir.path().join("mutants.out/log");
+    // Only the example of `add_one` might be its only test, and it fails.
+    let doctests_log = read_to_string(log_dir.join("baseline_doctests.log")).unwrap();
+    assert!(doctests_log.contains(" test --doc "));
+    assert!(doctests_log.contains("src/lib.rs - add_one (line 9) ... FAILED"));
+    let outcomes: serde_json::Value =
+        read_to_string(tmp_src_dir.path().join("mutants.out/outcomes.json"))
+            .unwrap()
+            .parse()
+            .unwrap();
+    for outcome in &outcomes["outcomes"].as_array().unwrap()[1..] {
+        for phase_result in outcome["phase_results"].as_array().unwrap() {
+            assert_ne!(phase_result["argv"][2], "--doc");
+        }
+    }
+}
+
 #[test]
 fn source_tree_parse_fails() {
     let tmp_src_dir = copy_of_testdata("parse_fails");
@@ -766,6 +951,41 @@ fn mutants_causing_tests_to_hang_are_stopped_by_manual_timeout() {
     assert_eq!(outcomes_json["timeout"], 1);
 }
 
+/// In the `build_hangs` tree, one mutant makes const evaluation loop forever, so the
+/// build never finishes, and it should be stopped by the build timeout.
+#[test]
+fn mutants_causing_build_to_hang_are_stopped_by_build_timeout() {
+    let tmp_src_dir = copy_of_testdata("build_hangs");
+    fs::create_dir(tmp_src_dir.path().join(".cargo")).unwrap();
+    fs::write(
+        tmp_src_dir.path().join(".cargo/mutants.toml"),
+        "build_timeout = 15\n",
+    )
+    .unwrap();
+    run()
+        .args(["mutants", "--no-times"])
+        .current_dir(tmp_src_dir.path())
+        .timeout(OUTER_TIMEOUT)
+        .assert()
+        .code(3) // exit_code::TIMEOUT
+        .stdout(contains(
+            "BUILD TIMEOUT src/lib.rs:5:5: replace should_stop -> bool with false",
+        ))
+        .stdout(contains("1 mutant tested: 1 build timeouts"));
+    assert_eq!(
+        read_to_string(tmp_src_dir.path().join("mutants.out/build_timeout.txt")).unwrap(),
+        "src/lib.rs:5:5: replace should_stop -> bool with false\n"
+    );
+    let outcomes_json: serde_json::Value =
+        read_to_string(tmp_src_dir.path().join("mutants.out/outcomes.json"))
+            .unwrap()
+            .parse()
+            .unwrap();
+    assert_eq!(outcomes_json["build_timeout"], 1);
+    assert_eq!(outcomes_json["timeout"], 0);
+    assert_eq!(outcomes_json["outcomes"][1]["summary"], "BuildTimeout");
+}
+
 #[test]
 fn log_file_names_are_short_and_dont_collide() {
     // The "well_tested" tree can generate multiple mutants from single lines. They get distinct file names.
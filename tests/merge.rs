@@ -0,0 +1,124 @@
+// Copyright 2024 Martin Pool
+
+//! Test `cargo mutants merge`.
+
+use std::fs::{read_to_string, write};
+
+use itertools::Itertools;
+use predicates::prelude::*;
+use pretty_assertions::assert_eq;
+use tempfile::tempdir;
+
+mod util;
+use util::{copy_of_testdata, run};
+
+#[test]
+fn merge_shards_of_factorial() {
+    let tmp = copy_of_testdata("factorial");
+    let shard_dir = |k: usize| tmp.path().join(format!("shard{k}"));
+    for k in 0..2 {
+        run()
+            .args(["mutants", "--no-times", "--shard", &format!("{k}/2"), "-d"])
+            .arg(tmp.path())
+            .arg("-o")
+            .arg(shard_dir(k))
+            .assert()
+            .code(predicate::in_iter([0, 2]));
+    }
+    // Every test fails for some mutant in each shard, so none are useless. Pretend that
+    // one test never failed in either shard, and another only in the second shard.
+    for (k, extra) in [(0, ""), (1, "factorial test_factorial\n")] {
+        let path = shard_dir(k).join("mutants.out/useless_tests.txt");
+        assert_eq!(read_to_string(&path).unwrap(), "");
+        write(&path, format!("factorial never_fails\n{extra}")).unwrap();
+    }
+    let merged = tempdir().unwrap();
+    run()
+        .args(["mutants", "merge", "-o"])
+        .arg(merged.path())
+        .arg(shard_dir(0))
+        .arg(shard_dir(1).join("mutants.out"))
+        .assert()
+        .code(2)
+        .stdout(predicate::str::contains(
+            "5 mutants tested: 1 missed, 4 caught",
+        ));
+
+    let out = merged.path().join("mutants.out");
+    let read_list = |name: &str| {
+        read_to_string(out.join(name))
+            .unwrap()
+            .lines()
+            .map(ToOwned::to_owned)
+            .sorted()
+            .collect_vec()
+    };
+    assert_eq!(
+        read_list("missed.txt"),
+        ["src/bin/factorial.rs:2:5: replace main with ()"]
+    );
+    assert_eq!(read_list("caught.txt").len(), 4);
+
+    let outcomes: serde_json::Value = read_to_string(out.join("outcomes.json"))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert_eq!(outcomes["total_mutants"], 5);
+    assert_eq!(outcomes["caught"], 4);
+    assert_eq!(outcomes["missed"], 1);
+    let outcomes = outcomes["outcomes"].as_array().unwrap();
+    // Both baselines are kept, and their logs are renamed so they don't collide.
+    assert_eq!(outcomes.len(), 7);
+    for outcome in outcomes {
+        let log_path = outcome["log_path"].as_str().unwrap();
+        assert!(log_path.starts_with(out.to_str().unwrap()));
+        assert!(std::path::Path::new(log_path).is_file());
+    }
+    assert!(out.join("log/baseline.log").is_file());
+    assert!(out.join("log/baseline_001.log").is_file());
+
+    // Kill counts for each test are added up across the shards.
+    let tests_json: serde_json::Value = read_to_string(out.join("tests.json"))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert_eq!(
+        tests_json,
+        serde_json::json!({
+            "tests": [{"name": "factorial test_factorial", "mutants_caught": 4}]
+        })
+    );
+
+    // Only tests that were useless in every shard are useless overall.
+    assert_eq!(
+        read_to_string(out.join("useless_tests.txt")).unwrap(),
+        "factorial never_fails\n"
+    );
+
+    let mutants: serde_json::Value = read_to_string(out.join("mutants.json"))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert_eq!(mutants.as_array().unwrap().len(), 5);
+}
+
+#[test]
+fn merge_refuses_overlapping_inputs() {
+    let tmp = copy_of_testdata("factorial");
+    run()
+        .args(["mutants", "--check", "-d"])
+        .arg(tmp.path())
+        .assert()
+        .success();
+    let merged = tempdir().unwrap();
+    run()
+        .args(["mutants", "merge", "-o"])
+        .arg(merged.path())
+        .arg(tmp.path())
+        .arg(tmp.path())
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "were they run with the same --shard?",
+        ));
+}
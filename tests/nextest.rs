@@ -2,6 +2,10 @@
 
 //! Integration tests for cargo mutants calling nextest.
 
+use std::fs::read_to_string;
+
+use pretty_assertions::assert_eq;
+
 mod util;
 use util::{copy_of_testdata, run};
 
@@ -18,4 +22,29 @@ fn test_with_nextest_on_small_tree() {
         "stdout:\n{}",
         String::from_utf8_lossy(&assert.get_output().stdout)
     );
+
+    let outcomes: serde_json::Value =
+        read_to_string(tmp_src_dir.path().join("mutants.out/outcomes.json"))
+            .unwrap()
+            .parse()
+            .unwrap();
+    let outcomes = outcomes["outcomes"].as_array().unwrap();
+    // The build is done by nextest, so that the tests don't need to rebuild.
+    let baseline_build = &outcomes[0]["phase_results"][0];
+    assert_eq!(baseline_build["phase"], "Build");
+    assert_eq!(
+        baseline_build["argv"].as_array().unwrap()[1..4],
+        ["nextest", "run", "--no-run"]
+    );
+    // The tests that failed are read from nextest's JUnit report.
+    let mut n_caught = 0;
+    for outcome in outcomes.iter().filter(|o| o["summary"] == "CaughtMutant") {
+        let test_phase = outcome["phase_results"].as_array().unwrap().last().unwrap();
+        assert_eq!(
+            test_phase["failing_tests"],
+            serde_json::json!(["cargo-mutants-testdata-small-well-tested test::test_factorial"])
+        );
+        n_caught += 1;
+    }
+    assert!(n_caught > 0);
 }
@@ -0,0 +1,77 @@
+// Copyright 2024 Martin Pool
+
+//! Test `--retries` and detection of flaky tests.
+
+use std::fs::read_to_string;
+
+use predicates::prelude::*;
+use pretty_assertions::assert_eq;
+
+mod util;
+use util::{copy_of_testdata, run};
+
+#[test]
+fn flaky_tests_are_detected_by_retries() {
+    let tmp = copy_of_testdata("flaky_test");
+    run()
+        .args([
+            "mutants",
+            "--no-times",
+            "--order=source",
+            "--retries=1",
+            "-d",
+        ])
+        .arg(tmp.path())
+        .assert()
+        .code(2) // flaky mutants are reported as problems
+        .stdout(predicate::str::contains("FLAKY    Unmutated baseline"))
+        .stdout(predicate::str::contains(
+            "FLAKY    src/lib.rs:11:5: replace is_ready -> bool with false",
+        ))
+        .stdout(predicate::str::contains(
+            "6 mutants tested: 5 caught, 1 flaky",
+        ));
+
+    let out = tmp.path().join("mutants.out");
+    assert_eq!(
+        read_to_string(out.join("flaky.txt")).unwrap(),
+        "src/lib.rs:11:5: replace is_ready -> bool with false\n"
+    );
+    let outcomes: serde_json::Value = read_to_string(out.join("outcomes.json"))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert_eq!(outcomes["flaky"], 1);
+    assert_eq!(outcomes["caught"], 5);
+    let flaky_outcome = outcomes["outcomes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|o| o["summary"] == "Flaky" && o["scenario"] != "Baseline")
+        .unwrap();
+    let test_statuses = flaky_outcome["phase_results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|pr| pr["phase"] == "Test")
+        .map(|pr| pr["process_status"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        test_statuses,
+        [
+            serde_json::json!({"Failure": 101}),
+            serde_json::json!("Success")
+        ]
+    );
+}
+
+#[test]
+fn flaky_baseline_fails_without_retries() {
+    let tmp = copy_of_testdata("flaky_test");
+    run()
+        .args(["mutants", "--no-times", "-d"])
+        .arg(tmp.path())
+        .assert()
+        .code(4)
+        .stdout(predicate::str::contains("FAILED   Unmutated baseline"));
+}
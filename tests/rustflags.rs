@@ -0,0 +1,73 @@
+// Copyright 2024 Martin Pool
+
+//! Test that rustflags from Cargo config files are passed on along with `--cap-lints`.
+
+use predicates::prelude::*;
+
+mod util;
+use util::{copy_of_testdata, run};
+
+#[test]
+fn rustflags_from_cargo_config_are_used() {
+    let tmp = copy_of_testdata("rustflags_in_config");
+    run()
+        .args(["mutants", "--no-times", "--no-shuffle", "-d"])
+        .arg(tmp.path())
+        .env_remove("RUSTFLAGS")
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("5 mutants tested: 5 caught"));
+}
+
+#[test]
+fn rustflags_from_environment_override_cargo_config() {
+    let tmp = copy_of_testdata("rustflags_in_config");
+    run()
+        .args(["mutants", "--no-times", "--no-shuffle", "-d"])
+        .arg(tmp.path())
+        .env("RUSTFLAGS", "--cfg other")
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .assert()
+        .code(4)
+        .stderr(predicate::str::contains(
+            "build failed in an unmutated tree",
+        ));
+}
+
+#[test]
+fn rustflags_from_cargo_config_args_are_used() {
+    let tmp = copy_of_testdata("rustflags_in_config");
+    std::fs::remove_dir_all(tmp.path().join(".cargo")).unwrap();
+    run()
+        .args(["mutants", "--no-times", "--no-shuffle", "-d"])
+        .arg(tmp.path())
+        .arg(r#"--cargo-arg=--config=build.rustflags=["--cfg", "mutants_testdata"]"#)
+        .env_remove("RUSTFLAGS")
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("5 mutants tested: 5 caught"));
+}
+
+#[test]
+fn config_files_in_cargo_args_are_relative_to_the_current_directory() {
+    let tmp = copy_of_testdata("rustflags_in_config");
+    std::fs::remove_dir_all(tmp.path().join(".cargo")).unwrap();
+    let cwd = tempfile::tempdir().unwrap();
+    std::fs::write(
+        cwd.path().join("flags.toml"),
+        "build.rustflags = [\"--cfg\", \"mutants_testdata\"]\n",
+    )
+    .unwrap();
+    run()
+        .args(["mutants", "--no-times", "--no-shuffle", "-d"])
+        .arg(tmp.path())
+        .arg("--cargo-arg=--config=flags.toml")
+        .current_dir(cwd.path())
+        .env_remove("RUSTFLAGS")
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("5 mutants tested: 5 caught"));
+}
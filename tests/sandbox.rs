@@ -0,0 +1,46 @@
+// Copyright 2024 Martin Pool
+
+//! Test `--sandbox`.
+
+#![cfg(target_os = "linux")]
+
+use std::path::Path;
+use std::process::Command;
+
+use predicates::prelude::*;
+
+mod util;
+use util::{copy_of_testdata, run};
+
+/// True if unprivileged user namespaces can be created here; they're disabled on
+/// some systems.
+fn sandbox_available() -> bool {
+    let available = Command::new("unshare")
+        .args(["--user", "--map-root-user", "--net", "--mount", "true"])
+        .status()
+        .is_ok_and(|status| status.success());
+    if !available {
+        eprintln!("sandbox is not available here; skipping test");
+    }
+    available
+}
+
+#[test]
+fn sandboxed_tests_have_private_tmp_and_no_network() {
+    if !sandbox_available() {
+        return;
+    }
+    let tmp = copy_of_testdata("sandbox");
+    // The tests create this, and would fail in every run after the first if they
+    // all shared the real /tmp.
+    let marker = Path::new("/tmp/cargo-mutants-testdata-sandbox.marker");
+    assert!(!marker.exists());
+    run()
+        .args(["mutants", "--no-times", "--sandbox", "-d"])
+        .arg(tmp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ok       Unmutated baseline"))
+        .stdout(predicate::str::contains("5 mutants tested: 5 caught"));
+    assert!(!marker.exists());
+}
@@ -0,0 +1,51 @@
+// Copyright 2024 Martin Pool
+
+//! Test `--schemata`: building several mutants together.
+
+use std::fs::read_to_string;
+
+use itertools::Itertools;
+use pretty_assertions::assert_eq;
+
+mod util;
+use util::{copy_of_testdata, run};
+
+#[test]
+fn schemata_find_the_same_results_as_separate_builds() {
+    let testdata = copy_of_testdata("factorial");
+    run()
+        .args(["mutants", "--schemata", "--no-shuffle", "--no-times", "-d"])
+        .arg(testdata.path())
+        .assert()
+        .code(2);
+    let out_dir = testdata.path().join("mutants.out");
+    let read_list = |name: &str| {
+        read_to_string(out_dir.join(name))
+            .unwrap()
+            .lines()
+            .map(ToOwned::to_owned)
+            .sorted()
+            .collect_vec()
+    };
+    assert_eq!(
+        read_list("missed.txt"),
+        ["src/bin/factorial.rs:2:5: replace main with ()"]
+    );
+    assert_eq!(
+        read_list("caught.txt"),
+        [
+            "src/bin/factorial.rs:10:11: replace *= with += in factorial",
+            "src/bin/factorial.rs:10:11: replace *= with /= in factorial",
+            "src/bin/factorial.rs:8:5: replace factorial -> u32 with 0",
+            "src/bin/factorial.rs:8:5: replace factorial -> u32 with 1",
+        ]
+    );
+    // The function-value mutants were built once, together, in a schema.
+    let schema_log = read_to_string(out_dir.join("log/src__bin__factorial.rs_schema.log")).unwrap();
+    assert!(schema_log.contains("schema of 3 mutants in src/bin/factorial.rs"));
+    assert!(!schema_log.contains("failed to build"));
+    let mutant_log =
+        read_to_string(out_dir.join("log/src__bin__factorial.rs_line_8_col_5.log")).unwrap();
+    assert!(mutant_log.contains("built in schema"));
+    assert!(!mutant_log.contains("build --tests"));
+}
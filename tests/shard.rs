@@ -5,7 +5,7 @@
 use itertools::Itertools;
 
 mod util;
-use util::run;
+use util::{copy_of_testdata, run};
 
 #[test]
 fn shard_divides_all_mutants() {
@@ -77,3 +77,151 @@ fn shard_divides_all_mutants() {
         }
     }
 }
+
+/// List the mutants in the workspace testdata tree, as names, with the given extra arguments.
+fn list_workspace_mutants(extra_args: &[&str]) -> Vec<String> {
+    String::from_utf8(
+        run()
+            .args(["mutants", "--list", "-d", "testdata/workspace"])
+            .args(extra_args)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone(),
+    )
+    .unwrap()
+    .lines()
+    .map(ToOwned::to_owned)
+    .collect_vec()
+}
+
+#[test]
+fn cost_strategy_shards_divide_all_mutants() {
+    let full_list = list_workspace_mutants(&[]);
+    let shard_lists = (0..3)
+        .map(|k| list_workspace_mutants(&["--shard", &format!("{k}/3"), "--shard-strategy=cost"]))
+        .collect_vec();
+    assert_eq!(
+        shard_lists.iter().flatten().sorted().collect_vec(),
+        full_list.iter().sorted().collect_vec()
+    );
+    assert!(shard_lists.iter().all(|l| !l.is_empty()));
+}
+
+#[test]
+fn cost_strategy_uses_previous_outcomes() {
+    // Make up a previous run in which the first mutant took much longer than all the
+    // others put together: it should get a shard to itself.
+    let mutants_json: serde_json::Value = serde_json::from_slice(
+        &run()
+            .args(["mutants", "--list", "--json", "-d", "testdata/workspace"])
+            .assert()
+            .success()
+            .get_output()
+            .stdout,
+    )
+    .unwrap();
+    let mutants = mutants_json.as_array().unwrap();
+    let outcomes = mutants
+        .iter()
+        .enumerate()
+        .map(|(i, mutant)| {
+            serde_json::json!({
+                "scenario": {"Mutant": mutant},
+                "summary": "CaughtMutant",
+                "phase_results": [
+                    {"phase": "Build", "duration": if i == 0 { 1000.0 } else { 1.0 }},
+                ],
+            })
+        })
+        .collect_vec();
+    let tmp = tempfile::tempdir().unwrap();
+    let outcomes_path = tmp.path().join("outcomes.json");
+    std::fs::write(
+        &outcomes_path,
+        serde_json::json!({ "outcomes": outcomes }).to_string(),
+    )
+    .unwrap();
+
+    let full_list = list_workspace_mutants(&[]);
+    let shard_lists = (0..2)
+        .map(|k| {
+            list_workspace_mutants(&[
+                "--shard",
+                &format!("{k}/2"),
+                "--shard-strategy=cost",
+                "--shard-costs",
+                outcomes_path.to_str().unwrap(),
+            ])
+        })
+        .collect_vec();
+    assert_eq!(shard_lists[0], [full_list[0].clone()]);
+    assert_eq!(shard_lists[1], full_list[1..]);
+}
+
+#[test]
+fn shard_costs_requires_cost_strategy() {
+    run()
+        .args([
+            "mutants",
+            "--list",
+            "-d",
+            "testdata/workspace",
+            "--shard=0/2",
+            "--shard-costs=outcomes.json",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "--shard-costs can only be used with --shard-strategy=cost",
+        ));
+}
+
+#[test]
+fn hash_strategy_keeps_mutants_in_the_same_shard_when_code_changes() {
+    let tmp = copy_of_testdata("well_tested");
+    let list_shards = || {
+        (0..3)
+            .map(|k| {
+                String::from_utf8(
+                    run()
+                        .args(["mutants", "--list", "--line-col=false", "-d"])
+                        .arg(tmp.path())
+                        .args(["--shard", &format!("{k}/3"), "--shard-strategy=hash"])
+                        .assert()
+                        .success()
+                        .get_output()
+                        .stdout
+                        .clone(),
+                )
+                .unwrap()
+                .lines()
+                .map(ToOwned::to_owned)
+                .collect_vec()
+            })
+            .collect_vec()
+    };
+    let before = list_shards();
+    assert!(before.iter().all(|l| !l.is_empty()));
+
+    // Adding a function at the start of a file shifts the index of every later mutant.
+    let simple_fns = tmp.path().join("src/simple_fns.rs");
+    let code = std::fs::read_to_string(&simple_fns).unwrap();
+    std::fs::write(
+        &simple_fns,
+        format!("pub fn newly_added() -> usize {{ 42 }}\n\n{code}"),
+    )
+    .unwrap();
+
+    let after = list_shards();
+    assert_eq!(
+        after.iter().map(Vec::len).sum::<usize>(),
+        before.iter().map(Vec::len).sum::<usize>() + 2
+    );
+    for (before_shard, after_shard) in before.iter().zip(&after) {
+        for name in before_shard {
+            assert!(after_shard.contains(name), "{name} moved to another shard");
+        }
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright 2024 Martin Pool
+
+//! Test mutating build scripts, examples, and benches, selected with `targets`.
+
+use std::fs::{create_dir, read_to_string, write};
+
+use pretty_assertions::assert_eq;
+
+mod util;
+use util::{copy_of_testdata, run};
+
+fn list_mutant_files(args: &[&str]) -> Vec<String> {
+    let tmp = copy_of_testdata("targets");
+    let output = run()
+        .args(["mutants", "--list", "-d"])
+        .arg(tmp.path())
+        .args(args)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let mut files: Vec<String> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| line.split(':').next().unwrap().to_owned())
+        .collect();
+    files.dedup();
+    files
+}
+
+#[test]
+fn only_lib_and_bin_targets_are_mutated_by_default() {
+    assert!(list_mutant_files(&[]).is_empty());
+}
+
+#[test]
+fn targets_arg_selects_kinds_of_target() {
+    assert_eq!(
+        list_mutant_files(&["--targets=lib,bin,example,bench,custom-build"]),
+        ["benches/speed.rs", "build.rs", "examples/greet.rs"]
+    );
+    assert_eq!(list_mutant_files(&["--targets=custom-build"]), ["build.rs"]);
+}
+
+#[test]
+fn build_script_and_example_mutants_are_tested() {
+    let tmp = copy_of_testdata("targets");
+    create_dir(tmp.path().join(".cargo")).unwrap();
+    write(
+        tmp.path().join(".cargo/mutants.toml"),
+        r#"targets = ["lib", "example", "custom-build"]"#,
+    )
+    .unwrap();
+    run()
+        .args(["mutants", "--no-times", "--no-shuffle", "-d"])
+        .arg(tmp.path())
+        .assert()
+        .code(2); // The example's main function isn't tested.
+    let caught = read_to_string(tmp.path().join("mutants.out/caught.txt")).unwrap();
+    assert!(caught.contains("build.rs:8:7: replace * with + in answer"));
+    assert!(caught.contains("examples/greet.rs:4:5: replace greeting -> String with String::new()"));
+    assert_eq!(
+        read_to_string(tmp.path().join("mutants.out/missed.txt")).unwrap(),
+        "examples/greet.rs:8:5: replace main with ()\n"
+    );
+    let baseline_log = read_to_string(tmp.path().join("mutants.out/log/baseline.log")).unwrap();
+    assert!(baseline_log.contains(" build --tests --examples "));
+}
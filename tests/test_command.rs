@@ -0,0 +1,44 @@
+// Copyright 2024 Martin Pool
+
+//! Test running tests with a custom `test_command`.
+
+use std::fs::{create_dir, read_to_string, write};
+
+use predicates::prelude::*;
+
+mod util;
+use util::{copy_of_testdata, run};
+
+#[test]
+#[cfg(unix)] // The command uses `sh`.
+fn custom_test_command_is_run_for_baseline_and_mutants() {
+    let tmp = copy_of_testdata("small_well_tested");
+    create_dir(tmp.path().join(".cargo")).unwrap();
+    write(
+        tmp.path().join(".cargo/mutants.toml"),
+        r#"test_command = ["sh", "-c", "echo testing package $1 in $0 && cargo test --manifest-path=$0/Cargo.toml", "{build_dir}", "{package}"]"#,
+    )
+    .unwrap();
+    run()
+        .args(["mutants", "--no-times", "-d"])
+        .arg(tmp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ok       Unmutated baseline"))
+        .stdout(predicate::str::contains("4 mutants tested: 4 caught"));
+    let baseline_log = read_to_string(tmp.path().join("mutants.out/log/baseline.log")).unwrap();
+    assert!(baseline_log.contains("*** sh -c "));
+    assert!(baseline_log.contains("testing package cargo-mutants-testdata-small-well-tested in /"));
+    assert!(baseline_log.contains("test result: ok. 1 passed"));
+}
+
+#[test]
+fn command_test_tool_needs_test_command() {
+    let tmp = copy_of_testdata("small_well_tested");
+    run()
+        .args(["mutants", "--test-tool=command", "-d"])
+        .arg(tmp.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("needs test_command"));
+}
@@ -146,6 +146,16 @@ fn workspace_tree_is_well_tested() {
                 .join(" "),
             "test --package cargo_mutants_testdata_workspace_utils --package main --package main2"
         );
+        assert_eq!(
+            baseline_phases[1]["packages"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|p| p.as_str().unwrap())
+                .sorted()
+                .collect_vec(),
+            ["cargo_mutants_testdata_workspace_utils", "main", "main2"]
+        );
     }
 
     assert!(outcomes.len() > 9);
@@ -167,25 +177,40 @@ fn workspace_tree_is_well_tested() {
             ["test", "--manifest-path"],
         );
     }
-    {
-        let baseline = json["outcomes"][0].as_object().unwrap();
-        assert_eq!(baseline["scenario"].as_str().unwrap(), "Baseline");
-        assert_eq!(baseline["summary"], "Success");
-        let baseline_phases = baseline["phase_results"].as_array().unwrap();
-        assert_eq!(baseline_phases.len(), 2);
-        assert_eq!(baseline_phases[0]["process_status"], "Success");
-        assert_eq!(
-            baseline_phases[0]["argv"].as_array().unwrap()[1..].iter().map(|v| v.as_str().unwrap()).join(" "),
-            "build --tests --package cargo_mutants_testdata_workspace_utils --package main --package main2",
-        );
-        assert_eq!(baseline_phases[1]["process_status"], "Success");
-        assert_eq!(
-            baseline_phases[1]["argv"].as_array().unwrap()[1..]
-                .iter()
-                .map(|v| v.as_str().unwrap())
-                .join(" "),
-            "test --package cargo_mutants_testdata_workspace_utils --package main --package main2",
-        );
+}
+
+#[test]
+fn baseline_tests_each_package_separately_with_per_package_timeouts() {
+    let tmp_src_dir = copy_of_testdata("workspace");
+    run()
+        .args([
+            "mutants",
+            "--per-package-timeouts",
+            "-p",
+            "main",
+            "-p",
+            "main2",
+            "-d",
+        ])
+        .arg(tmp_src_dir.path())
+        .assert()
+        .success();
+    let json: serde_json::Value =
+        fs::read_to_string(tmp_src_dir.path().join("mutants.out/outcomes.json"))
+            .unwrap()
+            .parse()
+            .unwrap();
+    let baseline = &json["outcomes"][0];
+    assert_eq!(baseline["scenario"], "Baseline");
+    let baseline_phases = baseline["phase_results"].as_array().unwrap();
+    assert_eq!(baseline_phases.len(), 3);
+    assert_eq!(baseline_phases[0]["phase"], "Build");
+    // Each package is tested separately, so that mutants in each package can have
+    // their own timeout.
+    for (phase, package) in baseline_phases[1..].iter().zip(["main", "main2"]) {
+        assert_eq!(phase["phase"], "Test");
+        assert_eq!(phase["process_status"], "Success");
+        assert_eq!(phase["packages"], json!([package]));
     }
 }
 